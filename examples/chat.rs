@@ -1,9 +1,9 @@
 //! A chat sample which allows multiple instances to communicate
 //! by writing/reading messages from the console.
 use kekbit::api::EncoderHandler;
+use kekbit::api::Reader;
 use kekbit::api::Writer;
 use kekbit::core::*;
-use kekbit::retry::RetryIter;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
@@ -14,16 +14,16 @@ const TIMEOUT: u64 = 30;
 fn run_writer(channel_id: u64, run: Arc<AtomicBool>) {
     let tmp_dir = std::env::temp_dir().join("kekchat");
     let msg_size = 1000;
-    let metadata = Metadata::new(1111, channel_id, msg_size * 1000, msg_size, TIMEOUT, TickUnit::Secs);
+    let metadata = Metadata::for_messages(1111, channel_id, msg_size, 1000, TIMEOUT, TickUnit::Secs);
     let mut writer = shm_writer(&tmp_dir, &metadata, EncoderHandler::default()).unwrap();
     std::thread::yield_now();
-    while run.load(Ordering::Relaxed) == true {
+    while run.load(Ordering::Relaxed) {
         let mut input = String::new();
         std::io::stdin().read_line(&mut input).expect("Failed read");
         let data = input.trim();
-        if data.len() > 0 {
+        if !data.is_empty() {
             writer.write(&data).unwrap();
-            if input.trim() == "Bye".to_string() {
+            if input.trim() == "Bye" {
                 println!("Sent Bye. Exiting.....");
                 run.store(false, Ordering::Relaxed);
                 break;
@@ -40,30 +40,26 @@ fn run_reader(channel_id: u64, run: Arc<AtomicBool>) {
         std::process::exit(0);
     }
     let mut reader = shm_timeout_reader(reader_res.unwrap());
-    let mut msg_iter: RetryIter<TimeoutReader<ShmReader>> = reader.try_iter().into();
-    while run.load(Ordering::Relaxed) == true {
-        for read_res in &mut msg_iter {
-            match read_res {
-                ReadResult::Record(msg) => {
-                    let msg_str = std::str::from_utf8(&msg).unwrap();
+    while run.load(Ordering::Relaxed) {
+        for text in reader.text_iter() {
+            match text {
+                Ok(msg_str) => {
                     println!(">>>{}", msg_str);
-                    if msg_str == "Bye".to_string() {
+                    if msg_str == "Bye" {
                         println!("Received Bye. Exiting.....");
                         run.store(false, Ordering::Relaxed);
                         std::process::exit(0);
                     }
                 }
-                ReadResult::Nothing => {
-                    std::thread::sleep(Duration::from_millis(300));
-                    break;
-                }
-                ReadResult::Failed(err) => {
-                    println!("Chat channel read error {:?}", err);
-                    run.store(false, Ordering::Relaxed);
-                    std::process::exit(0);
-                }
+                Err(err) => println!("Skipping non text message {}", err),
             }
         }
+        if let Some(err) = reader.exhausted() {
+            println!("Chat channel read error {:?}", err);
+            run.store(false, Ordering::Relaxed);
+            std::process::exit(0);
+        }
+        std::thread::sleep(Duration::from_millis(300));
     }
 }
 
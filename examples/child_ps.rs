@@ -9,15 +9,16 @@ use std::path::Path;
 use std::process::exit;
 use std::result::Result;
 
-const ITERATIONS: u32 = 1 * 1_000_000_0;
+const ITERATIONS: u32 = 10_000_000;
 const Q_PATH: &str = "/dev/shm";
 //const Q_PATH: &str = "./shm/keki";
 
+#[allow(clippy::result_unit_err)]
 pub fn run_writer() -> Result<(), ()> {
     info!("Creating writer process ...{}", getpid());
     let chunk_size = 100;
     let metadata = Metadata::new(100, 1000, chunk_size * (ITERATIONS + 100), 1000, 99999999999, TickUnit::Nanos);
-    let mut writer = shm_writer(&Path::new(Q_PATH), &metadata, EncoderHandler::default()).unwrap();
+    let mut writer = shm_writer(Path::new(Q_PATH), &metadata, EncoderHandler::default()).unwrap();
     let msg_bytes = "There are 10 kinds of people: those who know binary and those who don't".as_bytes();
     // let msgs: Vec<&str> = "There are 10 kinds of people: those who know binary and those who don't"
     //     .split_whitespace()
@@ -51,9 +52,10 @@ pub fn run_writer() -> Result<(), ()> {
     Ok(())
 }
 
+#[allow(clippy::result_unit_err)]
 pub fn run_reader() -> Result<(), ()> {
     info!("Creating reader porcess ...{}", getpid());
-    let mut reader = try_shm_reader(&Path::new(Q_PATH), 1000, 2000, 200).unwrap();
+    let mut reader = try_shm_reader(Path::new(Q_PATH), 1000, 2000, 200).unwrap();
     let mut stop = false;
     let mut msg_count = 0;
     while !stop {
@@ -69,7 +71,7 @@ pub fn run_reader() -> Result<(), ()> {
                     info!("Closed channel detected by reader");
                     stop = true;
                 }
-                ChannelFull | Failed => {
+                ChannelFull | Failed | StorageVanished | VersionChanged | RecordTooLarge(_) | CompressedRecord => {
                     error!("Read failed. Will stop. So far we read {} messages", msg_count);
                     panic!("Read failed!!!!");
                 }
@@ -140,7 +142,7 @@ fn main() {
         Ok(status) => info!("[main] Writer completed with status {:?}", status),
         Err(err) => panic!("[main] waitpid() on writer failed: {}", err),
     }
-    let shm_file_path = storage_path(&Path::new(Q_PATH), 1000);
+    let shm_file_path = storage_path(Path::new(Q_PATH), 1000);
     if shm_file_path.exists() {
         std::fs::remove_file(&shm_file_path).unwrap();
         info!("Channel data file {:?} removed", &shm_file_path);
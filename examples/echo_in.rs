@@ -15,7 +15,7 @@ fn main() {
     let channel_id = args[0];
     let tmp_dir = std::env::temp_dir().join("kekbit").join("echo_sample");
     let max_msg_size = 1024;
-    let metadata = Metadata::new(writer_id, channel_id, max_msg_size * 1000, max_msg_size, timeout_secs, Secs);
+    let metadata = Metadata::for_messages(writer_id, channel_id, max_msg_size, 1000, timeout_secs, Secs);
     let mut writer = shm_writer(&tmp_dir, &metadata, EncoderHandler::default()).unwrap();
     let mut last_msg_time = Secs.nix_time();
     loop {
@@ -29,8 +29,13 @@ fn main() {
             last_msg_time = ts;
         }
         let data = input.trim();
+        if data.is_empty() {
+            //empty records are reserved for heartbeats
+            writer.heartbeat().unwrap();
+            continue;
+        }
         writer.write(&data).unwrap();
-        if data == "Bye".to_string() {
+        if data == "Bye" {
             println!("Exiting.....");
             break;
         }
@@ -0,0 +1,125 @@
+//! Derive macros for the kekbit [Encodable](../kekbit/api/trait.Encodable.html) and
+//! [Decodable](../kekbit/api/trait.Decodable.html) traits.
+//!
+//! The derived implementations encode the fields of a struct in declaration order, every field using its own
+//! encoding, so a struct of numbers is encoded as the little endian bytes of its fields, with no padding
+//! or schema information in between. The struct is decoded using the raw binary data format, reading
+//! the fields back in the same order. Only fixed length fields, such numbers or arrays of numbers,
+//! could be decoded back unambiguously.
+//!
+//! # Examples
+//!
+//! ```
+//! use kekbit::api::{Decodable, Encodable, RawBinDataFormat};
+//! use kekbit_derive::{KekDecode, KekEncode};
+//!
+//! #[derive(KekEncode, KekDecode, Debug, PartialEq)]
+//! struct Quote {
+//!     instrument: u32,
+//!     bid: f64,
+//!     ask: f64,
+//! }
+//!
+//! let quote = Quote { instrument: 7, bid: 1.1, ask: 1.2 };
+//! let mut bytes = Vec::new();
+//! assert_eq!(quote.encode(&mut bytes).unwrap(), 20);
+//! assert_eq!(Quote::decode(&RawBinDataFormat, &mut &bytes[..]).unwrap(), quote);
+//! ```
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+/// Derives `kekbit::api::Encodable` for a struct, encoding its fields in declaration order.
+#[proc_macro_derive(KekEncode)]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let encode_fields = field_accessors(fields).map(|field| {
+        quote! {
+            len += ::kekbit::api::Encodable::encode(&self.#field, w)?;
+        }
+    });
+    let expanded = quote! {
+        impl #impl_generics ::kekbit::api::Encodable for #name #ty_generics #where_clause {
+            #[inline]
+            fn encode(&self, w: &mut impl ::std::io::Write) -> ::std::result::Result<usize, ::std::io::Error> {
+                let mut len = 0;
+                #(#encode_fields)*
+                Ok(len)
+            }
+        }
+    };
+    expanded.into()
+}
+
+/// Derives `kekbit::api::Decodable<RawBinDataFormat>` for a struct, decoding its fields in declaration order.
+#[proc_macro_derive(KekDecode)]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let decode_field = quote! {
+        ::kekbit::api::Decodable::<::kekbit::api::RawBinDataFormat>::decode(format, r)?
+    };
+    let body = match fields {
+        Fields::Named(named) => {
+            let decode_fields = named.named.iter().map(|field| {
+                let ident = &field.ident;
+                quote! { #ident: #decode_field }
+            });
+            quote! { #name { #(#decode_fields),* } }
+        }
+        Fields::Unnamed(unnamed) => {
+            let decode_fields = unnamed.unnamed.iter().map(|_| &decode_field);
+            quote! { #name ( #(#decode_fields),* ) }
+        }
+        Fields::Unit => quote! { #name },
+    };
+    let expanded = quote! {
+        impl #impl_generics ::kekbit::api::Decodable<::kekbit::api::RawBinDataFormat> for #name #ty_generics #where_clause {
+            #[inline]
+            fn decode(
+                format: &::kekbit::api::RawBinDataFormat,
+                r: &mut impl ::std::io::Read,
+            ) -> ::std::result::Result<Self, ::std::io::Error> {
+                Ok(#body)
+            }
+        }
+    };
+    expanded.into()
+}
+
+//Returns the fields of a struct, or an error pointing at the item which is not a struct.
+fn struct_fields(input: &DeriveInput) -> Result<&Fields, syn::Error> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(syn::Error::new(
+            input.span(),
+            "Only structs could be encoded into or decoded from kekbit records",
+        )),
+    }
+}
+
+//Returns the expressions which access every field, in declaration order.
+fn field_accessors(fields: &Fields) -> impl Iterator<Item = TokenStream2> + '_ {
+    fields.iter().enumerate().map(|(idx, field)| match &field.ident {
+        Some(ident) => quote! { #ident },
+        None => {
+            let idx = Index::from(idx);
+            quote! { #idx }
+        }
+    })
+}
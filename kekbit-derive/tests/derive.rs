@@ -0,0 +1,51 @@
+use kekbit::api::{Decodable, EncoderHandler, RawBinDataFormat, Reader, Writer};
+use kekbit::core::TickUnit::Nanos;
+use kekbit::core::*;
+use kekbit_derive::{KekDecode, KekEncode};
+use tempdir::TempDir;
+
+const FOREVER: u64 = 99_999_999_999;
+
+#[derive(KekEncode, KekDecode, Debug, PartialEq)]
+struct Request {
+    id: u64,
+    a: u64,
+    b: u64,
+}
+
+#[derive(KekEncode, KekDecode, Debug, PartialEq)]
+struct Reply(u64, i32, [u16; 2]);
+
+#[test]
+fn round_trip_through_channel() {
+    let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+    let test_tmp_dir = TempDir::new("kektest").unwrap();
+    let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    for id in 0..10 {
+        let request = Request {
+            id,
+            a: id * 2,
+            b: id * 3,
+        };
+        //the fields are packed one after the other, with no padding
+        assert_eq!(writer.write(&request).unwrap(), 32);
+    }
+    writer.write(&Reply(7, -1, [1, 2])).unwrap();
+    let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+    for id in 0..10u64 {
+        let mut record = reader.try_read().unwrap().unwrap();
+        assert_eq!(&record[8..16], &(id * 2).to_le_bytes());
+        let request = Request::decode(&RawBinDataFormat, &mut record).unwrap();
+        assert_eq!(
+            request,
+            Request {
+                id,
+                a: id * 2,
+                b: id * 3
+            }
+        );
+    }
+    let mut record = reader.try_read().unwrap().unwrap();
+    assert_eq!(record.len(), 16);
+    assert_eq!(Reply::decode(&RawBinDataFormat, &mut record).unwrap(), Reply(7, -1, [1, 2]));
+}
@@ -1,7 +1,60 @@
 //! Defines the general kekbit access protocol, based on the [Reader](api/trait.Reader.html) and [Writer](api/trait.Writer.html) traits.
+use std::convert::TryFrom;
 use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
 use std::io::Write;
 
+///Derive macros which encode and decode structs field by field. Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use kekbit_derive::{KekDecode, KekEncode};
+
+///A data format describes how records are encoded into a channel, such a
+///raw binary representation, plain text or a well known format like JSON.
+pub trait DataFormat {
+    ///Returns the unique identifier of this data format.
+    fn id(&self) -> u64;
+    ///Returns the media type(MIME type) of the records encoded in this data format.
+    fn media_type(&self) -> &'static str;
+}
+
+///Data format for records which are opaque binary data. Numbers are stored as little endian bytes,
+///tuples and arrays as the concatenation of their elements.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawBinDataFormat;
+
+impl DataFormat for RawBinDataFormat {
+    ///Returns 1, the id of the raw binary data format.
+    #[inline]
+    fn id(&self) -> u64 {
+        1
+    }
+    ///Returns `application/octet-stream`
+    #[inline]
+    fn media_type(&self) -> &'static str {
+        "application/octet-stream"
+    }
+}
+
+///Data format for records which are JSON documents. Requires the `json` feature.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonDataFormat;
+
+#[cfg(feature = "json")]
+impl DataFormat for JsonDataFormat {
+    ///Returns 4, the id of the JSON data format.
+    #[inline]
+    fn id(&self) -> u64 {
+        4
+    }
+    ///Returns `application/json`
+    #[inline]
+    fn media_type(&self) -> &'static str {
+        "application/json"
+    }
+}
+
 ///An entity which can be written into a channel
 pub trait Encodable {
     /// Encodes an object into a `Write`. It could simply write the
@@ -19,12 +72,286 @@ pub trait Encodable {
 }
 
 ///Any binary data is ready to be encoded into a channel.
-impl<T: AsRef<[u8]>> Encodable for T {
+impl Encodable for [u8] {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self)
+    }
+}
+
+impl<const N: usize> Encodable for [u8; N] {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self)
+    }
+}
+
+impl Encodable for Vec<u8> {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self)
+    }
+}
+
+///Text is encoded as its UTF-8 bytes.
+impl Encodable for str {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self.as_bytes())
+    }
+}
+
+impl Encodable for String {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self.as_bytes())
+    }
+}
+
+/// Wraps any byte container so it is encoded as its raw bytes. Before version 0.4 every `AsRef<[u8]>` type
+/// was encodable as is; such types, which are not encodable any more, could be written through this wrapper.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::api::*;
+/// use std::borrow::Cow;
+///
+/// let mut record = Vec::new();
+/// Bytes(Cow::from(&b"raw"[..])).encode(&mut record).unwrap();
+/// assert_eq!(record, b"raw");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bytes<T: AsRef<[u8]>>(pub T);
+
+impl<T: AsRef<[u8]>> Encodable for Bytes<T> {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(self.0.as_ref())
+    }
+}
+
+impl<T: Encodable + ?Sized> Encodable for &T {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        (**self).encode(w)
+    }
+}
+
+impl<T: Encodable + ?Sized> Encodable for Box<T> {
     #[inline]
     fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
-        w.write(self.as_ref())
+        (**self).encode(w)
+    }
+}
+
+macro_rules! encodable_num {
+    ($($num:ty),*) => {
+        $(
+            ///Numbers are encoded as their little endian bytes.
+            impl Encodable for $num {
+                #[inline]
+                fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+                    w.write(&self.to_le_bytes())
+                }
+            }
+        )*
+        $(
+            ///Arrays of numbers are encoded as the little endian bytes of every element, in order.
+            impl<const N: usize> Encodable for [$num; N] {
+                #[inline]
+                fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+                    let mut total = 0;
+                    for num in self.iter() {
+                        total += num.encode(w)?;
+                    }
+                    Ok(total)
+                }
+            }
+        )*
+    };
+}
+
+encodable_num!(u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Encodable for u8 {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        w.write(&[*self])
+    }
+}
+
+macro_rules! encodable_tuple {
+    ($($name:ident),+) => {
+        ///Tuples are encoded as the concatenation of their encoded elements.
+        impl<$($name: Encodable),+> Encodable for ($($name,)+) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+                let ($($name,)+) = self;
+                let mut total = 0;
+                $(total += $name.encode(w)?;)+
+                Ok(total)
+            }
+        }
+    };
+}
+
+encodable_tuple!(A);
+encodable_tuple!(A, B);
+encodable_tuple!(A, B, C);
+encodable_tuple!(A, B, C, D);
+encodable_tuple!(A, B, C, D, E);
+encodable_tuple!(A, B, C, D, E, F);
+encodable_tuple!(A, B, C, D, E, F, G);
+encodable_tuple!(A, B, C, D, E, F, G, H);
+
+/// Wraps an encodable entity so it is encoded prefixed by its length, as a little endian `u32`.
+/// A length prefixed entity could be followed by other data in the same record, such as
+/// a body which follows a variable length prefix, and still be parsed back.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::api::*;
+///
+/// let mut record = Vec::new();
+/// (LengthPrefixed("header"), "body").encode(&mut record).unwrap();
+/// let (header, body) = <(LengthPrefixed<String>, String)>::decode(&RawBinDataFormat, &mut &record[..]).unwrap();
+/// assert_eq!(header.0, "header");
+/// assert_eq!(body, "body");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthPrefixed<E>(pub E);
+
+impl<E: Encodable> Encodable for LengthPrefixed<E> {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        let mut data = Vec::new();
+        self.0.encode(&mut data)?;
+        let len = u32::try_from(data.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "Encoded data is too large"))?;
+        w.write_all(&len.to_le_bytes())?;
+        w.write_all(&data)?;
+        Ok(4 + data.len())
+    }
+}
+///An entity which can be read from a channel record encoded with a given data format.
+pub trait Decodable<D: DataFormat>: Sized {
+    /// Decodes an object from a `Read`, usually a record read from a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The data format used to encode the record
+    /// * `read` - A Reader used to pull data from the record
+    ///
+    /// # Errors
+    ///
+    /// If the record data is invalid or an IO error occurs.
+    fn decode(format: &D, read: &mut impl Read) -> Result<Self, Error>;
+}
+
+///A byte vector takes all the remaining data from a record.
+impl Decodable<RawBinDataFormat> for Vec<u8> {
+    #[inline]
+    fn decode(_format: &RawBinDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+///A string takes all the remaining data from a record, which must be valid UTF-8.
+impl Decodable<RawBinDataFormat> for String {
+    #[inline]
+    fn decode(_format: &RawBinDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+        let mut data = String::new();
+        r.read_to_string(&mut data)?;
+        Ok(data)
+    }
+}
+
+macro_rules! decodable_num {
+    ($($num:ty),*) => {
+        $(
+            ///Numbers are decoded from their little endian bytes.
+            impl Decodable<RawBinDataFormat> for $num {
+                #[inline]
+                fn decode(_format: &RawBinDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+                    let mut bytes = [0u8; std::mem::size_of::<$num>()];
+                    r.read_exact(&mut bytes)?;
+                    Ok(<$num>::from_le_bytes(bytes))
+                }
+            }
+
+            impl<const N: usize> Decodable<RawBinDataFormat> for [$num; N] {
+                #[inline]
+                fn decode(format: &RawBinDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+                    let mut nums = [<$num>::default(); N];
+                    for num in nums.iter_mut() {
+                        *num = <$num>::decode(format, r)?;
+                    }
+                    Ok(nums)
+                }
+            }
+        )*
+    };
+}
+
+decodable_num!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+macro_rules! decodable_tuple {
+    ($($name:ident),+) => {
+        ///Tuples are decoded element by element, in order.
+        impl<DF: DataFormat, $($name: Decodable<DF>),+> Decodable<DF> for ($($name,)+) {
+            #[inline]
+            fn decode(format: &DF, r: &mut impl Read) -> Result<Self, Error> {
+                Ok(($($name::decode(format, r)?,)+))
+            }
+        }
+    };
+}
+
+decodable_tuple!(A);
+decodable_tuple!(A, B);
+decodable_tuple!(A, B, C);
+decodable_tuple!(A, B, C, D);
+decodable_tuple!(A, B, C, D, E);
+decodable_tuple!(A, B, C, D, E, F);
+decodable_tuple!(A, B, C, D, E, F, G);
+decodable_tuple!(A, B, C, D, E, F, G, H);
+
+///A length prefixed entity is decoded only from the number of bytes given by its prefix.
+impl<T: Decodable<RawBinDataFormat>> Decodable<RawBinDataFormat> for LengthPrefixed<T> {
+    #[inline]
+    fn decode(format: &RawBinDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+        let len = u32::decode(format, r)?;
+        let mut data = vec![0u8; len as usize];
+        r.read_exact(&mut data)?;
+        T::decode(format, &mut &data[..]).map(LengthPrefixed)
+    }
+}
+
+///A JSON document is encoded as its compact text representation, so it could carry arbitrary JSON data
+///without a concrete type. Requires the `json` feature.
+#[cfg(feature = "json")]
+impl Encodable for serde_json::Value {
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        let data = serde_json::to_vec(self)?;
+        w.write_all(&data)?;
+        Ok(data.len())
+    }
+}
+
+///A JSON document is decoded from the whole record. Requires the `json` feature.
+#[cfg(feature = "json")]
+impl Decodable<JsonDataFormat> for serde_json::Value {
+    #[inline]
+    fn decode(_format: &JsonDataFormat, r: &mut impl Read) -> Result<Self, Error> {
+        Ok(serde_json::from_reader(r)?)
     }
 }
+
 /// Handlers are components which will decorate a *write operation* .
 /// They can be use to add various metadata to a record(like timestamp, sequence id,
 /// universal unique id, check sum, record encoding type) either before or after
@@ -90,6 +417,13 @@ pub trait Handler {
     fn handle(&mut self, data: &impl Encodable, w: &mut impl Write) -> Result<usize, Error> {
         self.incoming(data, w).and_then(|_| self.outgoing(data, w))
     }
+
+    /// Flags to be set in the header of the record handled last. Only handlers which transform
+    /// the whole record, such a compression handler, should override this method. By default no flags are set.
+    #[inline]
+    fn rec_flags(&self) -> u64 {
+        0
+    }
 }
 
 /// The simplest and most important of all handlers. Just writes data into channel.
@@ -107,6 +441,21 @@ impl Handler for EncoderHandler {
     }
 }
 
+///The state of an existing channel, as found when another channel with the same identifier is created.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelState {
+    ///The channel is still being created by its writer
+    Initializing,
+    ///The writer of the channel is still running
+    Live,
+    ///The channel was closed by its writer
+    Closed,
+    ///The writer of the channel is gone without closing it
+    Abandoned,
+    ///The state of the channel could not be determined
+    Unknown,
+}
+
 ///Channel Access errors
 #[derive(Debug)]
 pub enum ChannelError {
@@ -117,6 +466,27 @@ pub enum ChannelError {
         ///The signature red from the kekbit storage
         actual: u64,
     },
+    ///The channel belongs to another application
+    WrongApplication {
+        ///The application identifier expected by the reader
+        expected: u64,
+        ///The application identifier red from the kekbit storage
+        actual: u64,
+    },
+    ///The channel has another identifier than the expected one
+    WrongChannel {
+        ///The channel identifier expected by the reader
+        expected: u64,
+        ///The channel identifier red from the kekbit storage
+        actual: u64,
+    },
+    ///The channel was created by another writer than the expected one
+    WrongWriter {
+        ///The writer identifier expected by the reader
+        expected: u64,
+        ///The writer identifier red from the kekbit storage
+        actual: u64,
+    },
     ///The channel's storage is of an incompatible file format
     IncompatibleVersion {
         ///Expected storage version
@@ -138,6 +508,20 @@ pub enum ChannelError {
         ///Reason why maximum message length is invalid
         msg: &'static str,
     },
+    ///The record header length is invalid
+    InvalidRecordHeaderLength {
+        ///The record header length
+        rec_header_len: u32,
+        ///Reason why the record header length is invalid
+        msg: &'static str,
+    },
+    ///The metadata length is invalid
+    InvalidMetadataLength {
+        ///The metadata length
+        metadata_len: u32,
+        ///Reason why the metadata length is invalid
+        msg: &'static str,
+    },
     ///The channel storage does not exist
     StorageNotFound {
         ///The file expected to back the channel storage
@@ -149,24 +533,47 @@ pub enum ChannelError {
         ///The file that backs the channel storage
         file_name: String,
     },
-    ///The channel storage is not ready to access
+    ///The channel storage already exists
     StorageAlreadyExists {
         ///The file that backs the channel storage
         file_name: String,
+        ///The state of the existing channel
+        existing_state: ChannelState,
+    },
+    ///The channel storage is exclusively held by another writer
+    AlreadyLocked {
+        ///The file that backs the channel storage
+        file_name: String,
     },
     ///The channel storage can't be accessed
     CouldNotAccessStorage {
         ///The file that backs the channel storage
         file_name: String,
     },
+    ///There is not enough free space in the file system to create the channel storage
+    InsufficientSpace {
+        ///The amount of bytes required by the channel storage
+        requested: u64,
+        ///The amount of bytes available in the file system
+        available: u64,
+    },
     ///Mapping the channel's file to memory had failed
     MemoryMappingFailed {
         reason: String,
     },
+    ///Locking the channel's memory had failed. Usually the `RLIMIT_MEMLOCK` limit is too low or privileges are missing.
+    MemoryLockFailed {
+        reason: String,
+    },
 
     AccessError {
         reason: String,
     },
+    ///The memory which backs the channel is not 8 bytes aligned, so the channel records cannot be accessed atomically
+    MisalignedBuffer {
+        ///The address of the memory which backs the channel
+        address: usize,
+    },
 }
 
 ///Write operation errors
@@ -178,12 +585,25 @@ pub enum WriteError {
     NoSpaceForRecord,
     /// The encoding operation had failed
     EncodingError(Error),
+    /// The record was empty. Empty records are reserved for heartbeats, so they are never written.
+    EmptyRecord,
     /// You must wait until the channel is availble for write. When multiple threads
     /// try to write into a channel, this error will be return if a given thread could
     /// get write access to the channel
     Wait,
 }
 
+///The outcome of a [best-effort write](trait.Writer.html#method.write_if_space).
+#[derive(Debug)]
+pub enum WriteOutcome {
+    ///The record was written. It holds the total amount of bytes wrote into the channel.
+    Written(u32),
+    ///There was no space for the record, so it was dropped.
+    Dropped,
+    ///The write operation had failed for any other reason than the lack of space.
+    Error(WriteError),
+}
+
 ///The `Writer` trait allows writing chunk of bytes as records into a kekbit channel.
 /// Implementers of this trait are called 'kekbit writers'. Usually a writer is bound to
 /// a given channel, and it is expected that there is only one writer which directly writes into the channel, however
@@ -202,6 +622,24 @@ pub trait Writer {
     /// If the operation fails, than an error variant will be returned. Some errors such [EncodingError or NoSpaceForRecord](enum.WriteError.html) may
     /// allow future writes to succeed while others such [ChannelFull](enum.WriteError.html#ChannelFull) signals the end of life for the channel.
     fn write<E: Encodable>(&mut self, data: &E) -> Result<u32, WriteError>;
+    /// Writes a given record to a kekbit channel if there is space available for it, otherwise the record
+    /// is silently dropped. Useful for best-effort producers(e.g. optional telemetry) which rather lose a
+    /// record than handle a full channel.
+    ///
+    /// Returns `Written` with the total amount of bytes wrote into the channel, `Dropped` if the channel
+    /// is full or the record is too large, or `Error` if the write operation fails for any other reason.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - information to be encoded and pushed into channel.
+    #[inline]
+    fn write_if_space<E: Encodable>(&mut self, data: &E) -> WriteOutcome {
+        match self.write(data) {
+            Ok(bytes) => WriteOutcome::Written(bytes),
+            Err(WriteError::ChannelFull) | Err(WriteError::NoSpaceForRecord) => WriteOutcome::Dropped,
+            Err(err) => WriteOutcome::Error(err),
+        }
+    }
     /// Flushes the stream which possibly backs the kekbit writer.
     /// By default this method does nothing, and should be implemented only for `Writer`s which it makes sense.
     /// Returns the success of the operation
@@ -222,6 +660,74 @@ pub enum ReadError {
     Closed,
     ///Channel full. There is no more space available in this channel.
     ChannelFull,
+    ///The storage which backs the channel was truncated or removed, so the channel cannot be safely read anymore.
+    StorageVanished,
+    ///The storage version of the channel had changed since the reader was created, so its records may not be readable anymore.
+    VersionChanged,
+    ///The record is longer than the reader is willing to accept. It holds the record length.
+    RecordTooLarge(u32),
+    ///The next record is compressed, so it cannot be returned without being decompressed into a buffer owned
+    ///by the reader. It could be read with `ShmReader::read_inflated`. The reader stays at the compressed record,
+    ///and the channel is not considered exhausted.
+    CompressedRecord,
+}
+
+///Errors which may occur while a record is read and decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    ///The record could not be read from the channel
+    Read(ReadError),
+    ///The record was read, but it could not be decoded
+    Decoding(Error),
+}
+
+impl From<ReadError> for DecodeError {
+    #[inline]
+    fn from(err: ReadError) -> Self {
+        DecodeError::Read(err)
+    }
+}
+
+///Errors which may occur while records are copied from a channel into another.
+#[derive(Debug)]
+pub enum CopyError {
+    ///A record could not be read from the source channel
+    Read(ReadError),
+    ///A record could not be written into the destination channel
+    Write(WriteError),
+}
+
+impl From<ReadError> for CopyError {
+    #[inline]
+    fn from(err: ReadError) -> Self {
+        CopyError::Read(err)
+    }
+}
+
+impl From<WriteError> for CopyError {
+    #[inline]
+    fn from(err: WriteError) -> Self {
+        CopyError::Write(err)
+    }
+}
+
+///Errors which may occur while a record is received from a [KekReceiver](../core/struct.KekReceiver.html).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecvError {
+    ///The sender was dropped, and all the records it had sent were received
+    Disconnected,
+    ///The channel cannot be read anymore
+    Failed(ReadError),
+}
+
+impl From<ReadError> for RecvError {
+    #[inline]
+    fn from(err: ReadError) -> Self {
+        match err {
+            ReadError::Closed => RecvError::Disconnected,
+            err => RecvError::Failed(err),
+        }
+    }
 }
 
 ///The `Reader` trait allows reading bytes from a kekbit channel. Implementers of this trait
@@ -246,4 +752,184 @@ pub trait Reader {
     /// Returns `None` if the channel is active, or `Some<ReadError>` if the channel has been exhausted.
     /// The error returned is the reason for which the channel is considered exhausted.
     fn exhausted(&self) -> Option<ReadError>;
+
+    /// Reads the next record from the channel without blocking and decodes it using the given data format.
+    /// Any data left in the record after decoding is ignored.
+    ///
+    /// Returns the decoded record, or None if no record is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The data format used to encode the record
+    ///
+    /// # Errors
+    ///
+    /// If the read operation fails or the record cannot be decoded. A decoding failure does not exhaust the channel.
+    #[inline]
+    fn decode_next<D: DataFormat, T: Decodable<D>>(&mut self, format: &D) -> Result<Option<T>, DecodeError>
+    where
+        Self: Sized,
+    {
+        match self.try_read()? {
+            Some(mut record) => T::decode(format, &mut record).map(Some).map_err(DecodeError::Decoding),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads, without blocking, all the records currently available in the channel and passes each of them to the given callback.
+    ///
+    /// Returns the number of records processed.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Callback invoked for every record read
+    ///
+    /// # Errors
+    ///
+    /// If the channel gets exhausted. The records read before the failure were already passed to the callback.
+    #[inline]
+    fn drain<F: FnMut(&[u8])>(&mut self, mut f: F) -> Result<usize, ReadError>
+    where
+        Self: Sized,
+    {
+        let mut count = 0;
+        while let Some(record) = self.try_read()? {
+            f(record);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Reads records from the channel without blocking until it finds the record with the given sequence number.
+    /// Every record read before it is passed to the given callback.
+    ///
+    /// Returns the record with the given sequence number, or None if it was not written yet. In this case
+    /// the method should be called again later.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq` - The sequence number of the expected record
+    /// * `seq_of` - Extracts the sequence number of a record. Records without a sequence number should return `None`.
+    ///   See [SequenceHandler::sequence_of](../core/struct.SequenceHandler.html#method.sequence_of).
+    /// * `on_record` - Callback invoked for every record read before the expected one
+    ///
+    /// # Errors
+    ///
+    /// If the channel is exhausted before the expected record is read.
+    #[inline]
+    fn read_until_sequence<'a, S, C>(&mut self, seq: u64, seq_of: S, mut on_record: C) -> Result<Option<&'a [u8]>, ReadError>
+    where
+        Self: Sized,
+        S: Fn(&[u8]) -> Option<u64>,
+        C: FnMut(&'a [u8]),
+    {
+        while let Some(record) = self.try_read()? {
+            if seq_of(record) == Some(seq) {
+                return Ok(Some(record));
+            }
+            on_record(record);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn data_format_ids_are_unique() {
+        #[allow(unused_mut)]
+        let mut ids = vec![RawBinDataFormat.id()];
+        #[cfg(feature = "capnp")]
+        ids.push(crate::core::CapnpDataFormat.id());
+        #[cfg(feature = "json")]
+        ids.push(JsonDataFormat.id());
+        #[cfg(feature = "rkyv")]
+        ids.push(crate::core::RkyvDataFormat.id());
+        let mut unique = ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+    }
+
+    fn encoded(data: &impl Encodable) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let len = data.encode(&mut buf).unwrap();
+        assert_eq!(len, buf.len());
+        buf
+    }
+
+    #[test]
+    fn encode_numbers() {
+        let buf = encoded(&42u64);
+        assert_eq!(u64::from_le_bytes(buf[..].try_into().unwrap()), 42);
+        assert_eq!(encoded(&7u8), vec![7]);
+        assert_eq!(encoded(&-2i16), (-2i16).to_le_bytes().to_vec());
+        assert_eq!(encoded(&1.5f64), 1.5f64.to_le_bytes().to_vec());
+        assert_eq!(encoded(&[1u32, 2u32]), vec![1, 0, 0, 0, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encode_tuples() {
+        let buf = encoded(&(11u64, 22u64));
+        assert_eq!(buf.len(), 16);
+        assert_eq!(u64::from_le_bytes(buf[0..8].try_into().unwrap()), 11);
+        assert_eq!(u64::from_le_bytes(buf[8..16].try_into().unwrap()), 22);
+        assert_eq!(encoded(&(1u8, "ab", 3u16)), vec![1, b'a', b'b', 3, 0]);
+    }
+
+    #[test]
+    fn encode_bytes() {
+        assert_eq!(encoded(&"kek"), b"kek".to_vec());
+        assert_eq!(encoded(&"kek".to_string()), b"kek".to_vec());
+        assert_eq!(encoded(&b"kek"), b"kek".to_vec());
+        assert_eq!(encoded(&vec![1u8, 2u8]), vec![1, 2]);
+        assert_eq!(encoded(&Box::new([3u8; 3])), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn decode_numbers() {
+        let fmt = RawBinDataFormat;
+        let buf = encoded(&42u64);
+        assert_eq!(u64::decode(&fmt, &mut &buf[..]).unwrap(), 42);
+        let buf = encoded(&[-1i32, 5i32]);
+        assert_eq!(<[i32; 2]>::decode(&fmt, &mut &buf[..]).unwrap(), [-1, 5]);
+        let buf = encoded(&1u16);
+        assert_eq!(
+            u32::decode(&fmt, &mut &buf[..]).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn decode_tuples() {
+        let fmt = RawBinDataFormat;
+        let buf = encoded(&(11u64, 22u64));
+        assert_eq!(<(u64, u64)>::decode(&fmt, &mut &buf[..]).unwrap(), (11, 22));
+        let buf = encoded(&(3u8, "kek"));
+        assert_eq!(<(u8, String)>::decode(&fmt, &mut &buf[..]).unwrap(), (3, "kek".to_string()));
+        assert_eq!(<(u8, Vec<u8>)>::decode(&fmt, &mut &buf[..]).unwrap(), (3, b"kek".to_vec()));
+        assert_eq!(fmt.id(), 1);
+        assert_eq!(fmt.media_type(), "application/octet-stream");
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let fmt = RawBinDataFormat;
+        let buf = encoded(&(LengthPrefixed("kekbit"), 42u64));
+        assert_eq!(buf.len(), 4 + 6 + 8);
+        assert_eq!(u32::from_le_bytes(buf[0..4].try_into().unwrap()), 6);
+        assert_eq!(&buf[4..10], b"kekbit");
+        let (body, num) = <(LengthPrefixed<String>, u64)>::decode(&fmt, &mut &buf[..]).unwrap();
+        assert_eq!(body, LengthPrefixed("kekbit".to_string()));
+        assert_eq!(num, 42);
+        let nested = encoded(&LengthPrefixed(LengthPrefixed(7u8)));
+        assert_eq!(nested, vec![5, 0, 0, 0, 1, 0, 0, 0, 7]);
+        assert_eq!(
+            <LengthPrefixed<Vec<u8>>>::decode(&fmt, &mut &buf[..8]).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
 }
@@ -1,30 +1,98 @@
 //! Provides the components and functions required to work with memory mapped data channels.
+mod archive;
+#[cfg(feature = "rkyv")]
+mod archived;
+mod batch;
+mod builder;
+#[cfg(feature = "capnp")]
+mod capnproto;
+mod checksum;
+#[cfg(feature = "compression")]
+mod compression;
+mod copy;
+#[cfg(feature = "json")]
+mod export;
+mod flusher;
+mod frame;
 mod handlers;
+mod integrity;
+mod layout;
+mod lock;
+#[cfg(feature = "hdrhistogram")]
+mod measuring;
+mod merge;
 mod metadata;
+#[cfg(unix)]
+mod named;
+mod pair;
 mod reader;
+mod reconnect;
+mod replay;
+mod reqrep;
+mod shard;
+mod stream;
+mod tee;
 mod tick;
 mod utils;
 mod version;
 mod writer;
 
+pub use archive::*;
+#[cfg(feature = "rkyv")]
+pub use archived::*;
+pub use batch::*;
+pub use builder::*;
+#[cfg(feature = "capnp")]
+pub use capnproto::*;
+pub use checksum::*;
+#[cfg(feature = "compression")]
+pub use compression::*;
+pub use copy::*;
+#[cfg(feature = "json")]
+pub use export::*;
+pub use flusher::*;
+pub use frame::*;
 pub use handlers::*;
+pub use integrity::*;
+pub use layout::*;
+pub use lock::*;
+#[cfg(feature = "hdrhistogram")]
+pub use measuring::*;
+pub use merge::*;
 pub use metadata::*;
+#[cfg(unix)]
+pub use named::*;
+pub use pair::*;
 pub use reader::*;
+pub use reconnect::*;
+pub use replay::*;
+pub use reqrep::*;
+pub use shard::*;
+pub use stream::*;
+pub use tee::*;
 pub use tick::*;
 pub use writer::*;
 
-use log::{error, info};
 use memmap::MmapOptions;
 
 use crate::api::ChannelError;
 use crate::api::ChannelError::*;
+use crate::api::ChannelState;
 use crate::api::Handler;
+use crate::api::{ReadError, Reader};
+use crate::retry::RetryPolicy;
 
-use crate::core::utils::FOOTER_LEN;
+use crate::core::metadata::METADATA_LEN;
+use crate::core::utils::{free_space, is_no_space, lock_file, lock_memory, FOOTER_LEN};
 use std::fs::OpenOptions;
 use std::fs::{remove_file, DirBuilder};
 use std::path::Path;
 use std::result::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//the granularity used to copy the pages of a channel snapshot, no larger than any page size in use
+const SNAPSHOT_PAGE_LEN: usize = 4096;
+
 /// Creates a kekbit reader associated to a memory mapped channel.
 ///
 /// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or an error if the operation fails.
@@ -56,29 +124,227 @@ use std::result::Result;
 ///
 /// ```
 pub fn shm_reader(root_path: &Path, channel_id: u64) -> Result<ShmReader, ChannelError> {
-    let kek_file_path = storage_path(root_path, channel_id).into_path_buf();
+    let kek_file = open_storage(root_path, channel_id, true)?;
+    let mmap = unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+    ShmReader::new(mmap).map(|reader| reader.with_backing(kek_file))
+}
+
+/// Creates a kekbit reader associated to a memory mapped channel stored in the given file, which does not need
+/// to follow the [storage_path](fn.storage_path.html) layout. The channel id is taken from the channel metadata.
+///
+/// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or an error if the operation fails.
+///
+/// # Arguments
+///
+/// * `kek_file_path` - The path to the file which stores the channel
+///
+/// # Errors
+///
+/// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let kek_file_path = test_tmp_dir.path().join("prices.dat");
+/// # let writer = shm_writer_at(&kek_file_path, &metadata, EncoderHandler::default()).unwrap();
+/// let reader = shm_reader_at(&kek_file_path).unwrap();
+/// assert_eq!(reader.metadata().channel_id(), 42);
+/// ```
+pub fn shm_reader_at(kek_file_path: &Path) -> Result<ShmReader, ChannelError> {
+    let kek_file = open_storage_at(kek_file_path, true)?;
+    let mmap = unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+    let reader = ShmReader::new(mmap)?.with_backing(kek_file);
+    info!(
+        "[channel {}] Kekbit file {:?} opened for read.",
+        reader.metadata().channel_id(),
+        kek_file_path
+    );
+    Ok(reader)
+}
+
+/// Creates a kekbit reader over a private, copy-on-write, snapshot of a channel. The reader sees only the
+/// records written before it was created, so it could be used to inspect a live channel, e.g. by forensic
+/// tools, without any interference with the writer or the other readers. As the whole channel is copied in
+/// memory, this operation is expensive for large channels.
+///
+/// Returns a ready to use reader which points to the beginning of the channel snapshot if succeeds, or an error if the operation fails.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+///
+/// # Errors
+///
+/// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Before").unwrap();
+/// let mut reader = shm_reader_cow(&test_tmp_dir.path(), 42).unwrap();
+/// writer.write(&"After").unwrap();
+/// assert_eq!(reader.try_read().unwrap().unwrap(), b"Before");
+/// assert!(reader.try_read().unwrap().is_none());
+/// ```
+pub fn shm_reader_cow(root_path: &Path, channel_id: u64) -> Result<ShmReader, ChannelError> {
+    let kek_file = open_storage(root_path, channel_id, false)?;
+    let mut mmap =
+        unsafe { MmapOptions::new().map_copy(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+    //every page is copied by an atomic no-op update of its first word, which could be concurrently written
+    //by the writer, so the word is never torn. The pages are copied one by one, while the writer goes on,
+    //so the reader still checks the markers which follow the records, as it does on a live channel.
+    for offset in (0..mmap.len()).step_by(SNAPSHOT_PAGE_LEN) {
+        let word = unsafe { &*(mmap.as_mut_ptr().add(offset) as *const AtomicU64) };
+        word.fetch_or(0, Ordering::AcqRel);
+    }
+    info!("[channel {}] Kekbit channel snapshot taken", channel_id);
+    ShmReader::new(mmap).map(|reader| reader.with_backing(kek_file))
+}
+
+/// Reads all the records currently available in a channel into owned buffers. It is meant mostly for tests,
+/// to compare the contents of a channel with the expected records. The reading stops at the end of the
+/// available records, or when the channel is closed. Heartbeats are skipped.
+///
+/// Returns the records of the channel, in the order they were written.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+///
+/// # Errors
+///
+/// Any read failure, other than the channel was closed. If the channel cannot be opened the error is
+/// `StorageVanished` when the channel storage does not exist, and `Failed` otherwise, the cause being logged.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Hello").unwrap();
+/// writer.write(&"World").unwrap();
+/// assert_eq!(collect_channel(&test_tmp_dir.path(), 42).unwrap(), vec![b"Hello".to_vec(), b"World".to_vec()]);
+/// ```
+pub fn collect_channel(root_path: &Path, channel_id: u64) -> Result<Vec<Vec<u8>>, ReadError> {
+    let mut reader = shm_reader(root_path, channel_id).map_err(|err| {
+        error!(
+            "[channel {}] Channel cannot be opened to collect its records: {:?}",
+            channel_id, err
+        );
+        match err {
+            StorageNotFound { .. } => ReadError::StorageVanished,
+            _ => ReadError::Failed,
+        }
+    })?;
+    let mut records = Vec::new();
+    loop {
+        match reader.try_read() {
+            Ok(Some(record)) => records.push(record.to_vec()),
+            Ok(None) | Err(ReadError::Closed) => return Ok(records),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+//Opens the storage of a channel which is ready to be read.
+fn open_storage(root_path: &Path, channel_id: u64, write: bool) -> Result<std::fs::File, ChannelError> {
+    let kek_file = open_storage_at(&storage_path(root_path, channel_id), write)?;
+    info!("[channel {}] Kekbit file {:?} opened for read.", channel_id, kek_file);
+    Ok(kek_file)
+}
+
+fn open_storage_at(kek_file_path: &Path, write: bool) -> Result<std::fs::File, ChannelError> {
     let kek_lock_path = kek_file_path.with_extension("lock");
     if !kek_file_path.exists() {
         return Err(StorageNotFound {
             file_name: kek_file_path.to_str().unwrap().to_string(),
         });
     }
-    if kek_lock_path.exists() {
+    if lock_held(&kek_lock_path) {
         return Err(StorageNotReady {
             file_name: kek_file_path.to_str().unwrap().to_string(),
         });
     }
     let kek_file = OpenOptions::new()
-        .write(true)
+        .write(write)
         .read(true)
-        .open(&kek_file_path)
+        .open(kek_file_path)
         .map_err(|err| CouldNotAccessStorage {
             file_name: err.to_string(),
         })?;
+    //a writer which crashed before it wrote the metadata may leave behind a storage too short to hold it
+    let storage_len = kek_file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    if storage_len < METADATA_LEN as u64 {
+        warn!(
+            "Kekbit file {:?} has only {} bytes, which cannot hold the channel metadata",
+            kek_file_path, storage_len
+        );
+        return Err(StorageNotReady {
+            file_name: kek_file_path.to_str().unwrap().to_string(),
+        });
+    }
+    Ok(kek_file)
+}
 
-    info!("Kekbit file {:?} opened for read.", kek_file);
-    let mmap = unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
-    ShmReader::new(mmap)
+/// Creates a kekbit reader associated to a memory mapped channel which must belong to the given application.
+/// See [with_app_id](struct.Metadata.html#method.with_app_id).
+///
+/// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or an error if the operation fails.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+/// * `app_id` - The identifier of the application which must own the channel.
+///
+/// # Errors
+///
+/// [WrongApplication](../api/enum.ChannelError.html#variant.WrongApplication) if the channel was created for another application,
+/// or any error returned by [shm_reader](fn.shm_reader.html).
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos).with_app_id(7);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let reader = shm_reader_for_app(&test_tmp_dir.path(), 42, 7).unwrap();
+/// assert!(shm_reader_for_app(&test_tmp_dir.path(), 42, 8).is_err());
+/// ```
+pub fn shm_reader_for_app(root_path: &Path, channel_id: u64, app_id: u64) -> Result<ShmReader, ChannelError> {
+    let reader = shm_reader(root_path, channel_id)?;
+    let actual = reader.metadata().app_id();
+    if actual != app_id {
+        return Err(WrongApplication {
+            expected: app_id,
+            actual,
+        });
+    }
+    Ok(reader)
 }
 
 /// Tries multiple times to create a kekbit reader associated to a memory mapped channel.
@@ -120,19 +386,105 @@ pub fn shm_reader(root_path: &Path, channel_id: u64) -> Result<ShmReader, Channe
 /// println!("{:?}", reader.metadata());
 ///
 /// ```
+#[inline]
 pub fn try_shm_reader(root_path: &Path, channel_id: u64, duration_millis: u64, tries: u64) -> Result<ShmReader, ChannelError> {
-    assert!(tries > 0);
-    let interval = duration_millis / tries;
-    let sleep_duration = std::time::Duration::from_millis(interval);
-    let mut reader_res = shm_reader(root_path, channel_id);
-    let mut tries_left = tries;
-    while reader_res.is_err() && tries_left > 0 {
-        std::thread::sleep(sleep_duration);
-        reader_res = shm_reader(root_path, channel_id);
-        tries_left -= 1;
-    }
-    reader_res
+    try_shm_reader_with(root_path, channel_id, RetryPolicy::sleeping(duration_millis, tries))
+}
+
+/// Tries multiple times to create a kekbit reader associated to a memory mapped channel, as described
+/// by the given [RetryPolicy](../retry/struct.RetryPolicy.html). A low latency consumer may spin, or yield,
+/// between two attempts, while a patient one should sleep.
+///
+/// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or the error *returned by the last try* if it fails.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+/// * `policy` - How many times, for how long and how to wait between the attempts to create the reader
+///
+/// # Errors
+///
+/// Various [errors](enum.ChannelError.html) may occur if the operation fails.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use kekbit::retry::*;
+/// use std::time::Duration;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let policy = RetryPolicy::default()
+///     .duration(Duration::from_millis(10))
+///     .max_attempts(u64::MAX)
+///     .wait(PollWait::Yield);
+/// let reader = try_shm_reader_with(&test_tmp_dir.path(), 42, policy).unwrap();
+/// ```
+#[inline]
+pub fn try_shm_reader_with(root_path: &Path, channel_id: u64, policy: RetryPolicy) -> Result<ShmReader, ChannelError> {
+    policy.retry(|| shm_reader(root_path, channel_id))
+}
+
+/// Tries multiple times to create a kekbit reader associated to a channel, until the channel is
+/// initialized and its writer had declared it [ready](struct.ShmWriter.html#method.set_ready).
+/// Between two tries the function will spin/sleep for a about ```duration_millis/tries```
+/// milliseconds so potentially could be blocking.
+/// Use this method when a writer performs some application level initialization, such writing a
+/// schema record, before its channel could be read.
+///
+/// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or the error *returned by the last try* if it fails.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+/// * `duration_millis` - How long it should try in milliseconds
+/// * `tries` - How many times it will try during the given time duration
+///
+/// # Errors
+///
+/// Various [errors](enum.ChannelError.html) may occur if the operation fails. If the channel exists but it
+/// was not declared ready in time, `StorageNotReady` is returned.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// let channel_id = 42;
+/// # let metadata = Metadata::new(1850, channel_id, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.set_ready();
+/// let reader = try_shm_reader_ready(&test_tmp_dir.path(), channel_id, 1000, 10).unwrap();
+/// assert!(reader.is_ready());
+/// ```
+pub fn try_shm_reader_ready(
+    root_path: &Path,
+    channel_id: u64,
+    duration_millis: u64,
+    tries: u64,
+) -> Result<ShmReader, ChannelError> {
+    RetryPolicy::sleeping(duration_millis, tries).retry(|| {
+        shm_reader(root_path, channel_id).and_then(|reader| {
+            if reader.is_ready() {
+                Ok(reader)
+            } else {
+                Err(ChannelError::StorageNotReady {
+                    file_name: storage_path(root_path, channel_id).to_string_lossy().to_string(),
+                })
+            }
+        })
+    })
 }
+
 //This method should be removed as soon as metadata is exposed in the reader trait
 /// Decorates a [ShmReader](struct.ShmReader.html) with a timeout functionality.
 ///
@@ -185,56 +537,344 @@ pub fn shm_timeout_reader(reader: ShmReader) -> TimeoutReader<ShmReader> {
 /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
 /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
 /// ```
+#[inline]
 pub fn shm_writer<H: Handler>(root_path: &Path, metadata: &Metadata, rec_handler: H) -> Result<ShmWriter<H>, ChannelError> {
-    let kek_file_path = storage_path(root_path, metadata.channel_id()).into_path_buf();
+    ChannelBuilder::default().shm_writer(root_path, metadata, rec_handler)
+}
+
+/// Creates a file backed memory mapped kekbit channel stored in the given file, and a writer associate with it.
+/// Unlike [shm_writer](fn.shm_writer.html) the file does not follow the [storage_path](fn.storage_path.html) layout,
+/// so the channel should be opened using [shm_reader_at](fn.shm_reader_at.html). The lock file used during the
+/// channel creation is placed next to the channel file, having the `lock` extension.
+///
+/// Returns a ready to use writer to the new created channel or an error if the operation fails.
+///
+/// # Arguments
+///
+/// * `kek_file_path` - The path to the file which will store the channel. Missing parent folders are created.
+/// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+/// * `rec_handler` - The handler used by the writer to push records into the channel
+///
+/// # Errors
+///
+/// Various [errors](enum.ChannelError.html) may occur if the operation fails.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let kek_file_path = test_tmp_dir.path().join("volume").join("prices.dat");
+/// let mut writer = shm_writer_at(&kek_file_path, &metadata, EncoderHandler::default()).unwrap();
+/// ```
+#[inline]
+pub fn shm_writer_at<H: Handler>(
+    kek_file_path: &Path,
+    metadata: &Metadata,
+    rec_handler: H,
+) -> Result<ShmWriter<H>, ChannelError> {
+    ChannelBuilder::default().shm_writer_at(kek_file_path, metadata, rec_handler)
+}
+
+/// Creates a file backed memory mapped kekbit channel and a writer associate with it, but keeps the channel
+/// hidden from readers until the returned [LockGuard](struct.LockGuard.html) is released or dropped.
+/// This way a writer could perform any post creation setup, such as writing a schema record, before any
+/// reader attaches to the channel.
+///
+/// Returns a ready to use writer to the new created channel, together with the guard of the channel lock,
+/// or an error if the operation fails.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writers id.
+/// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+/// * `rec_handler` - The handler used by the writer to push records into the channel
+///
+/// # Errors
+///
+/// Various [errors](enum.ChannelError.html) may occur if the operation fails.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let (mut writer, lock) = shm_writer_locked(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Schema").unwrap();
+/// assert!(shm_reader(&test_tmp_dir.path(), 42).is_err());
+/// lock.release();
+/// assert!(shm_reader(&test_tmp_dir.path(), 42).is_ok());
+/// ```
+#[inline]
+pub fn shm_writer_locked<H: Handler>(
+    root_path: &Path,
+    metadata: &Metadata,
+    rec_handler: H,
+) -> Result<(ShmWriter<H>, LockGuard), ChannelError> {
+    ChannelBuilder::default().shm_writer_locked(root_path, metadata, rec_handler)
+}
+
+#[inline]
+fn create_shm_writer<H: Handler>(
+    kek_file_path: &Path,
+    metadata: &Metadata,
+    rec_handler: H,
+    options: &ChannelBuilder,
+) -> Result<ShmWriter<H>, ChannelError> {
+    create_shm_writer_locked(kek_file_path, metadata, rec_handler, options).map(|(writer, lock)| {
+        lock.release();
+        writer
+    })
+}
+
+fn create_shm_writer_locked<H: Handler>(
+    kek_file_path: &Path,
+    metadata: &Metadata,
+    rec_handler: H,
+    options: &ChannelBuilder,
+) -> Result<(ShmWriter<H>, LockGuard), ChannelError> {
     if kek_file_path.exists() {
         return Err(StorageAlreadyExists {
             file_name: kek_file_path.to_str().unwrap().to_string(),
+            existing_state: existing_state(kek_file_path),
         });
     }
-    let mut builder = DirBuilder::new();
-    builder.recursive(true);
-    builder
-        .create(&kek_file_path.parent().unwrap())
-        .map_err(|err| CouldNotAccessStorage {
-            file_name: err.to_string(),
-        })?;
+    ensure_storage_dir(kek_file_path).map_err(|err| CouldNotAccessStorage {
+        file_name: err.to_string(),
+    })?;
     let kek_lock_path = kek_file_path.with_extension("lock");
-    OpenOptions::new()
+    //if the lock is held another writer is creating the channel, a lock left behind by a crashed writer is taken over
+    let kek_lock = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(false)
         .open(&kek_lock_path)
         .map_err(|err| CouldNotAccessStorage {
             file_name: err.to_string(),
         })?;
-    info!("Kekbit lock {:?} created", kek_lock_path);
+    if lock_file(&kek_lock).is_err() {
+        return Err(StorageAlreadyExists {
+            file_name: kek_file_path.to_str().unwrap().to_string(),
+            existing_state: ChannelState::Initializing,
+        });
+    }
+    info!(
+        "[channel {} writer {}] Kekbit lock {:?} created",
+        metadata.channel_id(),
+        metadata.writer_id(),
+        kek_lock_path
+    );
+    //from now on, the lock is removed whenever the guard is dropped, on success or on any failure
+    let lock = LockGuard::new(kek_lock_path, kek_lock);
+    //the storage is created only if it does not exist, so a racing writer could never truncate it
     let kek_file = OpenOptions::new()
         .write(true)
         .read(true)
-        .create(true)
-        .open(&kek_file_path)
-        .map_err(|err| CouldNotAccessStorage {
-            file_name: err.to_string(),
+        .create_new(true)
+        .open(kek_file_path)
+        .map_err(|err| {
+            if err.kind() == std::io::ErrorKind::AlreadyExists {
+                StorageAlreadyExists {
+                    file_name: kek_file_path.to_str().unwrap().to_string(),
+                    existing_state: existing_state(kek_file_path),
+                }
+            } else {
+                CouldNotAccessStorage {
+                    file_name: err.to_string(),
+                }
+            }
         })?;
+    if let Err(err) = lock_file(&kek_file) {
+        error!(
+            "[channel {} writer {}] Could not lock kekbit channel {:?}: {}",
+            metadata.channel_id(),
+            metadata.writer_id(),
+            kek_file_path,
+            err
+        );
+        remove_storage(kek_file_path);
+        return Err(AlreadyLocked {
+            file_name: kek_file_path.to_str().unwrap().to_string(),
+        });
+    }
     let total_len = (metadata.capacity() + metadata.len() as u32 + FOOTER_LEN) as u64;
-    kek_file.set_len(total_len).map_err(|err| CouldNotAccessStorage {
-        file_name: err.to_string(),
-    })?;
-    info!("Kekbit channel store {:?} created.", kek_file);
-    let mut mmap =
-        unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+    let storage_dir = kek_file_path.parent().unwrap();
+    if let Err(err) = check_space(total_len, free_space(storage_dir)) {
+        error!(
+            "[channel {} writer {}] Not enough space to create kekbit channel {:?}: {:?}",
+            metadata.channel_id(),
+            metadata.writer_id(),
+            kek_file_path,
+            err
+        );
+        remove_storage(kek_file_path);
+        return Err(err);
+    }
+    if let Err(err) = kek_file.set_len(total_len) {
+        remove_storage(kek_file_path);
+        return Err(sizing_error(&err, total_len, free_space(storage_dir)));
+    }
+    info!(
+        "[channel {} writer {}] Kekbit channel store {:?} created.",
+        metadata.channel_id(),
+        metadata.writer_id(),
+        kek_file
+    );
+    let mut mmap = match unsafe { MmapOptions::new().map_mut(&kek_file) } {
+        Ok(mmap) => mmap,
+        Err(err) => {
+            remove_storage(kek_file_path);
+            return Err(MemoryMappingFailed { reason: err.to_string() });
+        }
+    };
+    if options.locked {
+        if let Err(err) = lock_memory(mmap.as_ptr(), mmap.len()) {
+            if options.lock_required {
+                error!(
+                    "[channel {} writer {}] Could not lock kekbit channel {:?} in memory: {}",
+                    metadata.channel_id(),
+                    metadata.writer_id(),
+                    kek_file_path,
+                    err
+                );
+                std::mem::drop(mmap);
+                remove_storage(kek_file_path);
+                return Err(MemoryLockFailed { reason: err.to_string() });
+            }
+            warn!(
+                "[channel {} writer {}] Could not lock kekbit channel {:?} in memory: {}. Pages may be swapped",
+                metadata.channel_id(),
+                metadata.writer_id(),
+                kek_file_path,
+                err
+            );
+        } else {
+            info!(
+                "[channel {} writer {}] Kekbit channel {:?} locked in memory",
+                metadata.channel_id(),
+                metadata.writer_id(),
+                kek_file_path
+            );
+        }
+    }
     let buf = &mut mmap[..];
     metadata.write_to(buf);
-    mmap.flush().map_err(|err| AccessError { reason: err.to_string() })?;
-    info!("Kekbit channel with store {:?} succesfully initialized", kek_file_path);
-    let res = ShmWriter::new(mmap, rec_handler);
+    if options.durable_init {
+        if let Err(err) = mmap.flush() {
+            std::mem::drop(mmap);
+            remove_storage(kek_file_path);
+            return Err(AccessError { reason: err.to_string() });
+        }
+    }
+    info!(
+        "[channel {} writer {}] Kekbit channel with store {:?} successfully initialized",
+        metadata.channel_id(),
+        metadata.writer_id(),
+        kek_file_path
+    );
+    let res = ShmWriter::new(mmap, rec_handler).map(|writer| {
+        let writer = writer.with_storage(kek_file);
+        #[cfg(feature = "digest")]
+        let writer = if options.digest { writer.with_digest() } else { writer };
+        if options.delete_on_close {
+            writer.with_delete_on_close(kek_file_path.to_path_buf())
+        } else {
+            writer
+        }
+    });
     if res.is_err() {
-        error!("Kekbit writer creation error . The file {:?} will be removed!", kek_file_path);
-        remove_file(&kek_file_path).expect("Could not remove kekbit file");
+        error!(
+            "[channel {} writer {}] Kekbit writer creation error . The file {:?} will be removed!",
+            metadata.channel_id(),
+            metadata.writer_id(),
+            kek_file_path
+        );
+        remove_storage(kek_file_path);
+    }
+    res.map(|writer| (writer, lock))
+}
+
+//Checks the space available in the file system, when it is known, is enough for a storage of the requested length
+fn check_space(requested: u64, available: Option<u64>) -> Result<(), ChannelError> {
+    match available {
+        Some(available) if requested > available => Err(InsufficientSpace { requested, available }),
+        _ => Ok(()),
+    }
+}
+
+//Maps the failure to size a new storage to a channel error. A file system which ran out of space
+//reports the space still available, as other processes may have used it since it was checked.
+fn sizing_error(err: &std::io::Error, requested: u64, available: Option<u64>) -> ChannelError {
+    if is_no_space(err) {
+        InsufficientSpace {
+            requested,
+            available: available.unwrap_or(0),
+        }
+    } else {
+        CouldNotAccessStorage {
+            file_name: err.to_string(),
+        }
+    }
+}
+
+//Finds out the state of a channel which already exists at the given path
+fn existing_state(kek_file_path: &Path) -> ChannelState {
+    if lock_held(&kek_file_path.with_extension("lock")) {
+        return ChannelState::Initializing;
+    }
+    match OpenOptions::new().write(true).read(true).open(kek_file_path) {
+        Ok(kek_file) => storage_state(&kek_file),
+        Err(_) => ChannelState::Unknown,
+    }
+}
+
+//Finds out the state of an existing channel storage. A live writer holds the lock of the storage,
+//while the state of a channel without writer is given by the marker found after its last record.
+fn storage_state(kek_file: &std::fs::File) -> ChannelState {
+    let live = lock_file(kek_file).is_err();
+    let reader = unsafe { MmapOptions::new().map_mut(kek_file) }
+        .ok()
+        .and_then(|mmap| ShmReader::new(mmap).ok());
+    let mut reader = match reader {
+        Some(reader) => reader,
+        None => return ChannelState::Unknown,
+    };
+    loop {
+        match reader.try_read() {
+            Ok(Some(_)) => continue,
+            Err(ReadError::Closed) => return ChannelState::Closed,
+            Ok(None) | Err(ReadError::ChannelFull) | Err(ReadError::Timeout(_)) if !live => return ChannelState::Abandoned,
+            Ok(None) | Err(ReadError::ChannelFull) | Err(ReadError::Timeout(_)) => return ChannelState::Live,
+            Err(_) => return ChannelState::Unknown,
+        }
+    }
+}
+
+//Removes the partially created storage of a channel, its lock file is removed by the lock guard
+fn remove_storage(kek_file_path: &Path) {
+    match remove_file(kek_file_path) {
+        Ok(_) => info!("Kekbit file {:?} removed", kek_file_path),
+        Err(err) => error!("Could not remove kekbit file {:?}: {}", kek_file_path, err),
+    }
+}
+
+//Checks if the given lock file is held by a writer which is still creating its channel. A lock file left
+//behind by a writer which crashed is stale. Where files cannot be locked, only the lock file presence tells.
+fn lock_held(kek_lock_path: &Path) -> bool {
+    match OpenOptions::new().read(true).open(kek_lock_path) {
+        Ok(lock) => cfg!(not(unix)) || lock_file(&lock).is_err(),
+        Err(_) => false,
     }
-    remove_file(&kek_lock_path).expect("Could not remove kekbit lock file");
-    info!("Kekbit lock file {:?} removed", kek_lock_path);
-    res
 }
 
 /// Returns the path to the file associated with a channel inside a kekbit root folder.
@@ -242,7 +882,7 @@ pub fn shm_writer<H: Handler>(root_path: &Path, metadata: &Metadata, rec_handler
 /// # Arguments
 ///
 ///  * `root_path` - Path to the kekbit root folder, a folder where channels are stored. Multiple such
-///   folders may exist in a system.  
+///    folders may exist in a system.
 ///  * `channel_id` - Channel for which the file path will be returned
 ///
 #[inline]
@@ -255,41 +895,141 @@ pub fn storage_path(root_path: &Path, channel_id: u64) -> Box<Path> {
     dir_path.with_extension("kekbit").into_boxed_path()
 }
 
+/// Creates, if it does not exist yet, the folder where a channel stores its data.
+/// It could be called before the channel is created, e.g. to prepare the folders of many channels
+/// ahead of time. Channel creation does the same, so calling it is never required.
+///
+/// # Arguments
+///
+///  * `root_path` - The path to the folder where all the channels will be stored
+///  * `channel_id` - Channel for which the folder will be created
+///
+/// # Errors
+///
+/// If the folder cannot be created. A folder created meanwhile by another thread or process is not an error.
+///
+/// # Example
+///
+/// ```
+/// use kekbit::core::*;
+///
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// ensure_channel_dir(test_tmp_dir.path(), 42).unwrap();
+/// assert!(storage_path(test_tmp_dir.path(), 42).parent().unwrap().is_dir());
+/// ```
+#[inline]
+pub fn ensure_channel_dir(root_path: &Path, channel_id: u64) -> std::io::Result<()> {
+    ensure_storage_dir(&storage_path(root_path, channel_id))
+}
+
+//Channels created at the same time may share their folder, so a folder created meanwhile by someone else is fine
+fn ensure_storage_dir(kek_file_path: &Path) -> std::io::Result<()> {
+    let dir_path = match kek_file_path.parent() {
+        Some(dir_path) if !dir_path.as_os_str().is_empty() => dir_path,
+        _ => return Ok(()),
+    };
+    match DirBuilder::new().recursive(true).create(dir_path) {
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && dir_path.is_dir() => Ok(()),
+        res => res,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::tick::TickUnit::Nanos;
-    use super::utils::{align, REC_HEADER_LEN};
+    use super::utils::{align, CONTINUED, REC_HEADER_LEN};
     use super::*;
     use crate::api::EncoderHandler;
     use crate::api::ReadError;
     use crate::api::ReadError::Timeout;
     use crate::api::Reader;
+    use crate::api::WriteError;
+    use crate::api::WriteOutcome;
     use crate::api::Writer;
     use crate::core::TickUnit::Millis;
+    use crate::retry::PollWait;
+    use log::{LevelFilter, Log, Metadata as LogMetadata, Record};
     use simple_logger::SimpleLogger;
+    use std::sync::atomic::Ordering;
     use std::sync::Arc;
+    use std::sync::Barrier;
+    use std::sync::Mutex;
     use std::sync::Once;
+    use std::time::{Duration, Instant};
     use tempdir::TempDir;
     const FOREVER: u64 = 99_999_999_999;
     static INIT_LOG: Once = Once::new();
+    static CAPTURED_LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    //Keeps a copy of every log message, so tests could check what was logged
+    struct CapturingLogger {
+        inner: SimpleLogger,
+    }
+
+    impl CapturingLogger {
+        fn install() {
+            let logger = CapturingLogger {
+                inner: SimpleLogger::new(),
+            };
+            log::set_boxed_logger(Box::new(logger)).unwrap();
+            log::set_max_level(LevelFilter::Trace);
+        }
+    }
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, metadata: &LogMetadata) -> bool {
+            self.inner.enabled(metadata)
+        }
+
+        fn log(&self, record: &Record) {
+            CAPTURED_LOGS.lock().unwrap().push(record.args().to_string());
+            self.inner.log(record);
+        }
+
+        fn flush(&self) {
+            self.inner.flush();
+        }
+    }
 
     #[test]
     fn check_max_len() {
         let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
         let test_tmp_dir = TempDir::new("kektest").unwrap();
-        let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
-        let reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
         assert_eq!(writer.metadata(), reader.metadata());
     }
 
+    #[test]
+    #[cfg(not(feature = "no-log"))]
+    fn log_channel_context() {
+        INIT_LOG.call_once(|| {
+            CapturingLogger::install();
+        });
+        let metadata = Metadata::new(4343, 4242, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Logged").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let logs = CAPTURED_LOGS.lock().unwrap();
+        let channel_logs: Vec<&String> = logs
+            .iter()
+            .filter(|msg| msg.starts_with("[channel 4242 writer 4343]"))
+            .collect();
+        assert!(channel_logs.iter().any(|msg| msg.contains("writer created")));
+        assert!(channel_logs.iter().any(|msg| msg.contains("Flushing the channel")));
+        assert!(channel_logs.iter().any(|msg| msg.contains("Channel marked as closed")));
+    }
+
     #[test]
     fn write_than_read() {
         INIT_LOG.call_once(|| {
-            SimpleLogger::new().init().unwrap();
+            CapturingLogger::install();
         });
         let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
         let test_tmp_dir = TempDir::new("kektest").unwrap();
-        let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
         let txt = "There are 10 kinds of people: those who know binary and those who don't";
         let msgs = txt.split_whitespace();
         let mut msg_count = 0;
@@ -304,46 +1044,22 @@ mod test {
         }
         assert_eq!(writer.write_offset(), bytes_written);
         writer.flush().unwrap(); //not really necessary
-        let mut reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
-        assert_eq!(reader.position(), 0);
-        let mut msg_iter = reader.try_iter();
-        let mut res_txt = String::new();
-        for read_res in &mut msg_iter {
-            match read_res {
-                ReadResult::Record(msg) => {
-                    let msg_str = std::str::from_utf8(&msg).unwrap();
-                    if !res_txt.is_empty() {
-                        res_txt.push(' ');
-                    }
-                    res_txt.push_str(msg_str);
-                    msg_count -= 1;
-                }
-                ReadResult::Nothing => {
-                    assert!(msg_count == 0);
-                    break;
-                }
-                ReadResult::Failed(err) => match err {
-                    ReadError::Closed => break,
-                    _ => {
-                        panic!("Unexpected read error {:?}", err);
-                    }
-                },
-            }
-        }
-        assert_eq!(res_txt, txt);
-        assert_eq!(bytes_written, reader.position());
+        let records = collect_channel(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(records.len(), msg_count);
+        let words: Vec<&str> = records.iter().map(|msg| std::str::from_utf8(msg).unwrap()).collect();
+        assert_eq!(words.join(" "), txt);
     }
 
     #[test]
     fn try_iterator_hint_size() {
         INIT_LOG.call_once(|| {
-            SimpleLogger::new().init().unwrap();
+            CapturingLogger::install();
         });
         let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
         let test_tmp_dir = TempDir::new("kektest").unwrap();
         let mut msg_count = 0;
         {
-            let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+            let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
             let txt = "There are 10 kinds of people: those who know binary and those who don't";
             let msgs = txt.split_whitespace();
             for m in msgs {
@@ -354,7 +1070,7 @@ mod test {
                 msg_count += 1;
             }
         }
-        let mut reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
         assert!(reader.exhausted().is_none());
         let mut read_iter = reader.try_iter();
         let sh1 = read_iter.size_hint();
@@ -416,34 +1132,34 @@ mod test {
     #[test]
     fn try_to_create_reader() {
         INIT_LOG.call_once(|| {
-            SimpleLogger::new().init().unwrap();
+            CapturingLogger::install();
         });
         let test_tmp_dir = Arc::new(TempDir::new("kektest").unwrap());
-        let never_reader = try_shm_reader(&test_tmp_dir.path(), 999_999, 300, 30);
+        let never_reader = try_shm_reader(test_tmp_dir.path(), 999_999, 300, 30);
         assert!(never_reader.is_err());
         let channel_id = 999;
         let root_dir = test_tmp_dir.clone();
         let handle = std::thread::spawn(move || {
-            let good_reader = try_shm_reader(&test_tmp_dir.path(), channel_id, 1000, 20);
+            let good_reader = try_shm_reader(test_tmp_dir.path(), channel_id, 1000, 20);
             assert!(good_reader.is_err());
         });
         let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
-        shm_writer(&root_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        shm_writer(root_dir.path(), &metadata, EncoderHandler::default()).unwrap();
         handle.join().unwrap();
     }
     use assert_matches::assert_matches;
     #[test]
     fn read_with_timeout() {
         INIT_LOG.call_once(|| {
-            SimpleLogger::new().init().unwrap();
+            CapturingLogger::install();
         });
         let timeout = 50;
         let metadata = Metadata::new(100, 1000, 10000, 1000, timeout, Millis);
         let test_tmp_dir = TempDir::new("kektest").unwrap();
-        let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
         let txt = "Just a bad day";
         writer.write(&txt.as_bytes()).unwrap();
-        let reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
         let mut timeout_reader = shm_timeout_reader(reader);
         let mut msg_iter = timeout_reader.try_iter();
         assert_matches!(msg_iter.next(), Some(ReadResult::Record(_)));
@@ -454,4 +1170,1811 @@ mod test {
         assert_matches!(msg_iter.next(), None);
         writer.flush().unwrap(); //not really necessary
     }
+
+    #[test]
+    fn coalesced_iter_stops_at_frontier() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for msg in &["one", "two", "three"] {
+            writer.write(&msg.as_bytes()).unwrap();
+        }
+        let mut iter = reader.coalesced_iter();
+        for _i in 0..3 {
+            assert_matches!(iter.next(), Some(ReadResult::Record(_)));
+        }
+        assert_matches!(iter.next(), Some(ReadResult::Nothing));
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_matches!(iter.next(), None);
+        assert_matches!(iter.next(), None);
+        //next round
+        writer.write(&"four".as_bytes()).unwrap();
+        assert_eq!(reader.coalesced_iter().count(), 2);
+        std::mem::drop(writer);
+        let mut iter = reader.coalesced_iter();
+        assert_matches!(iter.next(), Some(ReadResult::Failed(ReadError::Closed)));
+        assert_matches!(iter.next(), None);
+    }
+
+    #[test]
+    fn write_numbers() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&42u64).unwrap();
+        writer.write(&(7u64, 11u64)).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), &42u64.to_le_bytes());
+        let rec = reader.try_read().unwrap().unwrap();
+        assert_eq!(&rec[0..8], &7u64.to_le_bytes());
+        assert_eq!(&rec[8..16], &11u64.to_le_bytes());
+    }
+
+    #[test]
+    fn decode_next_record() {
+        use crate::api::{DecodeError, RawBinDataFormat};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&42u64).unwrap();
+        writer.write(&(7u64, 11u64)).unwrap();
+        writer.write(&1u8).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let num: Option<u64> = reader.decode_next(&RawBinDataFormat).unwrap();
+        assert_eq!(num, Some(42));
+        let pair: Option<(u64, u64)> = reader.decode_next(&RawBinDataFormat).unwrap();
+        assert_eq!(pair, Some((7, 11)));
+        let res: Result<Option<u64>, DecodeError> = reader.decode_next(&RawBinDataFormat);
+        assert_matches!(res, Err(DecodeError::Decoding(_)));
+        assert!(reader.exhausted().is_none());
+        let nothing: Option<u64> = reader.decode_next(&RawBinDataFormat).unwrap();
+        assert!(nothing.is_none());
+        std::mem::drop(writer);
+        let res: Result<Option<u64>, DecodeError> = reader.decode_next(&RawBinDataFormat);
+        assert_matches!(res, Err(DecodeError::Read(ReadError::Closed)));
+    }
+
+    #[test]
+    fn heartbeats_are_skipped() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(writer.heartbeat().unwrap(), REC_HEADER_LEN);
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.position(), REC_HEADER_LEN);
+        writer.heartbeat().unwrap();
+        writer.heartbeat().unwrap();
+        writer.write(&"alive".as_bytes()).unwrap();
+        writer.heartbeat().unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"alive");
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.position(), writer.write_offset());
+    }
+
+    #[test]
+    fn heartbeats_prove_writer_alive() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.heartbeats(), 0);
+        assert_eq!(reader.last_heartbeat_at(), None);
+        //an idle writer sends heartbeats, which are seen but not returned as data
+        let before = Nanos.nix_time();
+        writer.heartbeat().unwrap();
+        writer.heartbeat().unwrap();
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.heartbeats(), 2);
+        let first_seen = reader.last_heartbeat_at().unwrap();
+        assert!(first_seen >= before);
+        writer.write(&"data").unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"data");
+        assert_eq!(reader.heartbeats(), 2);
+        assert_eq!(reader.last_heartbeat_at(), Some(first_seen));
+        writer.heartbeat().unwrap();
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.heartbeats(), 3);
+        assert!(reader.last_heartbeat_at().unwrap() >= first_seen);
+    }
+
+    #[test]
+    fn heartbeats_of_a_backlog_keep_the_writer_time() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"data").unwrap();
+        writer.heartbeat().unwrap();
+        let written = Nanos.nix_time();
+        std::thread::sleep(Duration::from_millis(20));
+        //the reader attaches long after the heartbeat was written
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let attached = Nanos.nix_time();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"data");
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.heartbeats(), 1);
+        let seen = reader.last_heartbeat_at().unwrap();
+        assert!(seen <= written);
+        assert!(seen < attached);
+        assert_eq!(Some(seen), reader.footer().map(|footer| footer.last_write));
+    }
+
+    #[test]
+    fn empty_records_are_rejected() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert_matches!(writer.write(&""), Err(WriteError::EmptyRecord));
+        assert_matches!(writer.write(&Vec::new()), Err(WriteError::EmptyRecord));
+        assert_eq!(writer.write_offset(), 0);
+        writer.write(&"not empty").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"not empty");
+        assert_matches!(reader.try_read(), Ok(None));
+    }
+
+    #[test]
+    fn exhausted_callback() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let calls = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let cb_calls = calls.clone();
+        reader.on_exhausted(Box::new(move |err| cb_calls.borrow_mut().push(err)));
+        writer.write(&"Hello".as_bytes()).unwrap();
+        assert_matches!(reader.try_read(), Ok(Some(_)));
+        assert_matches!(reader.try_read(), Ok(None));
+        assert!(calls.borrow().is_empty());
+        std::mem::drop(writer);
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        assert_eq!(*calls.borrow(), vec![ReadError::Closed]);
+        //late registration
+        let late_calls = calls.clone();
+        reader.on_exhausted(Box::new(move |err| late_calls.borrow_mut().push(err)));
+        assert_eq!(calls.borrow().len(), 2);
+    }
+
+    #[test]
+    fn free_space_detection() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        assert!(free_space(test_tmp_dir.path()).is_some());
+        assert!(free_space(&test_tmp_dir.path().join("no_such_dir")).is_none());
+        assert!(is_no_space(&std::io::Error::from_raw_os_error(libc::ENOSPC)));
+        assert!(!is_no_space(&std::io::Error::from_raw_os_error(libc::EACCES)));
+    }
+
+    #[test]
+    fn not_enough_space_for_channel() {
+        let metadata = Metadata::new(100, 1000, 0xFFFF_0000, 1000, FOREVER, Nanos);
+        let requested = (metadata.capacity() + metadata.len() as u32 + FOOTER_LEN) as u64;
+        assert_matches!(
+            check_space(requested, Some(requested - 1)),
+            Err(ChannelError::InsufficientSpace { requested: r, available: a }) if r == requested && a == requested - 1
+        );
+        assert_matches!(check_space(requested, Some(requested)), Ok(()));
+        //when the free space is unknown, the storage creation itself will tell
+        assert_matches!(check_space(requested, None), Ok(()));
+        let no_space = std::io::Error::from_raw_os_error(libc::ENOSPC);
+        assert_matches!(
+            sizing_error(&no_space, requested, Some(4096)),
+            ChannelError::InsufficientSpace { requested: r, available: 4096 } if r == requested
+        );
+        let denied = std::io::Error::from_raw_os_error(libc::EACCES);
+        assert_matches!(
+            sizing_error(&denied, requested, Some(4096)),
+            ChannelError::CouldNotAccessStorage { .. }
+        );
+    }
+
+    #[test]
+    fn recover_after_corruption() {
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..10 {
+            writer.write(&format!("record-{:02}", i)).unwrap();
+        }
+        //every record takes 24 bytes, overwrite the fourth one with garbage
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(128 + 3 * 24)).unwrap();
+        kek_file.write_all(&[0xAB; 24]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for _ in 0..2 {
+            reader.try_read().unwrap().unwrap();
+        }
+        //the third record is followed by garbage, so it is not trusted
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.position(), 48);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.scan_recover();
+        let mut records = Vec::new();
+        while let Ok(Some(rec)) = reader.try_read() {
+            records.push(String::from_utf8(rec.to_vec()).unwrap());
+        }
+        assert_eq!(records.len(), 9);
+        assert_eq!(records[2], "record-02");
+        assert_eq!(records[3], "record-04");
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], 72..96);
+        assert!(reader.exhausted().is_none());
+    }
+
+    #[test]
+    fn recover_after_zeroed_garbage() {
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..2000 {
+            writer.write(&format!("record-{:04}", i)).unwrap();
+        }
+        //every record takes 24 bytes, all but the first and the last ones are overwritten by an unknown
+        //marker followed by zeros, which must not be mistaken for heartbeats
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        let mut garbage = [0u8; 24];
+        garbage[..8].copy_from_slice(&[0xAB; 8]);
+        kek_file.seek(SeekFrom::Start(128 + 24)).unwrap();
+        for _ in 1..1999 {
+            kek_file.write_all(&garbage).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.scan_recover();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"record-0000");
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"record-1999");
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], 24..1999 * 24);
+        assert!(reader.exhausted().is_none());
+    }
+
+    #[test]
+    fn recover_within_corruption_budget() {
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..10 {
+            writer.write(&format!("record-{:02}", i)).unwrap();
+        }
+        //every record takes 24 bytes, overwrite the fourth and the seventh ones with garbage
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        for corrupted in [3, 6] {
+            kek_file.seek(SeekFrom::Start(128 + corrupted * 24)).unwrap();
+            kek_file.write_all(&[0xAB; 24]).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let read = reader
+            .resilient_iter(48)
+            .take_while(|res| matches!(res, ReadResult::Record(_)))
+            .count();
+        assert_eq!(read, 8);
+        assert_eq!(reader.skipped_bytes(), 48);
+        assert!(reader.exhausted().is_none());
+        //the second corrupted record goes over the budget
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let results: Vec<_> = reader
+            .resilient_iter(30)
+            .map(|res| matches!(res, ReadResult::Record(_)))
+            .collect();
+        assert_eq!(results, [true, true, true, true, true, false]);
+        assert_eq!(reader.skipped_bytes(), 24);
+        assert_eq!(reader.exhausted(), Some(ReadError::Failed));
+    }
+
+    #[test]
+    fn write_if_space_drops_records() {
+        let metadata = Metadata::new(100, 1000, 16 * 1024, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let msg = [7u8; 100];
+        let mut written = 0;
+        while let WriteOutcome::Written(_) = writer.write_if_space(&msg) {
+            written += 1;
+        }
+        assert!(written > 0);
+        assert_matches!(writer.write_if_space(&msg), WriteOutcome::Dropped);
+        let too_large = vec![7u8; writer.metadata().max_msg_len() as usize + 1];
+        assert_matches!(writer.write_if_space(&too_large), WriteOutcome::Dropped);
+        assert_matches!(writer.write_if_space(&""), WriteOutcome::Error(WriteError::EmptyRecord));
+        assert_matches!(writer.write_if_space(&1u64), WriteOutcome::Written(16));
+    }
+
+    #[test]
+    fn estimated_record_capacity_is_accurate() {
+        for payload in [1, 8, 13, 100, 127] {
+            let metadata = Metadata::new(100, 1000, 16 * 1024 + 8, 1000, FOREVER, Nanos);
+            let expected = metadata.estimated_record_capacity(payload);
+            let test_tmp_dir = TempDir::new("kektest").unwrap();
+            let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+            let msg = vec![1u8; payload as usize];
+            let mut written = 0;
+            while writer.write(&msg).is_ok() {
+                written += 1;
+            }
+            assert_eq!(written, expected);
+        }
+    }
+
+    #[test]
+    fn reader_waits_for_ready_writer() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert_matches!(
+            try_shm_reader_ready(test_tmp_dir.path(), 1000, 20, 2).err(),
+            Some(ChannelError::StorageNotReady { .. })
+        );
+        let root_path = test_tmp_dir.path().to_path_buf();
+        let handle = std::thread::spawn(move || {
+            let mut reader = try_shm_reader_ready(&root_path, 1000, 2000, 200).unwrap();
+            assert!(reader.is_ready());
+            reader.try_read().unwrap().unwrap().to_vec()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        writer.write(&"Schema record").unwrap();
+        writer.set_ready();
+        assert_eq!(handle.join().unwrap(), b"Schema record");
+    }
+
+    #[test]
+    fn read_within_deadline() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_timeout_reader(shm_reader(test_tmp_dir.path(), 1000).unwrap());
+        let reply = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            writer.write(&"Late reply").unwrap();
+            writer
+        });
+        let short_deadline = loop {
+            match reader.try_read_within(20) {
+                Ok(None) => std::thread::sleep(std::time::Duration::from_millis(1)),
+                res => break res,
+            }
+        };
+        assert_matches!(short_deadline, Err(Timeout(_)));
+        assert!(reader.exhausted().is_none());
+        let long_deadline = loop {
+            match reader.try_read_within(2000) {
+                Ok(None) => std::thread::sleep(std::time::Duration::from_millis(1)),
+                res => break res,
+            }
+        };
+        assert_eq!(long_deadline.unwrap().unwrap(), b"Late reply");
+        reply.join().unwrap();
+    }
+
+    #[test]
+    fn forward_raw_records() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata_a = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default());
+        let mut writer_a = shm_writer(test_tmp_dir.path(), &metadata_a, handler).unwrap();
+        let metadata_b = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::new(100));
+        let mut writer_b = shm_writer(test_tmp_dir.path(), &metadata_b, handler).unwrap();
+        writer_a.write(&"First").unwrap();
+        writer_a.write(&"Second record").unwrap();
+        let mut reader_a = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut forwarded = Vec::new();
+        while let Some(rec) = reader_a.try_read().unwrap() {
+            assert_eq!(writer_b.write_raw(rec).unwrap(), align(rec.len() as u32 + REC_HEADER_LEN));
+            forwarded.push(rec.to_vec());
+        }
+        assert_eq!(forwarded.len(), 2);
+        let mut reader_b = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        for rec in forwarded {
+            assert_eq!(reader_b.try_read().unwrap().unwrap(), &rec[..]);
+        }
+        assert_matches!(reader_b.try_read(), Ok(None));
+        assert_matches!(writer_b.write_raw(&[]), Err(WriteError::EmptyRecord));
+        let too_large = vec![1u8; writer_b.metadata().max_msg_len() as usize + 1];
+        assert_matches!(writer_b.write_raw(&too_large), Err(WriteError::NoSpaceForRecord));
+    }
+
+    #[test]
+    fn larger_record_header() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos).with_rec_header_len(16);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert_eq!(writer.write(&"Hello").unwrap(), 24);
+        assert_eq!(writer.heartbeat().unwrap(), 16);
+        assert_eq!(writer.write(&12345u64).unwrap(), 24);
+        assert_eq!(writer.write_raw(b"Raw record").unwrap(), 32);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.metadata().rec_header_len(), 16);
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Hello");
+        assert_eq!(reader.try_read().unwrap().unwrap(), &12345u64.to_le_bytes());
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Raw record");
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.position(), writer.write_offset());
+        let report = reader.verify_integrity().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.records, 3);
+        assert_eq!(report.heartbeats, 1);
+        assert_eq!(metadata.estimated_record_capacity(8), metadata.capacity() / 24);
+    }
+
+    #[test]
+    fn truncated_storage() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Before truncation").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.guard_backing();
+        assert!(reader.validate_backing());
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Before truncation");
+        let kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        let len = kek_file.metadata().unwrap().len();
+        kek_file.set_len(0).unwrap();
+        assert!(!reader.validate_backing());
+        assert_matches!(reader.try_read(), Err(ReadError::StorageVanished));
+        assert_eq!(reader.exhausted(), Some(ReadError::StorageVanished));
+        //restore the storage, so the writer could close the channel
+        kek_file.set_len(len).unwrap();
+    }
+
+    #[test]
+    fn time_until_timeout() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, 1_000, Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Record").unwrap();
+        let fresh = writer.ticks_until_timeout();
+        assert!((900..=1_000).contains(&fresh));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let later = writer.ticks_until_timeout();
+        assert!(later <= fresh - 50);
+        writer.heartbeat().unwrap();
+        assert!(writer.ticks_until_timeout() > later);
+        let metadata = Metadata::new(100, 2000, 10000, 1000, 20, Millis);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Record").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(40));
+        assert_eq!(writer.ticks_until_timeout(), 0);
+    }
+
+    #[test]
+    fn misaligned_buffer() {
+        use std::io::Write;
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Aligned").unwrap();
+        drop(writer);
+        let channel = std::fs::read(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        for shift in 1..=8usize {
+            let shifted_path = test_tmp_dir.path().join(format!("shifted_{}", shift));
+            let mut shifted = std::fs::File::create(&shifted_path).unwrap();
+            shifted.write_all(&vec![0u8; shift]).unwrap();
+            shifted.write_all(&channel).unwrap();
+            drop(shifted);
+            let kek_file = OpenOptions::new().read(true).write(true).open(&shifted_path).unwrap();
+            let map = || unsafe { MmapOptions::new().offset(shift as u64).map_mut(&kek_file) }.unwrap();
+            if shift % 8 == 0 {
+                let mut reader = ShmReader::new(map()).unwrap();
+                assert_eq!(reader.try_read().unwrap().unwrap(), b"Aligned");
+            } else {
+                assert_matches!(ShmReader::new(map()), Err(MisalignedBuffer { .. }));
+                assert!(matches!(
+                    ShmWriter::new(map(), EncoderHandler::default()),
+                    Err(MisalignedBuffer { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn collect_channel_records() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let txt = "There are 10 kinds of people: those who know binary and those who don't";
+        let expected: Vec<Vec<u8>> = txt.split_whitespace().map(|word| word.as_bytes().to_vec()).collect();
+        for (idx, word) in expected.iter().enumerate() {
+            writer.write(word).unwrap();
+            if idx % 3 == 0 {
+                writer.heartbeat().unwrap();
+            }
+        }
+        assert_eq!(collect_channel(test_tmp_dir.path(), 1000).unwrap(), expected);
+        drop(writer);
+        assert_eq!(collect_channel(test_tmp_dir.path(), 1000).unwrap(), expected);
+        assert_eq!(collect_channel(test_tmp_dir.path(), 2000), Err(ReadError::StorageVanished));
+    }
+
+    #[test]
+    fn snapshot_reader() {
+        let metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..100u64 {
+            writer.write(&i).unwrap();
+        }
+        let mut snapshot = shm_reader_cow(test_tmp_dir.path(), 1000).unwrap();
+        for i in 100..200u64 {
+            writer.write(&i).unwrap();
+        }
+        drop(writer);
+        for i in 0..100u64 {
+            assert_eq!(snapshot.try_read().unwrap().unwrap(), &i.to_le_bytes());
+        }
+        assert_matches!(snapshot.try_read(), Ok(None));
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let read = reader
+            .try_iter()
+            .take_while(|res| matches!(res, ReadResult::Record(_)))
+            .count();
+        assert_eq!(read, 200);
+        assert_eq!(reader.exhausted(), Some(ReadError::Closed));
+        assert!(shm_reader_cow(test_tmp_dir.path(), 2000).is_err());
+    }
+
+    #[test]
+    fn channel_age() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let _writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let age = reader.metadata().age();
+        assert!(age >= std::time::Duration::from_millis(5));
+        assert!(age < std::time::Duration::from_secs(60));
+        assert!(reader.metadata().creation_system_time() <= std::time::SystemTime::now());
+        assert_eq!(reader.metadata().creation_system_time(), metadata.creation_system_time());
+    }
+
+    #[test]
+    fn write_up_to_capacity() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let max_msg_len = writer.metadata().max_msg_len();
+        let mut records = 0;
+        while writer.available() > max_msg_len + REC_HEADER_LEN {
+            writer.write_raw(&[1u8; 64]).unwrap();
+            records += 1;
+        }
+        let last = vec![2u8; (writer.available() - REC_HEADER_LEN) as usize];
+        writer.write_raw(&last).unwrap();
+        assert_eq!(writer.write_offset(), writer.metadata().capacity());
+        assert_eq!(writer.available(), 0);
+        assert_matches!(writer.write(&1u8), Err(WriteError::ChannelFull));
+        assert_matches!(writer.write_raw(&[1u8]), Err(WriteError::ChannelFull));
+        assert_matches!(writer.write_chunked(&[1u8]), Err(WriteError::ChannelFull));
+        assert_matches!(writer.heartbeat(), Err(WriteError::ChannelFull));
+        assert_eq!(writer.write_offset(), writer.metadata().capacity());
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for _ in 0..records {
+            assert_eq!(reader.try_read().unwrap().unwrap(), &[1u8; 64]);
+        }
+        assert_eq!(reader.try_read().unwrap().unwrap(), &last[..]);
+        assert_matches!(reader.try_read(), Ok(None));
+    }
+
+    #[test]
+    fn positioned_records() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..10usize {
+            writer.write(&"x".repeat(i * 7 + 1)).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut expected = Vec::new();
+        loop {
+            let position = reader.position();
+            match reader.try_read().unwrap() {
+                Some(record) => expected.push((position, record.to_vec())),
+                None => break,
+            }
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut positioned = Vec::new();
+        for (offset, res) in reader.try_iter_positioned() {
+            match res {
+                ReadResult::Record(record) => positioned.push((offset, record.to_vec())),
+                _ => {
+                    assert_eq!(offset, writer.write_offset());
+                    break;
+                }
+            }
+        }
+        assert_eq!(positioned.len(), 10);
+        assert_eq!(positioned, expected);
+        //a heartbeat is not part of the record
+        writer.heartbeat().unwrap();
+        writer.write(&"After").unwrap();
+        let (offset, res) = reader.try_iter_positioned().next().unwrap();
+        assert_matches!(res, ReadResult::Record(b"After"));
+        assert_eq!(offset, reader.position() - align(REC_HEADER_LEN + 5));
+        assert_eq!(
+            offset,
+            expected
+                .last()
+                .map(|(pos, rec)| pos + align(REC_HEADER_LEN + rec.len() as u32))
+                .unwrap()
+                + REC_HEADER_LEN
+        );
+    }
+
+    #[test]
+    fn peek_and_commit() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"First").unwrap();
+        writer.heartbeat().unwrap();
+        writer.write(&"Second").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert!(!reader.commit());
+        assert_eq!(reader.peek().unwrap().unwrap(), b"First");
+        assert_eq!(reader.position(), 0);
+        assert!(reader.commit());
+        assert!(!reader.commit());
+        let committed = reader.position();
+        assert_eq!(reader.peek().unwrap().unwrap(), b"Second");
+        //crash before the commit
+        drop(reader);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.seek(committed);
+        assert_eq!(reader.peek().unwrap().unwrap(), b"Second");
+        //a plain read discards the peeked record
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Second");
+        assert!(!reader.commit());
+        assert_matches!(reader.peek(), Ok(None));
+        assert!(!reader.commit());
+        assert_eq!(reader.position(), writer.write_offset());
+    }
+
+    #[test]
+    fn repeated_peek_counts_once() {
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.heartbeat().unwrap();
+        writer.heartbeat().unwrap();
+        writer.write(&"record-00").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for _ in 0..3 {
+            assert_eq!(reader.peek().unwrap().unwrap(), b"record-00");
+        }
+        assert_eq!(reader.heartbeats(), 2);
+        assert!(reader.commit());
+        assert_matches!(reader.peek(), Ok(None));
+        assert_eq!(reader.heartbeats(), 2);
+        //the second record takes 24 bytes after two heartbeats and the first record, overwrite it with garbage
+        writer.write(&"record-01").unwrap();
+        writer.write(&"record-02").unwrap();
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(128 + 16 + 24)).unwrap();
+        kek_file.write_all(&[0xAB; 24]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.resilient_iter(24).next(), Some(ReadResult::Record(b"record-00")));
+        for _ in 0..3 {
+            assert_eq!(reader.peek().unwrap().unwrap(), b"record-02");
+        }
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], 40..64);
+        assert!(reader.commit());
+        assert_matches!(reader.try_read(), Ok(None));
+        assert!(reader.exhausted().is_none());
+    }
+
+    #[test]
+    fn clone_unlinked_reader() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"First").unwrap();
+        writer.write(&"Second").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"First");
+        remove_file(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        assert!(shm_reader(test_tmp_dir.path(), 1000).is_err());
+        let mut clone = reader.try_clone().unwrap();
+        assert_eq!(clone.position(), 0);
+        assert_eq!(clone.metadata(), reader.metadata());
+        assert_eq!(clone.try_read().unwrap().unwrap(), b"First");
+        assert_eq!(clone.try_read().unwrap().unwrap(), b"Second");
+        writer.write(&"Third").unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Second");
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Third");
+        assert_eq!(clone.try_read().unwrap().unwrap(), b"Third");
+        drop(reader);
+        drop(writer);
+        assert_matches!(clone.try_read(), Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn single_writer() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let root = Arc::new(test_tmp_dir.path().to_path_buf());
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|writer_id| {
+                let root = root.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let metadata = Metadata::new(writer_id, 1000, 10000, 1000, FOREVER, Nanos);
+                    barrier.wait();
+                    match shm_writer(&root, &metadata, EncoderHandler::default()) {
+                        Ok(mut writer) => {
+                            writer.write(&writer_id).unwrap();
+                            Some(writer_id)
+                        }
+                        Err(StorageAlreadyExists { .. }) | Err(AlreadyLocked { .. }) => None,
+                        Err(err) => panic!("Unexpected error {:?}", err),
+                    }
+                })
+            })
+            .collect();
+        let winners: Vec<u64> = handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(winners.len(), 1);
+        let mut reader = shm_reader(&root, 1000).unwrap();
+        assert_eq!(reader.metadata().writer_id(), winners[0]);
+        assert_eq!(reader.try_read().unwrap().unwrap(), &winners[0].to_le_bytes());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writer_holds_storage_lock() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let kek_file = OpenOptions::new()
+            .read(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        assert!(lock_file(&kek_file).is_err());
+        let writer = writer.with_handler(EncoderHandler::default());
+        assert!(lock_file(&kek_file).is_err());
+        drop(writer);
+        assert!(lock_file(&kek_file).is_ok());
+    }
+
+    #[test]
+    fn bounded_scans() {
+        use std::fs::OpenOptions;
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&1u64).unwrap();
+        writer.write(&2u64).unwrap();
+        drop(writer);
+        //wipe the CLOSE marker, so the rest of the channel looks like an endless run of heartbeats
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(128 + 32)).unwrap();
+        kek_file.write_all(&[0u8; 8]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.scan_bound(), ScanBound::default());
+        reader.try_read().unwrap().unwrap();
+        reader.try_read().unwrap().unwrap();
+        assert_matches!(reader.try_read(), Err(ReadError::Failed));
+        let bound = ScanBound {
+            max_records: 100,
+            max_bytes: MAX_SCAN_BYTES,
+        };
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.set_scan_bound(bound);
+        reader.try_read().unwrap().unwrap();
+        reader.try_read().unwrap().unwrap();
+        assert_matches!(reader.try_read(), Err(ReadError::Failed));
+        assert!(reader.position() <= 32 + 101 * 8);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.set_scan_bound(bound);
+        let report = reader.verify_integrity().unwrap();
+        assert_matches!(report.anomalies[..], [Anomaly::ScanLimitExceeded { .. }, ..]);
+        let mut replay_reader = ReplayReader::with_records(reader, 1);
+        assert_matches!(replay_reader.try_read(), Err(ReadError::Failed));
+        //garbage which never looks like a record boundary
+        kek_file.seek(SeekFrom::Start(128 + 32)).unwrap();
+        kek_file.write_all(&[0xAB; 512]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.scan_recover();
+        reader.set_scan_bound(ScanBound {
+            max_records: MAX_SCAN_RECORDS,
+            max_bytes: 256,
+        });
+        reader.try_read().unwrap().unwrap();
+        reader.try_read().unwrap().unwrap();
+        assert_matches!(reader.try_read(), Err(ReadError::Failed));
+        assert!(reader.skipped().is_empty());
+    }
+
+    #[test]
+    fn reader_for_app() {
+        let app_a = 0xA;
+        let app_b = 0xB;
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos).with_app_id(app_a);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"For app A").unwrap();
+        assert_matches!(
+            shm_reader_for_app(test_tmp_dir.path(), 1000, app_b),
+            Err(ChannelError::WrongApplication { expected, actual }) if expected == app_b && actual == app_a
+        );
+        let mut reader = shm_reader_for_app(test_tmp_dir.path(), 1000, app_a).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"For app A");
+        //readers which do not opt in accept any channel
+        assert!(shm_reader(test_tmp_dir.path(), 1000).is_ok());
+        let metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let _writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert_matches!(
+            shm_reader_for_app(test_tmp_dir.path(), 2000, app_a),
+            Err(ChannelError::WrongApplication { actual: 0, .. })
+        );
+    }
+
+    #[test]
+    fn read_until_sequence() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default());
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        for i in 1..=8u64 {
+            writer.write(&(i * 10)).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut skipped = Vec::new();
+        let record = reader
+            .read_until_sequence(5, SequenceHandler::sequence_of, |rec| {
+                skipped.push(SequenceHandler::sequence_of(rec).unwrap())
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(skipped, vec![1, 2, 3, 4]);
+        assert_eq!(SequenceHandler::sequence_of(record), Some(5));
+        assert_eq!(&record[8..], &50u64.to_le_bytes());
+        let mut skipped = 0;
+        assert_matches!(
+            reader.read_until_sequence(20, SequenceHandler::sequence_of, |_| skipped += 1),
+            Ok(None)
+        );
+        assert_eq!(skipped, 3);
+        drop(writer);
+        assert_matches!(
+            reader.read_until_sequence(20, SequenceHandler::sequence_of, |_| ()),
+            Err(ReadError::Closed)
+        );
+    }
+
+    #[test]
+    fn version_changed() {
+        use std::fs::OpenOptions;
+        use std::io::{Seek, SeekFrom, Write};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Before upgrade").unwrap();
+        writer.write(&"After upgrade").unwrap();
+        let mut guarded = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        guarded.guard_version();
+        let mut unguarded = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(guarded.try_read().unwrap().unwrap(), b"Before upgrade");
+        assert_eq!(unguarded.try_read().unwrap().unwrap(), b"Before upgrade");
+        assert!(guarded.validate_version());
+        //a writer with a newer minor version takes over the channel
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(8)).unwrap();
+        kek_file.write_all(&((1u64 << 48) | (1u64 << 32)).to_le_bytes()).unwrap();
+        assert!(!guarded.validate_version());
+        assert_matches!(guarded.try_read(), Err(ReadError::VersionChanged));
+        assert_eq!(guarded.exhausted(), Some(ReadError::VersionChanged));
+        assert!(!unguarded.validate_version());
+        assert_eq!(unguarded.try_read().unwrap().unwrap(), b"After upgrade");
+    }
+
+    #[test]
+    fn drain_available_records() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.drain(|_| panic!("No record expected")).unwrap(), 0);
+        for i in 0..5u64 {
+            writer.write(&i).unwrap();
+        }
+        writer.heartbeat().unwrap();
+        let mut sum = 0;
+        assert_eq!(reader.drain(|rec| sum += rec[0] as u64).unwrap(), 5);
+        assert_eq!(sum, 10);
+        writer.write(&5u64).unwrap();
+        assert_eq!(reader.drain(|rec| assert_eq!(rec[0], 5)).unwrap(), 1);
+        drop(writer);
+        assert_matches!(reader.drain(|_| ()), Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn consecutive_reassembled_payloads() {
+        let metadata = Metadata::new(100, 1000, 16 * 1024, 100, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let max_msg_len = metadata.max_msg_len() as usize;
+        writer.write_chunked(&vec![b'a'; 2 * max_msg_len]).unwrap();
+        writer.write_chunked(&vec![b'b'; 4 * max_msg_len]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let first = reader.read_reassembled().unwrap().unwrap().to_vec();
+        let second = reader.read_reassembled().unwrap().unwrap().to_vec();
+        assert_eq!(first, vec![b'a'; 2 * max_msg_len]);
+        assert_eq!(second, vec![b'b'; 4 * max_msg_len]);
+    }
+
+    #[test]
+    fn chunked_records() {
+        let metadata = Metadata::new(100, 1000, 16 * 1024, 100, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let max_msg_len = metadata.max_msg_len() as usize;
+        let blob: Vec<u8> = (0..3 * max_msg_len).map(|i| i as u8).collect();
+        writer.write(&"Before").unwrap();
+        let chunked_len = writer.write_chunked(&blob).unwrap();
+        assert_eq!(chunked_len, 3 * metadata.bytes_per_record(max_msg_len as u32));
+        writer.write(&"After").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.read_reassembled().unwrap().unwrap(), b"Before");
+        assert_eq!(reader.read_reassembled().unwrap().unwrap(), &blob[..]);
+        assert_eq!(reader.read_reassembled().unwrap().unwrap(), b"After");
+        assert_matches!(reader.read_reassembled(), Ok(None));
+        //plain readers see every chunk as a record
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.drain(|_| ()).unwrap(), 5);
+        assert!(reader.verify_integrity().unwrap().is_ok());
+        //either all the chunks are written or none of them
+        let offset = writer.write_offset();
+        let too_large = vec![1u8; writer.available() as usize];
+        assert_matches!(writer.write_chunked(&too_large), Err(WriteError::NoSpaceForRecord));
+        assert_eq!(writer.write_offset(), offset);
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_matches!(writer.write_chunked(&[]), Err(WriteError::EmptyRecord));
+    }
+
+    #[test]
+    fn channel_for_messages() {
+        let metadata = Metadata::for_messages(100, 1000, 200, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let msg = vec![7u8; metadata.max_msg_len() as usize];
+        for _ in 0..1000 {
+            writer.write(&msg).unwrap();
+        }
+        assert_matches!(writer.write(&msg), Err(WriteError::ChannelFull));
+        assert_eq!(writer.write_offset(), metadata.capacity());
+    }
+
+    #[test]
+    fn partition_decoded_records() {
+        use crate::api::{DecodeError, RawBinDataFormat};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&(1u32, "one")).unwrap();
+        writer.write(&(2u32, [0xFFu8, 0xFE])).unwrap();
+        writer.write(&(3u32, "three")).unwrap();
+        writer.write(&[4u8, 0]).unwrap();
+        writer.write(&(5u32, "five")).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let (decoded, failures) = reader
+            .try_iter()
+            .decoded::<_, (u32, String)>(RawBinDataFormat)
+            .partition_results();
+        assert_eq!(
+            decoded,
+            vec![(1, "one".to_string()), (3, "three".to_string()), (5, "five".to_string())]
+        );
+        assert_eq!(failures.len(), 2);
+        assert!(failures.iter().all(|failure| matches!(failure, DecodeError::Decoding(_))));
+        assert!(reader.exhausted().is_none());
+        writer.write(&(6u32, "six")).unwrap();
+        drop(writer);
+        let (decoded, failures) = reader
+            .try_iter()
+            .decoded::<_, (u32, String)>(RawBinDataFormat)
+            .partition_results();
+        assert_eq!(decoded, vec![(6, "six".to_string())]);
+        assert!(failures.is_empty());
+        assert_eq!(reader.exhausted(), Some(ReadError::Closed));
+    }
+
+    #[test]
+    fn locked_until_released() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let (mut writer, lock) = shm_writer_locked(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Schema").unwrap();
+        assert_matches!(
+            shm_reader(test_tmp_dir.path(), 1000),
+            Err(ChannelError::StorageNotReady { .. })
+        );
+        assert_matches!(
+            try_shm_reader(test_tmp_dir.path(), 1000, 20, 2),
+            Err(ChannelError::StorageNotReady { .. })
+        );
+        lock.release();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Schema");
+        //dropping the guard releases the lock as well
+        let metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let (_writer, lock) = shm_writer_locked(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert!(shm_reader(test_tmp_dir.path(), 2000).is_err());
+        assert!(matches!(
+            shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists {
+                existing_state: ChannelState::Initializing,
+                ..
+            })
+        ));
+        drop(lock);
+        assert!(shm_reader(test_tmp_dir.path(), 2000).is_ok());
+    }
+
+    #[test]
+    fn stale_lock_is_taken_over() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        //a writer which crashed before it created the storage left its lock file behind
+        ensure_channel_dir(test_tmp_dir.path(), 1000).unwrap();
+        let kek_lock_path = storage_path(test_tmp_dir.path(), 1000).with_extension("lock");
+        std::fs::File::create(&kek_lock_path).unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        assert!(!kek_lock_path.exists());
+        writer.write(&"Hello").unwrap();
+        //a writer which crashed before it released the lock of its channel
+        std::fs::File::create(&kek_lock_path).unwrap();
+        assert_eq!(
+            shm_reader(test_tmp_dir.path(), 1000).unwrap().try_read().unwrap().unwrap(),
+            b"Hello"
+        );
+        assert!(matches!(
+            shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists {
+                existing_state: ChannelState::Live,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn swap_handler() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"First").unwrap();
+        writer.write(&"Second").unwrap();
+        let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default());
+        let mut writer = writer.with_handler(handler);
+        writer.write(&"Third").unwrap();
+        writer.set_handler(ChainedHandler::link(EncoderHandler::default(), SequenceHandler::new(100)));
+        writer.write(&"Fourth").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"First");
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Second");
+        let third = reader.try_read().unwrap().unwrap();
+        assert_eq!(SequenceHandler::sequence_of(third), Some(1));
+        assert_eq!(&third[8..], b"Third");
+        let fourth = reader.try_read().unwrap().unwrap();
+        assert_eq!(SequenceHandler::sequence_of(fourth), Some(101));
+        assert_eq!(&fourth[8..], b"Fourth");
+        assert_matches!(reader.try_read(), Ok(None));
+        drop(writer);
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn text_records() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let txt = "There are 10 kinds of people: those who know binary and those who don't";
+        for word in txt.split_whitespace() {
+            writer.write(&word).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let words: Vec<&str> = reader.text_iter().map(|word| word.unwrap()).collect();
+        assert_eq!(words, txt.split_whitespace().collect::<Vec<&str>>());
+        assert!(reader.text_iter().next().is_none());
+        writer.write(&vec![0xF0u8, 0x28, 0x8C, 0x28]).unwrap();
+        writer.write(&"valid").unwrap();
+        let mut texts = reader.text_iter();
+        assert!(texts.next().unwrap().is_err());
+        assert_eq!(texts.next().unwrap().unwrap(), "valid");
+        assert!(texts.next().is_none());
+        drop(writer);
+        assert!(reader.text_iter().next().is_none());
+        assert_matches!(reader.exhausted(), Some(ReadError::Closed));
+    }
+
+    #[test]
+    fn atomic_batches() {
+        let metadata = Metadata::new(100, 1000, 1024 * 1024, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        const BATCHES: u64 = 2000;
+        let handle = std::thread::spawn(move || {
+            for batch in 0..BATCHES {
+                let size = batch % 7 + 1;
+                let items: Vec<u64> = (0..size).map(|idx| batch << 16 | idx << 8 | size).collect();
+                writer.write_batch(&items).unwrap();
+            }
+        });
+        let (mut batch, mut idx) = (0, 0);
+        loop {
+            match reader.try_read() {
+                Ok(Some(record)) => {
+                    let item = read_u64_at(record, 0).unwrap();
+                    let size = item & 0xFF;
+                    assert_eq!((item >> 16, (item >> 8) & 0xFF), (batch, idx));
+                    idx += 1;
+                    assert_eq!(reader.batch_continues(), idx < size);
+                    if idx == size {
+                        batch += 1;
+                        idx = 0;
+                    }
+                }
+                //the reader could run out of records only between two batches
+                Ok(None) => assert_eq!(idx, 0),
+                Err(err) => {
+                    assert_eq!(err, ReadError::Closed);
+                    break;
+                }
+            }
+        }
+        handle.join().unwrap();
+        assert_eq!((batch, idx), (BATCHES, 0));
+    }
+
+    #[test]
+    fn raw_records() {
+        let metadata = Metadata::new(100, 1000, 16 * 1024, 100, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let max_msg_len = metadata.max_msg_len() as usize;
+        let blob: Vec<u8> = (0..max_msg_len * 2 + 10).map(|i| i as u8).collect();
+        writer.write_chunked(&blob).unwrap();
+        writer.heartbeat().unwrap();
+        writer.write(&7u64).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let raw: Vec<RawRecord> = reader.raw_iter().collect();
+        assert_eq!(raw.len(), 4);
+        //every chunk but the last one carries the continuation flag
+        assert_eq!(raw[0].length_word, max_msg_len as u64 | CONTINUED);
+        assert_eq!(raw[1].length_word, max_msg_len as u64 | CONTINUED);
+        assert_eq!(raw[2].length_word, 10);
+        assert_eq!(raw[3].length_word, 8);
+        let chunk_size = align(max_msg_len as u32 + REC_HEADER_LEN);
+        let offsets: Vec<u32> = raw.iter().map(|record| record.offset).collect();
+        assert_eq!(
+            offsets,
+            vec![0, chunk_size, 2 * chunk_size, 2 * chunk_size + 24 + REC_HEADER_LEN]
+        );
+        let body: Vec<u8> = raw[..3].iter().flat_map(|record| record.body.iter().copied()).collect();
+        assert_eq!(body, blob);
+        assert_eq!(raw[3].body, &7u64.to_le_bytes());
+        assert!(reader.raw_iter().next().is_none());
+    }
+
+    #[test]
+    fn timed_records() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..20u64 {
+            writer.write(&i).unwrap();
+        }
+        let before = std::time::SystemTime::now();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let timed: Vec<_> = reader.timed_read_iter().collect();
+        assert_eq!(timed.len(), 20);
+        assert!(timed[0].0 >= before);
+        assert!(timed.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        for (i, (_, record)) in timed.iter().enumerate() {
+            assert_eq!(read_u64_at(record, 0), Some(i as u64));
+        }
+        assert!(reader.timed_read_iter().next().is_none());
+    }
+
+    #[test]
+    fn channel_at_custom_path() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let kek_file_path = test_tmp_dir.path().join("mnt").join("orders.dat");
+        assert_matches!(shm_reader_at(&kek_file_path), Err(StorageNotFound { .. }));
+        let mut writer = shm_writer_at(&kek_file_path, &metadata, EncoderHandler::default()).unwrap();
+        assert!(kek_file_path.exists());
+        assert!(!kek_file_path.with_extension("lock").exists());
+        assert!(!storage_path(test_tmp_dir.path(), 1000).exists());
+        assert!(matches!(
+            shm_writer_at(&kek_file_path, &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists { .. })
+        ));
+        writer.write(&"Hello").unwrap();
+        writer.write(&"World").unwrap();
+        let mut reader = shm_reader_at(&kek_file_path).unwrap();
+        assert_eq!(reader.metadata().channel_id(), 1000);
+        assert_eq!(reader.metadata().writer_id(), 100);
+        let words: Vec<&str> = reader.text_iter().map(|word| word.unwrap()).collect();
+        assert_eq!(words, vec!["Hello", "World"]);
+    }
+
+    #[test]
+    fn spin_until_channel_is_created() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let connect_time = |channel_id: u64, wait: PollWait| {
+            let root_path = test_tmp_dir.path().to_path_buf();
+            let barrier = Arc::new(Barrier::new(2));
+            let writer_barrier = barrier.clone();
+            let handle = std::thread::spawn(move || {
+                let metadata = Metadata::new(100, channel_id, 10000, 1000, FOREVER, Nanos);
+                writer_barrier.wait();
+                std::thread::sleep(Duration::from_micros(5));
+                shm_writer(&root_path, &metadata, EncoderHandler::default()).unwrap()
+            });
+            let policy = RetryPolicy::default()
+                .duration(Duration::from_secs(5))
+                .max_attempts(u64::MAX)
+                .wait(wait);
+            barrier.wait();
+            let start = Instant::now();
+            let reader = try_shm_reader_with(test_tmp_dir.path(), channel_id, policy).unwrap();
+            let elapsed = start.elapsed();
+            assert_eq!(reader.metadata().channel_id(), channel_id);
+            drop(handle.join().unwrap());
+            elapsed
+        };
+        let sleeping = connect_time(1000, PollWait::Sleep(Duration::from_millis(50)));
+        let spinning = connect_time(2000, PollWait::Spin);
+        assert!(spinning < sleeping);
+        let policy = RetryPolicy::default()
+            .duration(Duration::from_secs(60))
+            .max_attempts(3)
+            .wait(PollWait::Yield);
+        let mut attempts = 0;
+        assert_matches!(
+            try_shm_reader_with(test_tmp_dir.path(), 3000, policy),
+            Err(StorageNotFound { .. })
+        );
+        assert!(policy
+            .retry(|| -> Result<(), ()> {
+                attempts += 1;
+                Err(())
+            })
+            .is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn expected_writer_and_channel() {
+        let (writer_a, writer_b) = (100, 200);
+        let metadata = Metadata::new(writer_a, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert!(reader.expect_writer(writer_a).is_ok());
+        assert!(reader.expect_channel(1000).is_ok());
+        assert_matches!(
+            reader.expect_writer(writer_b),
+            Err(ChannelError::WrongWriter { expected, actual }) if expected == writer_b && actual == writer_a
+        );
+        assert_matches!(
+            reader.expect_channel(2000),
+            Err(ChannelError::WrongChannel {
+                expected: 2000,
+                actual: 1000
+            })
+        );
+        //the channel is recreated by another writer
+        drop(writer);
+        drop(reader);
+        remove_file(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        let metadata = Metadata::new(writer_b, 1000, 10000, 1000, FOREVER, Nanos);
+        let _writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.expect_writer(writer_a), Err(ChannelError::WrongWriter { .. }));
+    }
+
+    #[test]
+    fn recreate_existing_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Hello").unwrap();
+        assert!(matches!(
+            shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists {
+                existing_state: ChannelState::Live,
+                ..
+            })
+        ));
+        drop(writer);
+        assert!(matches!(
+            shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists {
+                existing_state: ChannelState::Closed,
+                ..
+            })
+        ));
+        //a copy of a live channel has neither a writer nor a close marker
+        let copied_metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &copied_metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Hello").unwrap();
+        let abandoned_path = test_tmp_dir.path().join("abandoned.kekbit");
+        std::fs::copy(storage_path(test_tmp_dir.path(), 2000), &abandoned_path).unwrap();
+        assert!(matches!(
+            shm_writer_at(&abandoned_path, &metadata, EncoderHandler::default()),
+            Err(StorageAlreadyExists {
+                existing_state: ChannelState::Abandoned,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "no-log")]
+    fn logging_compiled_out() {
+        INIT_LOG.call_once(|| {
+            CapturingLogger::install();
+        });
+        let metadata = Metadata::new(4343, 4242, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Not logged").unwrap();
+        writer.flush().unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 4242).unwrap();
+        assert_matches!(reader.try_read(), Ok(Some(b"Not logged")));
+        drop(writer);
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        //only kekbit logging is compiled out, the application still logs
+        log::info!("Application log");
+        assert_eq!(*CAPTURED_LOGS.lock().unwrap(), vec!["Application log".to_string()]);
+    }
+
+    #[test]
+    fn writer_died_while_publishing() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Published").unwrap();
+        writer.write(&"Half published").unwrap();
+        //the writer dies without closing the channel
+        std::mem::forget(writer);
+        //it got killed after the length of the last record was published, but before the marker which follows it
+        let kek_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        let mut mmap = unsafe { MmapOptions::new().map_mut(&kek_file) }.unwrap();
+        let watermark = utils::WATERMARK.to_le_bytes();
+        let marker_pos = (0..mmap.len() - 8)
+            .step_by(8)
+            .find(|pos| mmap[*pos..*pos + 8] == watermark)
+            .unwrap();
+        mmap[marker_pos..marker_pos + 8].copy_from_slice(&0xDEAD_BEEF_DEAD_BEEFu64.to_le_bytes());
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.try_read(), Ok(Some(b"Published")));
+        let position = reader.position();
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_matches!(reader.try_read(), Ok(None));
+        assert_eq!(reader.position(), position);
+        assert!(reader.exhausted().is_none());
+    }
+
+    #[test]
+    fn final_record_closes_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"First").unwrap();
+        writer.write(&"Second").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.try_read(), Ok(Some(b"First")));
+        assert!(writer.write_final(&"Continued in 1001").unwrap() > 0);
+        assert_eq!(writer.available(), 0);
+        assert_matches!(writer.write(&"Too late"), Err(WriteError::ChannelFull));
+        assert_matches!(writer.heartbeat(), Err(WriteError::ChannelFull));
+        assert_matches!(reader.try_read(), Ok(Some(b"Second")));
+        assert_matches!(reader.try_read(), Ok(Some(b"Continued in 1001")));
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        //the writer is still alive, yet the channel is already closed
+        assert_matches!(reader.exhausted(), Some(ReadError::Closed));
+        drop(writer);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(
+            reader.try_iter().filter(|res| matches!(res, ReadResult::Record(_))).count(),
+            3
+        );
+        assert_matches!(reader.exhausted(), Some(ReadError::Closed));
+    }
+
+    #[test]
+    fn larger_metadata() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos).with_len(256);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Skip the header").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.metadata().len(), 256);
+        assert_matches!(reader.try_read(), Ok(Some(b"Skip the header")));
+        //the first record starts right after the metadata
+        let kek_file = OpenOptions::new()
+            .read(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        let mmap = unsafe { MmapOptions::new().map(&kek_file) }.unwrap();
+        assert_eq!(mmap.len(), 256 + metadata.capacity() as usize + FOOTER_LEN as usize);
+        assert_eq!(&mmap[256..264], &15u64.to_le_bytes());
+        assert_eq!(&mmap[264..279], b"Skip the header");
+    }
+
+    #[test]
+    fn control_word() {
+        const PAUSE: u64 = 1;
+        const RESUME: u64 = 2;
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.control_word(), (0, 0));
+        writer.write(&"Before pause").unwrap();
+        assert_eq!(writer.set_control_word(PAUSE), 1);
+        assert_eq!(reader.control_word(), (PAUSE, 1));
+        //the control word does not show up in the records
+        assert_matches!(reader.try_read(), Ok(Some(b"Before pause")));
+        assert_matches!(reader.try_read(), Ok(None));
+        let handle = std::thread::spawn(move || {
+            for _ in 0..1000 {
+                writer.set_control_word(PAUSE);
+            }
+            writer.set_control_word(RESUME)
+        });
+        let mut last_generation = 1;
+        loop {
+            let (control, generation) = reader.control_word();
+            assert!(generation >= last_generation);
+            last_generation = generation;
+            if control == RESUME {
+                break;
+            }
+            assert_eq!(control, PAUSE);
+        }
+        assert_eq!(handle.join().unwrap(), 1002);
+        assert_eq!(reader.control_word(), (RESUME, 1002));
+    }
+
+    #[test]
+    fn concurrent_channel_creation() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let root = test_tmp_dir.path().to_path_buf();
+        //all the channels share the same folder, which does not exist yet
+        let handles: Vec<_> = (0..16u64)
+            .map(|i| {
+                let root = root.clone();
+                std::thread::spawn(move || {
+                    let channel_id = (0xCAFE << 32) | i;
+                    let metadata = Metadata::new(100, channel_id, 10000, 1000, FOREVER, Nanos);
+                    shm_writer(&root, &metadata, EncoderHandler::default()).map(|_| ())
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().is_ok());
+        }
+        ensure_channel_dir(&root, 0xCAFE << 32).unwrap();
+        assert_eq!(
+            std::fs::read_dir(storage_path(&root, 0xCAFE << 32).parent().unwrap())
+                .unwrap()
+                .count(),
+            16
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_documents() {
+        use crate::api::{DataFormat, DecodeError, JsonDataFormat};
+        use serde_json::{json, Value};
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let order = json!({"id": 42, "side": "buy", "qty": 1.5, "tags": ["fast", null], "filled": false});
+        writer.write(&order).unwrap();
+        writer.write(&"{not json").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let decoded: Option<Value> = reader.decode_next(&JsonDataFormat).unwrap();
+        assert_eq!(decoded, Some(order));
+        assert_matches!(reader.decode_next::<_, Value>(&JsonDataFormat), Err(DecodeError::Decoding(_)));
+        assert_eq!(JsonDataFormat.media_type(), "application/json");
+    }
+
+    #[test]
+    fn rewind_to_marks() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..6u64 {
+            writer.write(&i).unwrap();
+        }
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let read_next = |reader: &mut ShmReader| read_u64_at(reader.try_read().unwrap().unwrap(), 0).unwrap();
+        assert_eq!(read_next(&mut reader), 0);
+        assert_eq!(read_next(&mut reader), 1);
+        reader.push_mark();
+        assert_eq!(read_next(&mut reader), 2);
+        reader.push_mark();
+        assert_eq!(read_next(&mut reader), 3);
+        assert_eq!(read_next(&mut reader), 4);
+        assert_eq!(reader.marks(), 2);
+        assert!(reader.pop_mark());
+        assert_eq!(read_next(&mut reader), 3);
+        assert!(reader.pop_mark());
+        assert_eq!(read_next(&mut reader), 2);
+        assert!(!reader.pop_mark());
+        //a committed mark keeps the reader where it is
+        reader.push_mark();
+        assert_eq!(read_next(&mut reader), 3);
+        assert!(reader.commit_mark());
+        assert!(!reader.commit_mark());
+        assert_eq!(read_next(&mut reader), 4);
+        assert_eq!(read_next(&mut reader), 5);
+        assert_matches!(reader.try_read(), Ok(None));
+    }
+
+    #[test]
+    fn storage_without_metadata() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        ensure_channel_dir(test_tmp_dir.path(), 1000).unwrap();
+        let kek_file_path = storage_path(test_tmp_dir.path(), 1000);
+        std::fs::File::create(&kek_file_path).unwrap();
+        assert_matches!(shm_reader(test_tmp_dir.path(), 1000), Err(StorageNotReady { .. }));
+        std::fs::write(&kek_file_path, [0u8; 64]).unwrap();
+        assert_matches!(shm_reader_at(&kek_file_path), Err(StorageNotReady { .. }));
+        //not even the metadata length check panics on a short buffer
+        assert_matches!(
+            Metadata::read(&[0u8; 64]),
+            Err(InvalidMetadataLength { metadata_len: 64, .. })
+        );
+    }
+
+    #[test]
+    fn conflated_reads() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.seek_to_latest(), Ok(None));
+        for i in 1..=20u64 {
+            writer.write(&i).unwrap();
+        }
+        let latest = reader.seek_to_latest().unwrap().unwrap();
+        assert_eq!(read_u64_at(latest, 0), Some(20));
+        assert_matches!(reader.try_read(), Ok(None));
+        writer.write(&21u64).unwrap();
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(21));
+        writer.write(&22u64).unwrap();
+        drop(writer);
+        let latest = reader.seek_to_latest().unwrap().unwrap();
+        assert_eq!(read_u64_at(latest, 0), Some(22));
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        assert_matches!(reader.seek_to_latest(), Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn recent_records_newest_first() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert!(reader.recent(3).unwrap().is_empty());
+        for i in 1..=10u64 {
+            writer.write(&i).unwrap();
+        }
+        let recent = reader.recent(3).unwrap();
+        let values: Vec<u64> = recent.iter().map(|(_, record)| read_u64_at(record, 0).unwrap()).collect();
+        assert_eq!(values, vec![10, 9, 8]);
+        //every record takes 8 bytes of header and 8 bytes of payload
+        let offsets: Vec<u32> = recent.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(offsets, vec![144, 128, 112]);
+        assert_matches!(reader.try_read(), Ok(None));
+        writer.write(&11u64).unwrap();
+        drop(writer);
+        assert_eq!(reader.recent(3).unwrap().len(), 1);
+        assert_matches!(reader.recent(3), Err(ReadError::Closed));
+    }
+
+    #[test]
+    fn small_channel() {
+        let metadata = Metadata::new_small(100, 1000, 1024, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.metadata().capacity(), 1024);
+        assert_eq!(reader.metadata().max_msg_len(), 120);
+        let msg = [7u8; 100];
+        let mut written = 0;
+        while writer.write(&&msg[..]).is_ok() {
+            written += 1;
+        }
+        assert_eq!(written, 1024 / 112);
+        let read = reader
+            .try_iter()
+            .take_while(|res| matches!(res, ReadResult::Record(_)))
+            .count();
+        assert_eq!(read, written);
+    }
+
+    #[test]
+    fn reader_record_limit() {
+        let metadata = Metadata::new(100, 1000, 200_000, 1024, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&&[1u8; 256][..]).unwrap();
+        writer.write(&&[2u8; 512][..]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap().with_max_record(256);
+        assert_eq!(reader.max_record(), 256);
+        assert_eq!(reader.try_read().unwrap().unwrap(), &[1u8; 256][..]);
+        assert_matches!(reader.try_read(), Err(ReadError::RecordTooLarge(512)));
+        assert_eq!(reader.exhausted(), Some(ReadError::RecordTooLarge(512)));
+        //the channel limit alone accepts the record
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.max_record(), u32::MAX);
+        reader.try_read().unwrap().unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), &[2u8; 512][..]);
+    }
+
+    #[test]
+    fn structured_records() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let start = Nanos.nix_time();
+        let layouts = [(true, false), (false, true), (true, true)];
+        for (channel_id, (timestamp, sequence)) in layouts.iter().copied().enumerate() {
+            let layout = RecordLayout { timestamp, sequence };
+            let metadata = Metadata::new(100, channel_id as u64, 10000, 1000, FOREVER, Nanos).with_record_layout(layout);
+            let mut chain = HandlerChain::builder();
+            if timestamp {
+                chain = chain.add(TimestampHandler::new(Nanos));
+            }
+            if sequence {
+                chain = chain.add(SequenceHandler::new(10));
+            }
+            let handler = chain.add(EncoderHandler::default()).build();
+            let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+            writer.write(&"first").unwrap();
+            writer.write(&"second").unwrap();
+            let mut reader = shm_reader(test_tmp_dir.path(), channel_id as u64).unwrap();
+            assert_eq!(reader.metadata().record_layout(), layout);
+            let records: Vec<_> = reader.structured_iter().collect();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].body, b"first");
+            assert_eq!(records[1].body, b"second");
+            assert_eq!(records[0].timestamp.is_some(), timestamp);
+            assert!(records[0].timestamp.is_none_or(|ts| ts >= start));
+            assert_eq!(records[0].sequence, if sequence { Some(11) } else { None });
+            assert_eq!(records[1].sequence, if sequence { Some(12) } else { None });
+        }
+        //channels without a declared layout yield the whole record as body
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"plain").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let record = reader.structured_iter().next().unwrap();
+        assert_eq!((record.timestamp, record.sequence, record.body), (None, None, &b"plain"[..]));
+    }
+
+    #[test]
+    fn recommended_capacity_holds_workload() {
+        //mostly small records, with a few large ones
+        let sizes: Vec<u32> = (0..5000u32).map(|i| if i % 100 == 0 { 900 } else { 10 + i % 50 }).collect();
+        let capacity = recommend_capacity(&sizes, 1.0);
+        assert_eq!(capacity % 8, 0);
+        assert_eq!(recommend_max_msg_len(&sizes), 900);
+        let metadata = Metadata::new(100, 1000, capacity, recommend_max_msg_len(&sizes), FOREVER, Nanos);
+        assert_eq!(metadata.capacity(), capacity);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for size in sizes.iter() {
+            writer.write(&&vec![1u8; *size as usize][..]).unwrap();
+        }
+        assert!(recommend_capacity(&sizes, 2.0) > capacity);
+        assert_eq!(recommend_capacity(&sizes, 0.5), capacity);
+        assert_eq!(recommend_capacity(&[], 1.5), 16 * 1024);
+    }
+
+    #[test]
+    fn cross_process_exchange() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let reader = crate::test_support::spawn_reader(test_tmp_dir.path(), 1000, 10_000);
+        let writer = crate::test_support::spawn_writer(test_tmp_dir.path(), 1000, 10_000);
+        assert!(writer.join());
+        assert!(reader.join());
+        assert_eq!(collect_channel(test_tmp_dir.path(), 1000).unwrap().len(), 10_000);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let created = Nanos.nix_time();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let footer = reader.footer().unwrap();
+        assert_eq!(footer.committed_len, 0);
+        assert!(footer.last_write >= created);
+        for i in 0..10u64 {
+            writer.write(&i).unwrap();
+        }
+        writer.heartbeat().unwrap();
+        let footer = reader.footer().unwrap();
+        assert_eq!(footer.committed_len, writer.write_offset());
+        assert_eq!(footer.committed_len, 10 * 16 + 8);
+        assert!(footer.last_write <= Nanos.nix_time());
+        assert!(footer.last_write >= created);
+        //an archive ends right after its records, so it has no footer
+        let archive_path = test_tmp_dir.path().join("1000.archive");
+        archive_channel(test_tmp_dir.path(), 1000, &archive_path).unwrap();
+        assert_eq!(shm_reader_at(&archive_path).unwrap().footer(), None);
+    }
+
+    #[test]
+    fn transient_marker_settles() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&1u64).unwrap();
+        writer.write(&2u64).unwrap();
+        //the header of the first record is replaced by an intermediate value, as seen by a reader racing the writer
+        let kek_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        let mut mmap = unsafe { MmapOptions::new().map_mut(&kek_file) }.unwrap();
+        let header_ptr = unsafe { mmap.as_mut_ptr().add(metadata.len()) } as *mut u64;
+        let header = utils::load_atomic_u64(header_ptr, Ordering::Acquire);
+        utils::store_atomic_u64(header_ptr, 0xDEAD_0000_0000_0008, Ordering::Release);
+        let mut impatient = shm_reader(test_tmp_dir.path(), 1000).unwrap().with_marker_retries(0);
+        assert_eq!(impatient.marker_retries(), 0);
+        assert_matches!(impatient.try_read(), Err(ReadError::Failed));
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap().with_marker_retries(u32::MAX);
+        let header_addr = header_ptr as usize;
+        let publisher = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            utils::store_atomic_u64(header_addr as *mut u64, header, Ordering::Release);
+        });
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(1));
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(2));
+        publisher.join().unwrap();
+        assert_eq!(reader.exhausted(), None);
+        drop(mmap);
+    }
 }
@@ -0,0 +1,120 @@
+//! Provides the archival of a channel into a compact file which holds only the records written so far.
+use super::utils::{CLOSE, FOOTER_LEN, U64_SIZE};
+use super::{open_storage, shm_reader};
+use crate::api::ChannelError::{AccessError, MemoryMappingFailed};
+use crate::api::{ChannelError, ReadError, Reader};
+use memmap::MmapOptions;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// Archives the current contents of a channel, which may still be written. The metadata and the records
+/// written so far are copied into a new file, but not the unused space at the end of the channel, so the archive
+/// is usually much smaller than the channel. The archive is closed, and it can be opened as any other
+/// channel using [shm_reader_at](fn.shm_reader_at.html). Records written while the archive is taken
+/// are not archived.
+///
+/// Returns the length of the archive file.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+/// * `dest` - The path of the archive file. It must not exist.
+///
+/// # Errors
+///
+/// If the channel cannot be read, it is corrupted, or the archive file cannot be written.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Hello").unwrap();
+/// let archive_path = test_tmp_dir.path().join("42.archive");
+/// archive_channel(&test_tmp_dir.path(), 42, &archive_path).unwrap();
+/// let mut reader = shm_reader_at(&archive_path).unwrap();
+/// assert_eq!(reader.try_read().unwrap().unwrap(), b"Hello");
+/// assert_eq!(reader.try_read(), Err(ReadError::Closed));
+/// ```
+pub fn archive_channel(root_path: &Path, channel_id: u64, dest: &Path) -> Result<u64, ChannelError> {
+    //the reader stops at the frontier, the first position not holding a published record
+    let mut reader = shm_reader(root_path, channel_id)?;
+    loop {
+        match reader.try_read() {
+            Ok(Some(_)) => (),
+            Ok(None) | Err(ReadError::Closed) => break,
+            Err(err) => {
+                return Err(AccessError {
+                    reason: format!("Channel read failed at position {}: {:?}", reader.position(), err),
+                })
+            }
+        }
+    }
+    let used_len = reader.metadata().len() + reader.position() as usize;
+    let kek_file = open_storage(root_path, channel_id, false)?;
+    let mmap = unsafe { MmapOptions::new().len(used_len).map(&kek_file) }
+        .map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+    let write_archive = || -> std::io::Result<()> {
+        let mut archive_file = OpenOptions::new().write(true).create_new(true).open(dest)?;
+        archive_file.write_all(&mmap[..])?;
+        //the archive ends with a closed footer, so its readers never wait for more records
+        archive_file.write_all(&CLOSE.to_le_bytes())?;
+        archive_file.write_all(&[0u8; FOOTER_LEN as usize - U64_SIZE])?;
+        archive_file.sync_all()
+    };
+    write_archive().map_err(|err| AccessError {
+        reason: format!("Archive {:?} could not be written: {}", dest, err),
+    })?;
+    let archive_len = (used_len + FOOTER_LEN as usize) as u64;
+    info!(
+        "[channel {}] Kekbit channel archived into {:?}, {} bytes",
+        channel_id, dest, archive_len
+    );
+    Ok(archive_len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{collect_channel, shm_reader_at, shm_writer, storage_path, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn archive_half_full_channel() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 32_000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        while writer.write_offset() < metadata.capacity() / 2 {
+            writer.write(&(writer.write_offset() as u64, "record")).unwrap();
+            writer.heartbeat().unwrap();
+        }
+        let archive_path = test_tmp_dir.path().join("1000.archive");
+        let archive_len = archive_channel(test_tmp_dir.path(), 1000, &archive_path).unwrap();
+        assert_eq!(std::fs::metadata(&archive_path).unwrap().len(), archive_len);
+        let channel_len = std::fs::metadata(storage_path(test_tmp_dir.path(), 1000)).unwrap().len();
+        assert!(archive_len < channel_len);
+        //the archive is a closed channel which holds exactly the same records
+        writer.write(&"Not archived").unwrap();
+        let records = collect_channel(test_tmp_dir.path(), 1000).unwrap();
+        let mut archive_reader = shm_reader_at(&archive_path).unwrap();
+        assert_eq!(archive_reader.metadata(), writer.metadata());
+        let mut archived = Vec::new();
+        while let Ok(Some(record)) = archive_reader.try_read() {
+            archived.push(record.to_vec());
+        }
+        assert_eq!(archive_reader.exhausted(), Some(ReadError::Closed));
+        assert_eq!(archived.len(), records.len() - 1);
+        assert_eq!(&archived[..], &records[..records.len() - 1]);
+        assert!(archive_channel(test_tmp_dir.path(), 1000, &archive_path).is_err());
+    }
+}
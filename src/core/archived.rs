@@ -0,0 +1,155 @@
+//! Provides zero-copy access to records serialized with [rkyv](https://docs.rs/rkyv).
+use crate::api::{DataFormat, DecodeError, Encodable, Reader};
+use rkyv::api::high::{HighSerializer, HighValidator};
+use rkyv::bytecheck::CheckBytes;
+use rkyv::rancor;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Portable, Serialize};
+use std::io::{Error, ErrorKind, Write};
+
+///Data format for records serialized with rkyv. Requires the `rkyv` feature.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RkyvDataFormat;
+
+impl DataFormat for RkyvDataFormat {
+    ///Returns 2, the id of the rkyv data format.
+    #[inline]
+    fn id(&self) -> u64 {
+        2
+    }
+    ///Returns `application/x-rkyv`
+    #[inline]
+    fn media_type(&self) -> &'static str {
+        "application/x-rkyv"
+    }
+}
+
+///Wraps a value which will be written into a channel serialized with rkyv.
+///Requires the `rkyv` feature.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct RkyvRecord<'a, T>(pub &'a T);
+
+impl<'a, T> Encodable for RkyvRecord<'a, T>
+where
+    T: for<'b> Serialize<HighSerializer<AlignedVec, ArenaHandle<'b>, rancor::Error>>,
+{
+    #[inline]
+    fn encode(&self, w: &mut impl Write) -> Result<usize, Error> {
+        let bytes = rkyv::to_bytes::<rancor::Error>(self.0).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        w.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+/// Extends any [Reader](../api/trait.Reader.html) with zero-copy access to records serialized with rkyv.
+/// Records are validated, than returned as archived views which point directly into the channel,
+/// so no deserialization takes place. Requires the `rkyv` feature.
+///
+/// rkyv requires the archived data to be aligned. Channel records are 8 bytes aligned, so any type
+/// whose archived form requires at most 8 bytes alignment is supported, as long as the record handler writes
+/// no headers, or only headers which are a multiple of 8 bytes long. Compressed records are not supported,
+/// as they are not aligned after decompression.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&RkyvRecord(&vec![1u64, 2, 3])).unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let archived = reader.try_read_archived::<Vec<u64>>().unwrap().unwrap();
+/// assert_eq!(archived.len(), 3);
+/// ```
+pub trait ArchivedReader: Reader {
+    /// Reads the next record from the channel without blocking and returns its archived view.
+    ///
+    /// Returns the archived record, or None if no record is available.
+    ///
+    /// # Errors
+    ///
+    /// If the read operation fails or the record is not a valid archive. A validation failure does not exhaust the channel.
+    #[inline]
+    fn try_read_archived<'a, T>(&mut self) -> Result<Option<&'a T::Archived>, DecodeError>
+    where
+        T: Archive,
+        T::Archived: Portable + for<'b> CheckBytes<HighValidator<'b, rancor::Error>>,
+    {
+        match self.try_read()? {
+            Some(record) => rkyv::access::<T::Archived, rancor::Error>(record)
+                .map(Some)
+                .map_err(|err| DecodeError::Decoding(Error::new(ErrorKind::InvalidData, err))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Reader> ArchivedReader for R {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, ChainedHandler, Metadata, SequenceHandler};
+    use assert_matches::assert_matches;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[derive(Archive, Serialize)]
+    #[rkyv(derive(Debug))]
+    struct Trade {
+        id: u64,
+        price: f64,
+        qty: u32,
+        symbol: String,
+    }
+
+    #[test]
+    fn archived_round_trip() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for id in 0..10u64 {
+            let trade = Trade {
+                id,
+                price: 100.25 + id as f64,
+                qty: 10 * id as u32,
+                symbol: format!("KEK{}", id),
+            };
+            writer.write(&RkyvRecord(&trade)).unwrap();
+        }
+        writer.write(&"Not an archived trade").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for id in 0..10u64 {
+            let trade = reader.try_read_archived::<Trade>().unwrap().unwrap();
+            assert_eq!(trade.id, id);
+            assert_eq!(trade.price, 100.25 + id as f64);
+            assert_eq!(trade.qty, 10 * id as u32);
+            assert_eq!(trade.symbol.as_str(), format!("KEK{}", id));
+        }
+        assert_matches!(reader.try_read_archived::<Trade>(), Err(DecodeError::Decoding(_)));
+        assert_matches!(reader.try_read_archived::<Trade>(), Ok(None));
+    }
+
+    #[test]
+    fn archived_after_header() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default());
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        writer.write(&RkyvRecord(&(7u64, 3.5f64))).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let record = reader.try_read().unwrap().unwrap();
+        assert_eq!(&record[..8], &1u64.to_le_bytes());
+        let archived = rkyv::access::<<(u64, f64) as Archive>::Archived, rancor::Error>(&record[8..]).unwrap();
+        assert_eq!(archived.0, 7);
+        assert_eq!(archived.1, 3.5);
+    }
+}
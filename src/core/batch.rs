@@ -0,0 +1,219 @@
+//! Provides a writer which packs many small items into a single channel record, and the iterator which unpacks them.
+use super::{read_u32_at, ShmWriter};
+use crate::api::{Encodable, Handler, LengthPrefixed, WriteError, Writer};
+use std::io::{Error, ErrorKind};
+
+/// A [Writer](../api/trait.Writer.html) which accumulates small items into an internal buffer and pushes
+/// them into the channel as a single record, saving the per record header and padding. Inside a batch
+/// every item is [length prefixed](../api/struct.LengthPrefixed.html). A batch is written when it reaches
+/// the given size or item count, when it is explicitly [flushed](#method.flush_batch), or when the
+/// batching writer is dropped. The batch records should be read back using [unbatch](fn.unbatch.html).
+///
+/// Batching trades latency for throughput, as no item becomes visible to readers until its batch is written.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let mut batch_writer = BatchWriter::new(writer, 512, 16);
+/// for i in 0..10u64 {
+///     batch_writer.write(&i).unwrap();
+/// }
+/// batch_writer.flush_batch().unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let record = reader.try_read().unwrap().unwrap();
+/// assert_eq!(unbatch(record).count(), 10);
+/// ```
+pub struct BatchWriter<H: Handler> {
+    writer: ShmWriter<H>,
+    max_bytes: usize,
+    max_items: u32,
+    batch: Vec<u8>,
+    items: u32,
+}
+
+impl<H: Handler> BatchWriter<H> {
+    /// Creates a batching writer on top of the given channel writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer used to push the batches into the channel
+    /// * `max_bytes` - The size of a batch which triggers its write. It should not exceed the maximum
+    ///   message length of the channel.
+    /// * `max_items` - The number of items which triggers the write of a batch
+    #[inline]
+    pub fn new(writer: ShmWriter<H>, max_bytes: u32, max_items: u32) -> BatchWriter<H> {
+        BatchWriter {
+            writer,
+            max_bytes: max_bytes as usize,
+            max_items: max_items.max(1),
+            batch: Vec::with_capacity(max_bytes as usize),
+            items: 0,
+        }
+    }
+
+    /// Writes the pending items, if any, into the channel as a single record.
+    ///
+    /// Returns the total amount of bytes wrote into the channel, or zero if no item was pending.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the underlying channel writer. In case of failure the pending items are kept.
+    pub fn flush_batch(&mut self) -> Result<u32, WriteError> {
+        if self.items == 0 {
+            return Ok(0);
+        }
+        let written = self.writer.write(&self.batch)?;
+        self.batch.clear();
+        self.items = 0;
+        Ok(written)
+    }
+
+    /// Returns the number of items waiting to be written.
+    #[inline]
+    pub fn pending(&self) -> u32 {
+        self.items
+    }
+
+    /// Returns a reference to the underlying channel writer.
+    #[inline]
+    pub fn writer(&self) -> &ShmWriter<H> {
+        &self.writer
+    }
+}
+
+impl<H: Handler> Writer for BatchWriter<H> {
+    /// Adds an item to the current batch. If the item would not fit in the current batch, the batch is
+    /// written first. If the batch reaches the size or item count limit it is written.
+    ///
+    /// Returns the total amount of bytes wrote into the channel by this call, which is zero
+    /// if the item was only buffered.
+    ///
+    /// # Errors
+    ///
+    /// If the item cannot be encoded or the underlying channel writer fails. An empty item is rejected
+    /// with `EmptyRecord`.
+    fn write<E: Encodable>(&mut self, data: &E) -> Result<u32, WriteError> {
+        let mut item = Vec::new();
+        LengthPrefixed(data).encode(&mut item).map_err(WriteError::EncodingError)?;
+        if item.len() == 4 {
+            return Err(WriteError::EmptyRecord);
+        }
+        let mut written = 0;
+        if self.items > 0 && self.batch.len() + item.len() > self.max_bytes {
+            written += self.flush_batch()?;
+        }
+        self.batch.extend_from_slice(&item);
+        self.items += 1;
+        if self.batch.len() >= self.max_bytes || self.items >= self.max_items {
+            written += self.flush_batch()?;
+        }
+        Ok(written)
+    }
+
+    /// Writes the pending items, then flushes the underlying channel writer.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.flush_batch()
+            .map_err(|err| Error::other(format!("Could not write batch {:?}", err)))?;
+        self.writer.flush()
+    }
+}
+
+impl<H: Handler> Drop for BatchWriter<H> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_batch() {
+            error!("Could not write the pending batch of {} items: {:?}", self.items, err);
+        }
+    }
+}
+
+/// Returns an iterator over the items of a record written by a [BatchWriter](struct.BatchWriter.html).
+///
+/// # Arguments
+///
+/// * `record` - A record read from a channel fed by a batching writer
+#[inline]
+pub fn unbatch(record: &[u8]) -> Unbatch<'_> {
+    Unbatch { record, failed: false }
+}
+
+/// An iterator over the items of a batch record. It yields an error, then ends, if the record is malformed.
+pub struct Unbatch<'a> {
+    record: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Iterator for Unbatch<'a> {
+    type Item = Result<&'a [u8], Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.record.is_empty() {
+            return None;
+        }
+        let item =
+            read_u32_at(self.record, 0).and_then(|len| self.record[4..].get(..len as usize).map(|item| (item, 4 + len as usize)));
+        match item {
+            Some((item, consumed)) => {
+                self.record = &self.record[consumed..];
+                Some(Ok(item))
+            }
+            None => {
+                self.failed = true;
+                Some(Err(Error::new(ErrorKind::UnexpectedEof, "Truncated batch item")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Reader};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn batch_tiny_items() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut batch_writer = BatchWriter::new(writer, 256, 1000);
+        for i in 0..100u32 {
+            batch_writer.write(&i).unwrap();
+        }
+        assert!(batch_writer.pending() > 0);
+        assert!(batch_writer.flush_batch().unwrap() > 0);
+        assert_eq!(batch_writer.pending(), 0);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut records = 0;
+        let mut items = Vec::new();
+        while let Some(record) = reader.try_read().unwrap() {
+            records += 1;
+            for item in unbatch(record) {
+                items.push(read_u32_at(item.unwrap(), 0).unwrap());
+            }
+        }
+        assert_eq!(records, 4);
+        assert_eq!(items, (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn truncated_batch() {
+        let mut record = Vec::new();
+        LengthPrefixed("item").encode(&mut record).unwrap();
+        record.extend_from_slice(&[9, 0, 0, 0, 1]);
+        let mut items = unbatch(&record);
+        assert_eq!(items.next().unwrap().unwrap(), b"item");
+        assert_eq!(items.next().unwrap().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(items.next().is_none());
+    }
+}
@@ -0,0 +1,343 @@
+//! Provides a builder used to fine tune how a channel is created.
+use super::{create_shm_writer, create_shm_writer_locked};
+use super::{storage_path, LockGuard, Metadata, ShmWriter};
+use crate::api::{ChannelError, Handler};
+use std::path::Path;
+
+/// Configures the creation of a channel beyond what is described by its [Metadata](struct.Metadata.html).
+/// The options set by a builder only affect the process which creates the channel, they are not persisted
+/// in the channel storage.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = ChannelBuilder::default()
+///     .locked(true)
+///     .shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default())
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChannelBuilder {
+    pub(super) locked: bool,
+    pub(super) lock_required: bool,
+    pub(super) durable_init: bool,
+    pub(super) delete_on_close: bool,
+    #[cfg(feature = "digest")]
+    pub(super) digest: bool,
+}
+
+impl Default for ChannelBuilder {
+    #[inline]
+    fn default() -> ChannelBuilder {
+        ChannelBuilder {
+            locked: false,
+            lock_required: false,
+            durable_init: true,
+            delete_on_close: false,
+            #[cfg(feature = "digest")]
+            digest: false,
+        }
+    }
+}
+
+impl ChannelBuilder {
+    /// Creates a builder with the default options, the same ones used by [shm_writer](fn.shm_writer.html).
+    #[inline]
+    pub fn new() -> ChannelBuilder {
+        ChannelBuilder::default()
+    }
+
+    /// Locks the channel's memory in RAM, so the kernel will never swap it out. This avoids unpredictable
+    /// stalls for latency critical channels. By default a failure to lock the memory, usually due to a low
+    /// `RLIMIT_MEMLOCK`, is logged and the channel is created anyway. See [lock_required](#method.lock_required).
+    ///
+    /// # Arguments
+    ///
+    /// * `locked` - If true the channel memory will be locked
+    #[inline]
+    pub fn locked(mut self, locked: bool) -> ChannelBuilder {
+        self.locked = locked;
+        self
+    }
+
+    /// Specifies if the channel creation must fail when the channel memory could not be locked.
+    /// It has effect only for [locked](#method.locked) channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `required` - If true a failure to lock the memory will abort the channel creation
+    #[inline]
+    pub fn lock_required(mut self, required: bool) -> ChannelBuilder {
+        self.lock_required = required;
+        self
+    }
+
+    /// Specifies if the channel metadata is flushed to the storage as soon as the channel is created. This is
+    /// the default. Skipping the flush speeds up the creation of short lived channels, such the ones used in tests,
+    /// as the metadata is still available to any process which maps the channel. However, if the system crashes
+    /// before the operating system flushes the channel pages, the metadata could be lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `durable` - If false the channel metadata will not be flushed at creation
+    #[inline]
+    pub fn durable_init(mut self, durable: bool) -> ChannelBuilder {
+        self.durable_init = durable;
+        self
+    }
+
+    /// Specifies if the file which backs the channel is removed when its writer is dropped. By default the
+    /// file is kept, so the channel could still be read, or archived, after it was closed. Ephemeral channels
+    /// may be removed, so they do not need to be cleaned up by hand. On unix, readers which already mapped
+    /// the channel may still read it after the file is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `delete` - If true the channel file will be removed when the channel is closed
+    #[inline]
+    pub fn delete_on_close(mut self, delete: bool) -> ChannelBuilder {
+        self.delete_on_close = delete;
+        self
+    }
+
+    /// Specifies if the writer keeps a running digest of all the records written into the channel. The digest is
+    /// updated after every write and stored in the channel footer, so the records could later be checked against it
+    /// with [verify_digest](struct.ShmReader.html#method.verify_digest), e.g. to detect any modification of
+    /// the channel data after it was closed. The digest is a BLAKE3 hash, so the records cannot be changed in a way
+    /// which keeps the digest. As the digest lives in the channel itself, a copy of it should be kept elsewhere to
+    /// also detect a rewrite of the whole channel. By default no digest is computed, as it costs some CPU on every write.
+    /// Requires the `digest` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - If true the writer will maintain the digest of the channel records
+    #[cfg(feature = "digest")]
+    #[inline]
+    pub fn digest(mut self, digest: bool) -> ChannelBuilder {
+        self.digest = digest;
+        self
+    }
+
+    /// Creates a file backed memory mapped kekbit channel using the options of this builder, and a writer associated with it.
+    ///
+    /// Returns a ready to use writer to the new created channel or an error if the operation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_path` - The path to the folder where all the channels will be stored grouped by writers id.
+    /// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+    /// * `rec_handler` - The handler used by the writer to push records into the channel
+    ///
+    /// # Errors
+    ///
+    /// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+    #[inline]
+    pub fn shm_writer<H: Handler>(
+        &self,
+        root_path: &Path,
+        metadata: &Metadata,
+        rec_handler: H,
+    ) -> Result<ShmWriter<H>, ChannelError> {
+        create_shm_writer(&storage_path(root_path, metadata.channel_id()), metadata, rec_handler, self)
+    }
+
+    /// Creates a file backed memory mapped kekbit channel stored in the given file using the options of this builder,
+    /// and a writer associated with it. See [shm_writer_at](fn.shm_writer_at.html).
+    ///
+    /// Returns a ready to use writer to the new created channel or an error if the operation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `kek_file_path` - The path to the file which will store the channel. Missing parent folders are created.
+    /// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+    /// * `rec_handler` - The handler used by the writer to push records into the channel
+    ///
+    /// # Errors
+    ///
+    /// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+    #[inline]
+    pub fn shm_writer_at<H: Handler>(
+        &self,
+        kek_file_path: &Path,
+        metadata: &Metadata,
+        rec_handler: H,
+    ) -> Result<ShmWriter<H>, ChannelError> {
+        create_shm_writer(kek_file_path, metadata, rec_handler, self)
+    }
+
+    /// Creates a file backed memory mapped kekbit channel using the options of this builder, and a writer associated with it.
+    /// The channel is hidden from readers until the returned lock guard is released, see [shm_writer_locked](fn.shm_writer_locked.html).
+    ///
+    /// Returns a ready to use writer to the new created channel, together with the guard of the channel lock,
+    /// or an error if the operation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_path` - The path to the folder where all the channels will be stored grouped by writers id.
+    /// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+    /// * `rec_handler` - The handler used by the writer to push records into the channel
+    ///
+    /// # Errors
+    ///
+    /// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+    #[inline]
+    pub fn shm_writer_locked<H: Handler>(
+        &self,
+        root_path: &Path,
+        metadata: &Metadata,
+        rec_handler: H,
+    ) -> Result<(ShmWriter<H>, LockGuard), ChannelError> {
+        create_shm_writer_locked(&storage_path(root_path, metadata.channel_id()), metadata, rec_handler, self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::ChannelError::MemoryLockFailed;
+    use crate::api::Reader;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, ReadResult};
+    use assert_matches::assert_matches;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn locked_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = ChannelBuilder::new()
+            .locked(true)
+            .shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default())
+            .unwrap();
+        writer.write(&"locked".as_bytes()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.try_iter().next(), Some(ReadResult::Record(b"locked")));
+    }
+
+    #[test]
+    fn required_lock() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let res = ChannelBuilder::new().locked(true).lock_required(true).shm_writer(
+            test_tmp_dir.path(),
+            &metadata,
+            EncoderHandler::default(),
+        );
+        //locking may not be permitted, but if it fails no storage should be left behind
+        match res {
+            Ok(mut writer) => {
+                writer.write(&"locked".as_bytes()).unwrap();
+            }
+            Err(err) => {
+                assert_matches!(err, MemoryLockFailed { .. });
+                assert!(!crate::core::storage_path(test_tmp_dir.path(), 1000).exists());
+            }
+        }
+    }
+
+    #[test]
+    fn fast_channel_creation() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let builder = ChannelBuilder::new().durable_init(false);
+        let mut writers = Vec::new();
+        for channel_id in 0..50u64 {
+            let metadata = Metadata::new(100, channel_id, 10000, 1000, FOREVER, Nanos);
+            let mut writer = builder
+                .shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default())
+                .unwrap();
+            writer.write(&channel_id).unwrap();
+            writers.push(writer);
+        }
+        for channel_id in 0..50u64 {
+            let mut reader = shm_reader(test_tmp_dir.path(), channel_id).unwrap();
+            assert_eq!(reader.metadata().channel_id(), channel_id);
+            assert_eq!(reader.try_read().unwrap().unwrap(), &channel_id.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn delete_channel_on_close() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let kept_metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut kept = ChannelBuilder::new()
+            .shm_writer(test_tmp_dir.path(), &kept_metadata, EncoderHandler::default())
+            .unwrap();
+        kept.write(&"kept").unwrap();
+        drop(kept);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_matches!(reader.try_iter().next(), Some(ReadResult::Record(b"kept")));
+        let metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = ChannelBuilder::new()
+            .delete_on_close(true)
+            .shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default())
+            .unwrap();
+        writer.write(&"ephemeral").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        let kek_file_path = crate::core::storage_path(test_tmp_dir.path(), 2000);
+        assert!(kek_file_path.exists());
+        drop(writer);
+        assert!(!kek_file_path.exists());
+        //a reader which mapped the channel can still read it
+        assert_matches!(reader.try_iter().next(), Some(ReadResult::Record(b"ephemeral")));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn channel_digest() {
+        use std::io::{Seek, SeekFrom, Write};
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = ChannelBuilder::new()
+            .digest(true)
+            .shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default())
+            .unwrap();
+        //a channel with no records has the digest of no data, which is not mistaken for a missing digest
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.footer().unwrap().digest, Some(*blake3::hash(&[]).as_bytes()));
+        for i in 0..10u64 {
+            writer.write(&i).unwrap();
+            writer.heartbeat().unwrap();
+        }
+        writer.write_chunked(&[7u8; 2500]).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.verify_digest(), Some(true));
+        drop(writer);
+        assert_eq!(reader.verify_digest(), Some(true));
+        //the digest covers the length and the bytes of every record, heartbeats are skipped
+        let mut hasher = blake3::Hasher::new();
+        for i in 0..10u64 {
+            hasher.update(&8u32.to_le_bytes());
+            hasher.update(&i.to_le_bytes());
+        }
+        for chunk in [7u8; 2500].chunks(reader.metadata().max_msg_len() as usize) {
+            hasher.update(&(chunk.len() as u32).to_le_bytes());
+            hasher.update(chunk);
+        }
+        assert_eq!(reader.footer().unwrap().digest, Some(*hasher.finalize().as_bytes()));
+        //a single byte of a record body is flipped
+        let mut kek_file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(crate::core::storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(128 + 3 * 24 + 8)).unwrap();
+        kek_file.write_all(&[0xFF]).unwrap();
+        assert_eq!(reader.verify_digest(), Some(false));
+        //without a digest there is nothing to verify
+        let metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let _writer = ChannelBuilder::new()
+            .shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default())
+            .unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        assert_eq!(reader.footer().unwrap().digest, None);
+        assert_eq!(reader.verify_digest(), None);
+    }
+}
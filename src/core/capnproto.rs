@@ -0,0 +1,235 @@
+//! Provides zero-copy access to the segments of records holding [Cap'n Proto](https://capnproto.org) messages.
+//! Only the message framing is handled, kekbit does not depend on the `capnp` crate, so the messages themselves
+//! are decoded by the application, from the segments located in place. There is no write side, the messages are
+//! framed by the application, e.g. with `capnp::serialize::write_message`. Requires the `capnp` feature.
+use crate::api::{DataFormat, DecodeError, Reader};
+use std::io::{Error, ErrorKind};
+
+//Cap'n Proto messages are made of 8 bytes words.
+const WORD_LEN: usize = 8;
+
+///Data format for records which hold a Cap'n Proto message in the standard stream framing,
+///as written by `capnp::serialize::write_message`. Requires the `capnp` feature.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CapnpDataFormat;
+
+impl DataFormat for CapnpDataFormat {
+    ///Returns 3, the id of the Cap'n Proto data format.
+    #[inline]
+    fn id(&self) -> u64 {
+        3
+    }
+    ///Returns `application/x-capnp`
+    #[inline]
+    fn media_type(&self) -> &'static str {
+        "application/x-capnp"
+    }
+}
+
+/// The segments of a Cap'n Proto message, which point directly into the channel record holding the message.
+/// They could be handed to a Cap'n Proto message reader, e.g. through `capnp::message::SegmentArray`, so the
+/// fields of the message are read in place, without any copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapnpMessage<'a> {
+    segments: Vec<&'a [u8]>,
+}
+
+impl<'a> CapnpMessage<'a> {
+    /// Parses the segment table of a framed Cap'n Proto message, and returns the segments which follow it.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record which holds the framed message
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidData` if the record is not a complete framed message, or if its segments
+    /// are not 8 bytes aligned.
+    pub fn parse(record: &'a [u8]) -> Result<CapnpMessage<'a>, Error> {
+        let count = read_u32(record, 0).ok_or_else(|| invalid("Missing segment count"))? as usize + 1;
+        //the segment table holds the count and every segment size, padded to a whole word
+        let table_len = (count + 1) * 4;
+        let table_len = table_len + table_len % WORD_LEN;
+        if table_len > record.len() {
+            return Err(invalid("Incomplete segment table"));
+        }
+        let mut segments = Vec::with_capacity(count);
+        let mut offset = table_len;
+        for idx in 0..count {
+            let words = read_u32(record, (idx + 1) * 4).unwrap() as usize;
+            let segment = record
+                .get(offset..offset + words * WORD_LEN)
+                .ok_or_else(|| invalid("Incomplete segment"))?;
+            if (segment.as_ptr() as usize) & (WORD_LEN - 1) != 0 {
+                return Err(invalid("Segment is not 8 bytes aligned"));
+            }
+            segments.push(segment);
+            offset += segment.len();
+        }
+        if offset != record.len() {
+            return Err(invalid("Trailing bytes after the last segment"));
+        }
+        Ok(CapnpMessage { segments })
+    }
+
+    /// Returns the number of segments of the message.
+    #[inline]
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns the segment with the given index, if it exists.
+    #[inline]
+    pub fn segment(&self, idx: usize) -> Option<&'a [u8]> {
+        self.segments.get(idx).copied()
+    }
+
+    /// Returns all the segments of the message, in order.
+    #[inline]
+    pub fn segments(&self) -> &[&'a [u8]] {
+        &self.segments
+    }
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+}
+
+#[inline]
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Extends any [Reader](../api/trait.Reader.html) with zero-copy access to the segments of records holding
+/// Cap'n Proto messages. The segments are only located and checked, reading the message fields is left to
+/// the `capnp` crate.
+///
+/// Cap'n Proto requires its segments to be 8 bytes aligned. Channel records are 8 bytes aligned, and so are the
+/// segments of a framed message, as long as the record handler writes no headers, or only headers which are a
+/// multiple of 8 bytes long. Compressed records are not supported, as they are not aligned after decompression.
+///
+/// A message is written as a plain byte record, e.g. the bytes produced by `capnp::serialize::write_message`.
+/// Requires the `capnp` feature.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// //a single segment, holding a root pointer to an empty struct
+/// let mut message = vec![0u8; 8];
+/// message[4] = 1;
+/// message.extend_from_slice(&0xFFFF_FFFCu64.to_le_bytes());
+/// writer.write(&message).unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let message = reader.try_read_capnp().unwrap().unwrap();
+/// assert_eq!(message.segment_count(), 1);
+/// ```
+pub trait CapnpReader: Reader {
+    /// Reads the next record from the channel without blocking and returns the segments of the message it holds.
+    ///
+    /// Returns the message segments, or None if no record is available.
+    ///
+    /// # Errors
+    ///
+    /// If the read operation fails or the record is not a framed message. A decoding failure does not exhaust the channel.
+    #[inline]
+    fn try_read_capnp<'a>(&mut self) -> Result<Option<CapnpMessage<'a>>, DecodeError> {
+        match self.try_read()? {
+            Some(record) => CapnpMessage::parse(record).map(Some).map_err(DecodeError::Decoding),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Reader> CapnpReader for R {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{read_u64_at, shm_reader, shm_writer, Metadata};
+    use assert_matches::assert_matches;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    //Frames a message whose root struct, in the first segment, holds the given data words.
+    //Every other segment is filled with the given number of zero words.
+    fn framed_message(fields: &[u64], other_segments: &[u32]) -> Vec<u8> {
+        let mut sizes = vec![1 + fields.len() as u32];
+        sizes.extend_from_slice(other_segments);
+        let mut message = Vec::new();
+        message.extend_from_slice(&(sizes.len() as u32 - 1).to_le_bytes());
+        for size in &sizes {
+            message.extend_from_slice(&size.to_le_bytes());
+        }
+        if message.len() & (WORD_LEN - 1) != 0 {
+            message.extend_from_slice(&[0; 4]);
+        }
+        //a struct pointer to the next word, with the data section holding the fields
+        message.extend_from_slice(&((fields.len() as u64) << 32).to_le_bytes());
+        for field in fields {
+            message.extend_from_slice(&field.to_le_bytes());
+        }
+        for size in other_segments {
+            message.extend(vec![0u8; *size as usize * WORD_LEN]);
+        }
+        message
+    }
+
+    //the messages are framed by hand, as the capnp crate is not a dependency
+    #[test]
+    fn framed_segments_round_trip() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for id in 0..10u64 {
+            writer.write(&framed_message(&[id, id * 100], &[])).unwrap();
+        }
+        writer.write(&framed_message(&[7], &[3, 1])).unwrap();
+        writer.write(&"Not a message").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for id in 0..10u64 {
+            let message = reader.try_read_capnp().unwrap().unwrap();
+            assert_eq!(message.segment_count(), 1);
+            let root = message.segment(0).unwrap();
+            assert_eq!(read_u64_at(root, 8), Some(id));
+            assert_eq!(read_u64_at(root, 16), Some(id * 100));
+        }
+        let message = reader.try_read_capnp().unwrap().unwrap();
+        let sizes: Vec<usize> = message.segments().iter().map(|segment| segment.len()).collect();
+        assert_eq!(sizes, vec![16, 24, 8]);
+        assert_eq!(message.segment(3), None);
+        assert_matches!(reader.try_read_capnp(), Err(DecodeError::Decoding(_)));
+        assert_matches!(reader.try_read_capnp(), Ok(None));
+    }
+
+    //Copies the bytes into a buffer which is 8 bytes aligned, as channel records are.
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        bytes.chunks(WORD_LEN).map(|word| read_u64_at(word, 0).unwrap()).collect()
+    }
+
+    #[test]
+    fn reject_incomplete_messages() {
+        let words = aligned(&framed_message(&[1, 2], &[2]));
+        let message = unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * WORD_LEN) };
+        assert!(CapnpMessage::parse(message).is_ok());
+        assert!(CapnpMessage::parse(&message[..3]).is_err());
+        assert!(CapnpMessage::parse(&message[..8]).is_err());
+        assert!(CapnpMessage::parse(&message[..message.len() - 8]).is_err());
+        let mut longer = words.clone();
+        longer.push(0);
+        let longer = unsafe { std::slice::from_raw_parts(longer.as_ptr() as *const u8, longer.len() * WORD_LEN) };
+        assert!(CapnpMessage::parse(longer).is_err());
+        //a segment which is not aligned cannot be read in place
+        assert!(CapnpMessage::parse(&message[4..]).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Provides a handler which appends a checksum to every record, and the iterator which validates it.
+use super::read_u32_at;
+use crate::api::{Encodable, Handler, Reader};
+use std::io::{Result, Write};
+use std::iter::FusedIterator;
+
+const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 checksum of the given data, as used by the [ChecksumHandler](struct.ChecksumHandler.html).
+///
+/// # Arguments
+///
+/// * `data` - The data to checksum
+#[inline]
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (crc >> 8) ^ CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize]
+    })
+}
+
+//Extends the running BLAKE3 digest of a channel with the given record. The record length is digested too,
+//so moving bytes from a record to its neighbour changes the digest.
+#[cfg(feature = "digest")]
+#[inline]
+pub(crate) fn digest_record(hasher: &mut blake3::Hasher, record: &[u8]) {
+    hasher.update(&(record.len() as u32).to_le_bytes());
+    hasher.update(record);
+}
+
+/// Handler which appends the CRC-32 checksum of the record produced by the handler it wraps, so readers
+/// could detect corrupted records. The checksummed records should be read back using
+/// [verified_iter](struct.ShmReader.html#method.verified_iter) or [split_checksum](fn.split_checksum.html).
+///
+/// As it checksums the whole record, this handler should be at the top of a handler chain.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, ChecksumHandler::new(EncoderHandler::default())).unwrap();
+/// writer.write(&"Checked").unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// assert_eq!(reader.verified_iter().next(), Some((true, &b"Checked"[..])));
+/// ```
+#[derive(Debug)]
+pub struct ChecksumHandler<H: Handler> {
+    inner: H,
+    buf: Vec<u8>,
+}
+
+impl<H: Handler> ChecksumHandler<H> {
+    /// Creates a handler which checksums the records produced by the given handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The handler which produces the records
+    #[inline]
+    pub fn new(inner: H) -> ChecksumHandler<H> {
+        ChecksumHandler { inner, buf: Vec::new() }
+    }
+}
+
+impl<H: Handler> Handler for ChecksumHandler<H> {
+    /// Writes the record produced by the inner handler into the channel, followed by its checksum.
+    fn handle(&mut self, data: &impl Encodable, w: &mut impl Write) -> Result<usize> {
+        self.buf.clear();
+        self.inner.handle(data, &mut self.buf)?;
+        w.write_all(&self.buf)?;
+        w.write_all(&crc32(&self.buf).to_le_bytes())?;
+        Ok(self.buf.len() + CHECKSUM_LEN)
+    }
+
+    #[inline]
+    fn rec_flags(&self) -> u64 {
+        self.inner.rec_flags()
+    }
+}
+
+/// Splits a record written by a [ChecksumHandler](struct.ChecksumHandler.html) into its payload,
+/// and returns it together with the outcome of the checksum validation. A record too short
+/// to hold a checksum is returned as it is, and flagged as invalid.
+///
+/// # Arguments
+///
+/// * `record` - A checksummed record
+#[inline]
+pub fn split_checksum(record: &[u8]) -> (bool, &[u8]) {
+    match record.len().checked_sub(CHECKSUM_LEN) {
+        Some(payload_len) => {
+            let payload = &record[..payload_len];
+            (read_u32_at(record, payload_len) == Some(crc32(payload)), payload)
+        }
+        None => (false, record),
+    }
+}
+
+///A non-blocking iterator over the checksummed records currently available in the channel.
+///Every record payload is yielded together with the outcome of its checksum validation, so corrupted
+///records could be skipped one by one. The iterator ends when no more records are available or the channel is exhausted.
+pub struct VerifiedIter<'a, R: Reader> {
+    pub(super) inner: &'a mut R,
+}
+
+impl<'a, R: Reader> Iterator for VerifiedIter<'a, R> {
+    type Item = (bool, &'a [u8]);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(Some(record)) => Some(split_checksum(record)),
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+impl<'a, R: Reader> FusedIterator for VerifiedIter<'a, R> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, storage_path, Metadata};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn known_checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(split_checksum(&[1, 2]), (false, &[1u8, 2][..]));
+    }
+
+    #[test]
+    fn flag_corrupted_record() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = ChecksumHandler::new(EncoderHandler::default());
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        for i in 0..5 {
+            writer.write(&format!("record-{}", i)).unwrap();
+        }
+        //every record takes 8 bytes of header, 8 bytes of payload and 4 bytes of checksum, aligned to 24 bytes
+        let mut kek_file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        kek_file.seek(SeekFrom::Start(metadata.len() as u64 + 2 * 24 + 8)).unwrap();
+        kek_file.write_all(b"R").unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let records: Vec<(bool, &[u8])> = reader.verified_iter().collect();
+        assert_eq!(
+            records,
+            vec![
+                (true, &b"record-0"[..]),
+                (true, &b"record-1"[..]),
+                (false, &b"Record-2"[..]),
+                (true, &b"record-3"[..]),
+                (true, &b"record-4"[..]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Provides a handler which compresses the records above a given size.
+use super::utils::COMPRESSED;
+use crate::api::{Encodable, Handler};
+use std::io::{Result, Write};
+
+/// Handler which compresses, using LZ4, the records produced by the handler it wraps.
+/// Small records do not benefit from compression, so only the records of at least `min_size` bytes
+/// are compressed, and only if the compressed record is smaller than the original one. Compressed records
+/// are flagged in their header, and readers decompress them with [read_inflated](struct.ShmReader.html#method.read_inflated),
+/// which returns all the other records as they are. A plain `try_read` stops at a compressed record with
+/// `CompressedRecord`, as it cannot hand out a record decompressed into a buffer it reuses.
+///
+/// As it compresses the whole record, this handler should be at the top of a handler chain.
+/// Requires the `compression` feature.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let handler = CompressionHandler::new(EncoderHandler::default(), 256);
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, handler).unwrap();
+/// writer.write(&"Short records are stored as they are").unwrap();
+/// writer.write(&"Long records are compressed. ".repeat(20)).unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// assert_eq!(reader.read_inflated().unwrap().unwrap(), b"Short records are stored as they are");
+/// assert_eq!(reader.try_read(), Err(ReadError::CompressedRecord));
+/// assert_eq!(reader.read_inflated().unwrap().unwrap(), "Long records are compressed. ".repeat(20).as_bytes());
+/// ```
+#[derive(Debug)]
+pub struct CompressionHandler<H: Handler> {
+    inner: H,
+    min_size: usize,
+    buf: Vec<u8>,
+    compressed: bool,
+}
+
+impl<H: Handler> CompressionHandler<H> {
+    /// Creates a handler which compresses the records produced by the given handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The handler which produces the records
+    /// * `min_size` - The minimum size of a record in order to be compressed
+    #[inline]
+    pub fn new(inner: H, min_size: usize) -> CompressionHandler<H> {
+        CompressionHandler {
+            inner,
+            min_size,
+            buf: Vec::new(),
+            compressed: false,
+        }
+    }
+}
+
+impl<H: Handler> Handler for CompressionHandler<H> {
+    /// Compresses the record produced by the inner handler, if it is large enough, than writes it into the channel.
+    fn handle(&mut self, data: &impl Encodable, w: &mut impl Write) -> Result<usize> {
+        self.buf.clear();
+        self.compressed = false;
+        self.inner.handle(data, &mut self.buf)?;
+        if self.buf.len() >= self.min_size {
+            let compressed = lz4_flex::compress_prepend_size(&self.buf);
+            if compressed.len() < self.buf.len() {
+                w.write_all(&compressed)?;
+                self.compressed = true;
+                return Ok(compressed.len());
+            }
+        }
+        w.write_all(&self.buf)?;
+        Ok(self.buf.len())
+    }
+
+    #[inline]
+    fn rec_flags(&self) -> u64 {
+        if self.compressed {
+            COMPRESSED
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{DecodeError, EncoderHandler, RawBinDataFormat, ReadError, Reader, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn compress_large_records() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = CompressionHandler::new(EncoderHandler::default(), 64);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        let small = "A small record";
+        let large = "A large and compressible record. ".repeat(20);
+        assert_eq!(writer.write(&small).unwrap(), 24);
+        let large_size = writer.write(&large).unwrap();
+        assert!((large_size as usize) < large.len());
+        writer.write(&small).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(reader.read_inflated().unwrap().unwrap(), small.as_bytes());
+        //a compressed record is not borrowed from the channel, so only read_inflated returns it
+        assert_eq!(reader.try_read(), Err(ReadError::CompressedRecord));
+        assert_eq!(reader.exhausted(), None);
+        assert_eq!(reader.read_inflated().unwrap().unwrap(), large.as_bytes());
+        assert_eq!(reader.read_inflated().unwrap().unwrap(), small.as_bytes());
+        assert_eq!(reader.read_inflated().unwrap(), None);
+        assert!(reader.verify_integrity().unwrap().is_ok());
+        //the reader limit applies to the inflated record
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap().with_max_record(100);
+        reader.read_inflated().unwrap().unwrap();
+        assert_eq!(reader.read_inflated(), Err(ReadError::RecordTooLarge(large.len() as u32)));
+    }
+
+    #[test]
+    fn inflated_records_are_distinct() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = CompressionHandler::new(EncoderHandler::default(), 64);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        writer.write(&vec![b'a'; 1000]).unwrap();
+        writer.write(&vec![b'b'; 1000]).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let first = reader.read_inflated().unwrap().unwrap().to_vec();
+        let second = reader.read_inflated().unwrap().unwrap().to_vec();
+        assert_eq!(first, vec![b'a'; 1000]);
+        assert_eq!(second, vec![b'b'; 1000]);
+    }
+
+    #[test]
+    fn iterators_stop_at_compressed_records() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = CompressionHandler::new(EncoderHandler::default(), 64);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        let small = "A small record";
+        let large = "A large and compressible record. ".repeat(20);
+        writer.write(&small).unwrap();
+        writer.write(&large).unwrap();
+        writer.write(&small).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let (decoded, failures) = reader.try_iter().decoded::<_, Vec<u8>>(RawBinDataFormat).partition_results();
+        assert_eq!(decoded, vec![small.as_bytes().to_vec()]);
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(failures[0], DecodeError::Read(ReadError::CompressedRecord)));
+        assert_eq!(reader.try_iter().count(), 1);
+        //the compressed record is left in place for read_inflated
+        assert_eq!(reader.read_inflated().unwrap().unwrap(), large.as_bytes());
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let texts: Vec<_> = reader.text_iter().map(|text| text.unwrap().to_string()).collect();
+        assert_eq!(texts, vec![small.to_string()]);
+        assert_eq!(reader.try_read(), Err(ReadError::CompressedRecord));
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let raw: Vec<_> = reader.raw_iter().collect();
+        assert_eq!(raw.len(), 3);
+        assert_eq!(raw[0].body, small.as_bytes());
+        assert_ne!(raw[1].length_word & COMPRESSED, 0);
+        assert!(raw[1].body.len() < large.len());
+        assert_eq!(raw[2].body, small.as_bytes());
+    }
+}
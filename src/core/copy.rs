@@ -0,0 +1,113 @@
+//! Provides the copy of a whole channel into another one, the building block for channel snapshots and migrations.
+use super::ShmWriter;
+use crate::api::{CopyError, Handler, ReadError, Reader};
+use crossbeam_utils::Backoff;
+use std::time::Duration;
+
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// Copies every record read from a channel into another channel, until the source channel is closed.
+/// The records are written [verbatim](struct.ShmWriter.html#method.write_raw), so the destination
+/// will hold exactly the same records as the source. Once the source is closed the destination writer
+/// is dropped, so the destination channel gets closed too. If the source channel is still written the
+/// function waits for new records, spinning at first, and sleeping if the source stays idle.
+///
+/// Returns the number of records copied.
+///
+/// # Arguments
+///
+/// * `src_reader` - The reader of the source channel
+/// * `dst_writer` - The writer of the destination channel. It should not be smaller than the source channel.
+///
+/// # Errors
+///
+/// If any record could not be read or written. The destination channel is closed in this case too,
+/// as its writer is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let copy_metadata = Metadata::new(1850, 43, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Hello").unwrap();
+/// drop(writer);
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let copy_writer = shm_writer(&test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+/// assert_eq!(copy_channel(&mut reader, copy_writer).unwrap(), 1);
+/// ```
+pub fn copy_channel<R: Reader, H: Handler>(src_reader: &mut R, mut dst_writer: ShmWriter<H>) -> Result<usize, CopyError> {
+    let mut copied = 0;
+    let backoff = Backoff::new();
+    loop {
+        match src_reader.try_read() {
+            Ok(Some(record)) => {
+                dst_writer.write_raw(record)?;
+                copied += 1;
+                backoff.reset();
+            }
+            Ok(None) => {
+                if backoff.is_completed() {
+                    std::thread::sleep(IDLE_SLEEP);
+                } else {
+                    backoff.snooze();
+                }
+            }
+            Err(ReadError::Closed) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    info!(
+        "[channel {} writer {}] Copy completed, {} records copied",
+        dst_writer.metadata().channel_id(),
+        dst_writer.metadata().writer_id(),
+        copied
+    );
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{collect_channel, shm_reader, shm_writer, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn copy_populated_channel() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let copy_metadata = Metadata::new(200, 2000, 100_000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..500u64 {
+            writer.write(&(i, "record")).unwrap();
+            if i % 7 == 0 {
+                writer.heartbeat().unwrap();
+            }
+        }
+        let copy_writer = shm_writer(test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        //the source is closed while the copy is in progress
+        let handle = std::thread::spawn(move || {
+            for i in 500..1000u64 {
+                writer.write(&(i, "record")).unwrap();
+            }
+        });
+        assert_eq!(copy_channel(&mut reader, copy_writer).unwrap(), 1000);
+        handle.join().unwrap();
+        let records = collect_channel(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(records.len(), 1000);
+        assert_eq!(collect_channel(test_tmp_dir.path(), 2000).unwrap(), records);
+        let mut copy_reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        while let Ok(Some(_)) = copy_reader.try_read() {}
+        assert_eq!(copy_reader.exhausted(), Some(ReadError::Closed));
+    }
+}
@@ -0,0 +1,119 @@
+//! Provides the export of channel contents as newline delimited JSON.
+use super::shm_reader;
+use crate::api::ChannelError::AccessError;
+use crate::api::{ChannelError, ReadError, Reader};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+
+/// Exports the records of a channel as newline delimited JSON, one line per record, so the channel
+/// could be piped into log pipelines or inspected with text tools. Every line holds the record offset,
+/// its length and its data, as `{"offset":N,"len":L,"encoding":"utf8","data":"..."}`. Records which are
+/// valid UTF-8 are exported as strings, any other record is exported base64 encoded, in which case the
+/// `encoding` is `base64`. Heartbeats are skipped. The export stops at the end of the available records.
+///
+/// Returns the number of records exported.
+///
+/// # Arguments
+///
+/// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+/// * `channel_id` - The channel identifier.
+/// * `out` - Where the JSON lines are written
+///
+/// # Errors
+///
+/// If the channel cannot be read, it is corrupted, or the JSON lines cannot be written.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Hello").unwrap();
+/// let mut out = Vec::new();
+/// assert_eq!(export_ndjson(&test_tmp_dir.path(), 42, &mut out).unwrap(), 1);
+/// assert_eq!(out, b"{\"data\":\"Hello\",\"encoding\":\"utf8\",\"len\":5,\"offset\":0}\n");
+/// ```
+pub fn export_ndjson(root_path: &Path, channel_id: u64, out: &mut impl Write) -> Result<usize, ChannelError> {
+    let mut reader = shm_reader(root_path, channel_id)?;
+    let mut count = 0;
+    loop {
+        let mut offset = reader.position();
+        while let Some((next_offset, 0)) = reader.next_record_offset(offset) {
+            offset = next_offset;
+        }
+        match reader.try_read() {
+            Ok(Some(record)) => {
+                let line = match std::str::from_utf8(record) {
+                    Ok(text) => json!({"offset": offset, "len": record.len(), "encoding": "utf8", "data": text}),
+                    Err(_) => {
+                        json!({"offset": offset, "len": record.len(), "encoding": "base64", "data": STANDARD.encode(record)})
+                    }
+                };
+                writeln!(out, "{}", line).map_err(|err| AccessError { reason: err.to_string() })?;
+                count += 1;
+            }
+            Ok(None) | Err(ReadError::Closed) => break,
+            Err(err) => {
+                return Err(AccessError {
+                    reason: format!("Channel read failed at position {}: {:?}", offset, err),
+                })
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_writer, Metadata};
+    use serde_json::Value;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn export_text_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let records = ["first", "second \"quoted\"", "third\nline"];
+        for rec in records.iter() {
+            writer.write(rec).unwrap();
+            writer.heartbeat().unwrap();
+        }
+        writer.write(&[0xFFu8, 0xFE]).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(export_ndjson(test_tmp_dir.path(), 1000, &mut out).unwrap(), 4);
+        let lines: Vec<Value> = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        let mut offset = 0;
+        for (line, rec) in lines.iter().zip(records.iter()) {
+            assert_eq!(line["offset"], offset);
+            assert_eq!(line["len"], rec.len());
+            assert_eq!(line["encoding"], "utf8");
+            assert_eq!(line["data"], *rec);
+            offset += metadata.bytes_per_record(rec.len() as u32) + 8;
+        }
+        assert_eq!(lines[3]["offset"], offset);
+        assert_eq!(lines[3]["encoding"], "base64");
+        assert_eq!(lines[3]["data"], "//4=");
+        drop(writer);
+        let mut out = Vec::new();
+        assert_eq!(export_ndjson(test_tmp_dir.path(), 1000, &mut out).unwrap(), 4);
+        assert!(export_ndjson(test_tmp_dir.path(), 2000, &mut out).is_err());
+    }
+}
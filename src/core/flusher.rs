@@ -0,0 +1,205 @@
+//! Provides a flusher which persists the records of a channel from a background thread.
+use memmap::MmapMut;
+use std::io::Error;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Flushes, at a given time interval, the records written into a channel from a dedicated thread,
+/// so the writer never blocks on a flush operation. The writer publishes the channel frontier, and the
+/// flusher persists only the range written since the previous flush. When it stops, the flusher
+/// performs a final flush. A `BackgroundFlusher` must be created using the
+/// [background_flusher](struct.ShmWriter.html#method.background_flusher) method of a writer.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use std::time::Duration;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let flusher = writer.background_flusher(Duration::from_millis(10)).unwrap();
+/// writer.write(&"Persisted in background").unwrap();
+/// let flushed = flusher.stop().unwrap();
+/// assert_eq!(flushed, writer.write_offset());
+/// ```
+#[derive(Debug)]
+pub struct BackgroundFlusher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<u32, Error>>>,
+}
+
+impl BackgroundFlusher {
+    pub(super) fn start(
+        mmap: Arc<MmapMut>,
+        data_offset: usize,
+        frontier: Arc<AtomicU32>,
+        interval: Duration,
+    ) -> Result<BackgroundFlusher, Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let handle = std::thread::Builder::new()
+            .name("kekbit-flusher".to_string())
+            .spawn(move || {
+                let mut flushed_pos = 0usize;
+                let mut flushed = 0u32;
+                let mut failure = None;
+                loop {
+                    let stopping = stop_flag.load(Ordering::Acquire);
+                    let crt = frontier.load(Ordering::Acquire);
+                    if crt > flushed || flushed_pos == 0 {
+                        //the WATERMARK which follows the last record is flushed as well
+                        let end = (data_offset + crt as usize + 8).min(mmap.len());
+                        match mmap.flush_range(flushed_pos, end - flushed_pos) {
+                            Ok(_) => {
+                                flushed_pos = data_offset + crt as usize;
+                                flushed = crt;
+                            }
+                            Err(err) => {
+                                error!("Background flush failed {}", err);
+                                failure = Some(err);
+                            }
+                        }
+                    }
+                    if stopping {
+                        break;
+                    }
+                    std::thread::park_timeout(interval);
+                }
+                info!("Background flusher stopped. Flushed up to {}", flushed);
+                failure.map_or(Ok(flushed), Err)
+            })?;
+        Ok(BackgroundFlusher {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stops the flusher, after a final flush.
+    ///
+    /// Returns the channel offset up to which the records were flushed.
+    ///
+    /// # Errors
+    ///
+    /// The last error reported by a flush operation, if any.
+    #[inline]
+    pub fn stop(mut self) -> Result<u32, Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<u32, Error> {
+        self.stop.store(true, Ordering::Release);
+        match self.handle.take() {
+            Some(handle) => {
+                handle.thread().unpark();
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(Error::other("Background flusher panicked")))
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for BackgroundFlusher {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            error!("Background flusher failed {}", err);
+        }
+    }
+}
+
+//Decides when a writer flushes the records it wrote, so it flushes at most once per time interval.
+//The time is passed in by the writer, measured in the channel tick unit.
+#[derive(Debug)]
+pub(super) struct FlushInterval {
+    interval: u64,
+    last_flush: u64,
+    flushed: u32,
+}
+
+impl FlushInterval {
+    #[inline]
+    pub(super) fn new(interval: u64, now: u64, flushed: u32) -> FlushInterval {
+        FlushInterval {
+            interval,
+            last_flush: now,
+            flushed,
+        }
+    }
+
+    //Returns the range of the channel written since the last flush, if the interval has elapsed.
+    #[inline]
+    pub(super) fn due(&mut self, now: u64, write_offset: u32) -> Option<Range<u32>> {
+        if now.saturating_sub(self.last_flush) < self.interval || write_offset <= self.flushed {
+            return None;
+        }
+        let range = self.flushed..write_offset;
+        self.last_flush = now;
+        self.flushed = write_offset;
+        Some(range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_writer, storage_path, FrameReader, Metadata};
+    use std::convert::TryInto;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn flush_in_background() {
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let flusher = writer.background_flusher(Duration::from_millis(1)).unwrap();
+        for i in 0..2000u64 {
+            writer.write(&i).unwrap();
+            if i % 100 == 0 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        assert_eq!(flusher.stop().unwrap(), writer.write_offset());
+        let data = std::fs::read(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        let frames: Vec<u64> = FrameReader::new(&data[metadata.len()..])
+            .map(|frame| u64::from_le_bytes(frame.unwrap().payload.try_into().unwrap()))
+            .collect();
+        assert_eq!(frames, (0..2000).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn flush_at_most_once_per_interval() {
+        //the clock is injected by the writer, a burst of writes within 100 ticks is flushed only once
+        let mut policy = FlushInterval::new(100, 1000, 0);
+        let flushes: Vec<_> = (0..50u32)
+            .filter_map(|i| policy.due(1100 + u64::from(i), (i + 1) * 16))
+            .collect();
+        assert_eq!(flushes, vec![0..16]);
+        assert_eq!(policy.due(1199, 1000), None);
+        assert_eq!(policy.due(1200, 1000), Some(16..1000));
+        //nothing new was written
+        assert_eq!(policy.due(5000, 1000), None);
+        assert_eq!(policy.due(5001, 1016), Some(1000..1016));
+        let metadata = Metadata::new(100, 1000, 300_000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.flush_interval(Duration::from_millis(1));
+        for i in 0..2000u64 {
+            writer.write(&i).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(2));
+        writer.write(&2000u64).unwrap();
+        let data = std::fs::read(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        assert_eq!(FrameReader::new(&data[metadata.len()..]).count(), 2001);
+    }
+}
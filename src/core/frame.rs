@@ -0,0 +1,178 @@
+//! Provides a parser of the record framing of channel data held in memory.
+use super::utils::{align, strip_flags, CLOSE, COMPRESSED, CONTINUED, HEARTBEAT, REC_HEADER_LEN, U64_SIZE, WATERMARK};
+use crate::api::ReadError;
+use std::convert::TryInto;
+use std::iter::FusedIterator;
+
+/// A record parsed by a [FrameReader](struct.FrameReader.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// The offset of the record in the data region
+    pub offset: u32,
+    /// The record payload, as it is stored in the channel
+    pub payload: &'a [u8],
+    /// True if the payload is compressed, see [CompressionHandler](struct.CompressionHandler.html)
+    pub compressed: bool,
+    /// True if the payload is a chunk followed by the rest of a chunked record, see
+    /// [write_chunked](struct.ShmWriter.html#method.write_chunked)
+    pub continued: bool,
+}
+
+/// An iterator over the records of a channel data region held in a byte buffer, rather than in a
+/// memory mapped file. It is useful to parse and validate channel data copied out-of-band, e.g. sent
+/// over a socket or stored in a blob store. The buffer must hold only the data region of a channel,
+/// without the channel metadata.
+///
+/// The iterator ends when it reaches a `WATERMARK` or the end of the buffer. A closed channel or a corrupted
+/// record is reported as an error, after which the iterator ends. Heartbeats are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::FrameReader;
+///
+/// let mut data = Vec::new();
+/// data.extend_from_slice(&5u64.to_le_bytes());
+/// data.extend_from_slice(b"hello\0\0\0");
+/// data.extend_from_slice(&u64::MAX.to_le_bytes());
+/// let mut frames = FrameReader::new(&data);
+/// assert_eq!(frames.next().unwrap().unwrap().payload, b"hello");
+/// assert!(frames.next().unwrap().is_err());
+/// assert!(frames.next().is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    rec_header_len: u32,
+    done: bool,
+}
+
+impl<'a> FrameReader<'a> {
+    /// Creates an iterator over the records held in the given channel data region.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data region of a channel
+    #[inline]
+    pub fn new(data: &'a [u8]) -> FrameReader<'a> {
+        FrameReader::with_rec_header_len(data, REC_HEADER_LEN)
+    }
+
+    /// Creates an iterator over the records held in the given channel data region, for a channel with
+    /// a non default [record header length](struct.Metadata.html#method.with_rec_header_len).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data region of a channel
+    /// * `rec_header_len` - The length of the record header
+    #[inline]
+    pub fn with_rec_header_len(data: &'a [u8], rec_header_len: u32) -> FrameReader<'a> {
+        assert!(rec_header_len >= REC_HEADER_LEN);
+        FrameReader {
+            data,
+            offset: 0,
+            rec_header_len,
+            done: false,
+        }
+    }
+
+    ///Returns the offset of the next record to be parsed.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn fail(&mut self, failure: ReadError) -> Option<Result<Frame<'a>, ReadError>> {
+        self.done = true;
+        Some(Err(failure))
+    }
+}
+
+impl<'a> Iterator for FrameReader<'a> {
+    type Item = Result<Frame<'a>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done && self.offset + U64_SIZE <= self.data.len() {
+            let header = u64::from_le_bytes(self.data[self.offset..self.offset + U64_SIZE].try_into().unwrap());
+            match strip_flags(header) {
+                HEARTBEAT => self.offset += self.rec_header_len as usize,
+                WATERMARK => self.done = true,
+                CLOSE => return self.fail(ReadError::Closed),
+                rec_len if rec_len <= u32::MAX as u64 => {
+                    let start = self.offset + self.rec_header_len as usize;
+                    let end = start + rec_len as usize;
+                    if end > self.data.len() {
+                        return self.fail(ReadError::Failed);
+                    }
+                    let frame = Frame {
+                        offset: self.offset as u32,
+                        payload: &self.data[start..end],
+                        compressed: header & COMPRESSED != 0,
+                        continued: header & CONTINUED != 0,
+                    };
+                    self.offset += align(self.rec_header_len + rec_len as u32) as usize;
+                    return Some(Ok(frame));
+                }
+                _ => return self.fail(ReadError::Failed),
+            }
+        }
+        None
+    }
+}
+
+impl<'a> FusedIterator for FrameReader<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn push_record(data: &mut Vec<u8>, header: u64, payload: &[u8]) {
+        data.extend_from_slice(&header.to_le_bytes());
+        data.extend_from_slice(payload);
+        data.resize(align(data.len() as u32) as usize, 0);
+    }
+
+    #[test]
+    fn parse_records() {
+        let mut data = Vec::new();
+        push_record(&mut data, 5, b"first");
+        push_record(&mut data, HEARTBEAT, b"");
+        push_record(&mut data, 8, b"second!!");
+        push_record(&mut data, 4 | COMPRESSED, b"zzzz");
+        push_record(&mut data, 2 | CONTINUED, b"ch");
+        push_record(&mut data, WATERMARK, b"");
+        let frames: Vec<Frame> = FrameReader::new(&data).map(|f| f.unwrap()).collect();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].payload, b"first");
+        assert_eq!(frames[1].offset, 24);
+        assert_eq!(frames[1].payload, b"second!!");
+        assert!(!frames[1].compressed);
+        assert!(frames[2].compressed);
+        assert!(!frames[2].continued);
+        assert!(frames[3].continued);
+        assert_eq!(frames[3].payload, b"ch");
+        //a truncated buffer just ends
+        assert_eq!(FrameReader::new(&data[..20]).count(), 1);
+    }
+
+    #[test]
+    fn parse_closed_and_corrupted() {
+        let mut data = Vec::new();
+        push_record(&mut data, 3, b"abc");
+        push_record(&mut data, CLOSE, b"");
+        let mut frames = FrameReader::new(&data);
+        assert_eq!(frames.next().unwrap().unwrap().payload, b"abc");
+        assert_eq!(frames.next().unwrap(), Err(ReadError::Closed));
+        assert_eq!(frames.next(), None);
+        let mut data = Vec::new();
+        push_record(&mut data, 3, b"abc");
+        push_record(&mut data, 100, b"too short");
+        let mut frames = FrameReader::new(&data);
+        assert!(frames.next().unwrap().is_ok());
+        assert_eq!(frames.next().unwrap(), Err(ReadError::Failed));
+        assert_eq!(frames.position(), 16);
+        assert_eq!(frames.next(), None);
+    }
+}
@@ -1,8 +1,12 @@
 use crate::api::Encodable;
 use crate::api::Handler;
-use crate::core::TickUnit;
+use crate::core::{read_u64_at, TickUnit};
 use std::io::Result;
 use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Handler which adds a timestamp to a record using a given tick unit.
 /// This is probably the most used decorator.
@@ -29,6 +33,100 @@ impl TimestampHandler {
     }
 }
 
+/// A coarse monotonic clock, which is updated by a background thread at a given resolution. Reading it is a simple
+/// atomic load, so it is much cheaper than a system call, at the price of precision. The background thread stops
+/// soon after the clock and all its clones are dropped.
+///
+/// The clock is anchored to the system time when it starts, and it then advances with the monotonic clock, so
+/// it never goes backwards, even if the system time is adjusted. Over long periods it may drift apart from the
+/// system time.
+#[derive(Debug, Clone)]
+pub struct CoarseClock {
+    tick: TickUnit,
+    now: Arc<AtomicU64>,
+}
+
+impl CoarseClock {
+    /// Starts a coarse clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The tick unit used to measure the time
+    /// * `resolution` - The time interval between two clock updates
+    ///
+    /// # Errors
+    ///
+    /// If the thread which updates the clock cannot be started.
+    pub fn start(tick: TickUnit, resolution: Duration) -> Result<CoarseClock> {
+        let start = Instant::now();
+        let start_time = tick.nix_time();
+        let now = Arc::new(AtomicU64::new(start_time));
+        let clock: Weak<AtomicU64> = Arc::downgrade(&now);
+        thread::Builder::new().name("kekbit-clock".to_string()).spawn(move || loop {
+            thread::sleep(resolution);
+            match clock.upgrade() {
+                Some(now) => now.store(start_time + tick.convert(start.elapsed()), Ordering::Release),
+                None => break,
+            }
+        })?;
+        Ok(CoarseClock { tick, now })
+    }
+
+    /// Returns the time of the last clock update, measured in the clock tick unit since midnight, January 1, 1970 UTC.
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.now.load(Ordering::Acquire)
+    }
+
+    /// Returns the tick unit of this clock.
+    #[inline]
+    pub fn tick_unit(&self) -> TickUnit {
+        self.tick
+    }
+}
+
+/// Handler which adds a timestamp to a record like the [TimestampHandler](struct.TimestampHandler.html), but reads
+/// the time from a [CoarseClock](struct.CoarseClock.html) rather than the system clock. It should be used for high
+/// rate channels, where the cost of a system call for every record matters more than the timestamp precision.
+/// The timestamps written by a handler never decrease.
+#[derive(Debug, Clone)]
+pub struct CachedTimestampHandler {
+    clock: CoarseClock,
+    last: u64,
+}
+
+impl CachedTimestampHandler {
+    /// Creates a handler which uses its own coarse clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The tick unit used for timestamps
+    /// * `resolution` - The time interval between two clock updates
+    ///
+    /// # Errors
+    ///
+    /// If the clock cannot be started.
+    #[inline]
+    pub fn new(tick: TickUnit, resolution: Duration) -> Result<CachedTimestampHandler> {
+        CoarseClock::start(tick, resolution).map(CachedTimestampHandler::with_clock)
+    }
+
+    /// Creates a handler which uses the given clock, so many writers could share a single clock.
+    #[inline]
+    pub fn with_clock(clock: CoarseClock) -> CachedTimestampHandler {
+        CachedTimestampHandler { clock, last: 0 }
+    }
+}
+
+impl Handler for CachedTimestampHandler {
+    ///Writes the time of the coarse clock into a channel before a record.
+    #[inline]
+    fn incoming(&mut self, _data: &impl Encodable, w: &mut impl Write) -> Result<usize> {
+        self.last = self.last.max(self.clock.now());
+        w.write(&self.last.to_le_bytes())
+    }
+}
+
 ///Handler which adds a sequence id to a record.
 #[derive(Default, Debug)]
 #[repr(transparent)]
@@ -47,6 +145,18 @@ impl SequenceHandler {
     pub fn new(seq: u64) -> SequenceHandler {
         SequenceHandler { seq }
     }
+
+    ///Returns the sequence number written by a SequenceHandler at the beginning of a record,
+    ///or None if the record is too short to hold one.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A record written by a handler chain which starts with a SequenceHandler
+    ///
+    #[inline]
+    pub fn sequence_of(record: &[u8]) -> Option<u64> {
+        read_u64_at(record, 0)
+    }
 }
 
 impl Handler for SequenceHandler {
@@ -91,6 +201,141 @@ impl<H: Handler, D: Handler> Handler for ChainedHandler<H, D> {
             .and_then(|_| self.handler.handle(d, w))
             .and_then(|_| self.decorator.outgoing(d, w))
     }
+
+    #[inline]
+    fn rec_flags(&self) -> u64 {
+        self.decorator.rec_flags() | self.handler.rec_flags()
+    }
+}
+
+/// A handler which applies a list of handlers, avoiding the deeply nested types created by
+/// [ChainedHandler](struct.ChainedHandler.html). It is created using a [HandlerChainBuilder](struct.HandlerChainBuilder.html).
+/// The handlers are applied in the order they were added: the `incoming` methods of all but the last handler run first,
+/// then the last handler, usually an `EncoderHandler`, is invoked using its `handle` method, then the `outgoing`
+/// methods run in reverse order.
+///
+/// As the chained handlers are used through dynamic dispatch, the record is first encoded into an internal buffer,
+/// and every handler receives the encoded bytes.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// let handler = HandlerChain::builder()
+///     .add(TimestampHandler::new(TickUnit::Nanos))
+///     .add(SequenceHandler::default())
+///     .add(EncoderHandler::default())
+///     .build();
+/// ```
+pub struct HandlerChain {
+    handlers: Vec<Box<dyn DynHandler>>,
+    buf: Vec<u8>,
+}
+
+impl HandlerChain {
+    /// Returns a builder for an empty chain of handlers.
+    #[inline]
+    pub fn builder() -> HandlerChainBuilder {
+        HandlerChainBuilder { handlers: Vec::new() }
+    }
+
+    /// Returns the number of handlers in this chain.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Returns true if this chain has no handlers, in which case it writes nothing.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+impl Handler for HandlerChain {
+    #[inline]
+    fn handle(&mut self, d: &impl Encodable, mut w: &mut impl Write) -> Result<usize> {
+        let (last, decorators) = match self.handlers.split_last_mut() {
+            Some(split) => split,
+            None => return Ok(0),
+        };
+        self.buf.clear();
+        d.encode(&mut self.buf)?;
+        let mut written = 0;
+        for handler in decorators.iter_mut() {
+            written += handler.dyn_incoming(&self.buf, &mut w)?;
+        }
+        written += last.dyn_handle(&self.buf, &mut w)?;
+        for handler in decorators.iter_mut().rev() {
+            written += handler.dyn_outgoing(&self.buf, &mut w)?;
+        }
+        Ok(written)
+    }
+
+    #[inline]
+    fn rec_flags(&self) -> u64 {
+        self.handlers.iter().fold(0, |flags, handler| flags | handler.dyn_rec_flags())
+    }
+}
+
+/// Collects the handlers of a [HandlerChain](struct.HandlerChain.html).
+pub struct HandlerChainBuilder {
+    handlers: Vec<Box<dyn DynHandler>>,
+}
+
+impl HandlerChainBuilder {
+    /// Appends a handler to the chain. The last handler added is the one which should write the record itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - The handler to be added
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<H: Handler + 'static>(mut self, handler: H) -> HandlerChainBuilder {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Creates the chain of handlers.
+    #[inline]
+    pub fn build(self) -> HandlerChain {
+        HandlerChain {
+            handlers: self.handlers,
+            buf: Vec::new(),
+        }
+    }
+}
+
+//An object safe view of a handler, used to keep handlers of different types in the same chain
+trait DynHandler {
+    fn dyn_incoming(&mut self, data: &[u8], w: &mut dyn Write) -> Result<usize>;
+    fn dyn_outgoing(&mut self, data: &[u8], w: &mut dyn Write) -> Result<usize>;
+    fn dyn_handle(&mut self, data: &[u8], w: &mut dyn Write) -> Result<usize>;
+    fn dyn_rec_flags(&self) -> u64;
+}
+
+impl<H: Handler> DynHandler for H {
+    #[inline]
+    fn dyn_incoming(&mut self, data: &[u8], mut w: &mut dyn Write) -> Result<usize> {
+        self.incoming(&data, &mut w)
+    }
+
+    #[inline]
+    fn dyn_outgoing(&mut self, data: &[u8], mut w: &mut dyn Write) -> Result<usize> {
+        self.outgoing(&data, &mut w)
+    }
+
+    #[inline]
+    fn dyn_handle(&mut self, data: &[u8], mut w: &mut dyn Write) -> Result<usize> {
+        self.handle(&data, &mut w)
+    }
+
+    #[inline]
+    fn dyn_rec_flags(&self) -> u64 {
+        self.rec_flags()
+    }
 }
 
 #[cfg(test)]
@@ -119,11 +364,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cached_ts_handler() {
+        let tick = TickUnit::Nanos;
+        let before = tick.nix_time();
+        let mut ts_handler = CachedTimestampHandler::new(tick, Duration::from_millis(1)).unwrap();
+        let c = &mut std::io::Cursor::new(Vec::new());
+        for _ in 0..20 {
+            ts_handler.handle(&"Doesn't matter".to_string(), c).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let after = tick.nix_time();
+        c.set_position(0);
+        let mut stamps = Vec::new();
+        for _i in 0..20 {
+            let mut res = vec![0u8; 8];
+            c.read_exact(&mut res).unwrap();
+            stamps.push(u64::from_le_bytes(res[..].try_into().unwrap()));
+        }
+        assert!(stamps.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(stamps[0] >= before);
+        assert!(stamps[19] > stamps[0]);
+        assert!(stamps[19] <= after);
+    }
+
+    #[test]
+    fn cached_ts_never_decreases() {
+        const STAMPS: u32 = 100_000;
+        let tick = TickUnit::Nanos;
+        let clock = CoarseClock::start(tick, Duration::from_millis(1)).unwrap();
+        let mut cached = CachedTimestampHandler::with_clock(clock.clone());
+        let mut buf = [0u8; 8];
+        let mut last = 0;
+        for _ in 0..STAMPS {
+            cached.incoming(&0u8, &mut &mut buf[..]).unwrap();
+            let stamp = u64::from_le_bytes(buf);
+            assert!(stamp >= last);
+            last = stamp;
+        }
+        assert!(last <= clock.now());
+    }
+
     #[test]
     fn test_seq_handler() {
         let mut seq_handler = SequenceHandler::new(47);
         assert_eq!(seq_handler.seq, 47);
-        let expected = vec![48, 49, 50];
+        let expected = [48, 49, 50];
         let c = &mut std::io::Cursor::new(Vec::new());
         seq_handler.handle(&"Doesn't matter".to_string(), c).unwrap();
         seq_handler.handle(&"Doesn't matter".to_string(), c).unwrap();
@@ -153,7 +439,29 @@ mod tests {
         let mut chain = ChainedHandler::link(l3, h5);
         let c = &mut std::io::Cursor::new(Vec::new());
         chain.handle(&"Doesn't matter".to_string(), c).unwrap();
-        let expected = vec![-1, 3, 2, 1, 1, 2, 3, -1];
+        let expected = [-1, 3, 2, 1, 1, 2, 3, -1];
+        c.set_position(0);
+        for exp in expected.iter() {
+            let mut res = vec![0u8; 8];
+            c.read_exact(&mut res).unwrap();
+            let id = i64::from_le_bytes(res[..].try_into().unwrap());
+            assert_eq!(*exp, id);
+        }
+    }
+
+    #[test]
+    fn test_handler_chain() {
+        let mut chain = HandlerChain::builder()
+            .add(OutHandler::default())
+            .add(InHandler::default())
+            .add(IdHandler { id: 3 })
+            .add(IdHandler { id: 2 })
+            .add(IdHandler { id: 1 })
+            .build();
+        assert_eq!(chain.len(), 5);
+        let c = &mut std::io::Cursor::new(Vec::new());
+        chain.handle(&"Doesn't matter".to_string(), c).unwrap();
+        let expected = [-1, 3, 2, 1, 1, 2, 3, -1];
         c.set_position(0);
         for exp in expected.iter() {
             let mut res = vec![0u8; 8];
@@ -161,6 +469,7 @@ mod tests {
             let id = i64::from_le_bytes(res[..].try_into().unwrap());
             assert_eq!(*exp, id);
         }
+        assert_eq!(HandlerChain::builder().build().handle(&"Nothing", c).unwrap(), 0);
     }
 
     struct IdHandler {
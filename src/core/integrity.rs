@@ -0,0 +1,236 @@
+//! Provides a self check of the records stored in a channel.
+use super::utils::{align, is_aligned, strip_flags, CLOSE, HEARTBEAT, U64_SIZE, WATERMARK};
+use super::ShmReader;
+use crate::api::ReadError;
+
+/// An anomaly found while checking the integrity of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The length word at `offset` is neither a valid record length nor a known marker.
+    ImpossibleLength { offset: u32, len: u64 },
+    /// The record at `offset`, of length `len`, extends beyond the channel capacity.
+    Overflow { offset: u32, len: u32 },
+    /// The channel has no `WATERMARK` or `CLOSE` marker at its frontier.
+    MissingTerminal,
+    /// A `CLOSE` marker was found at `offset`, beyond the channel frontier.
+    ExtraCloseMarker { offset: u32 },
+    /// The reader position, `offset`, is not the beginning of a record.
+    MisalignedPosition { offset: u32 },
+    /// The check walked more records or bytes than the reader [scan bound](struct.ScanBound.html) allows,
+    /// the last one being at `offset`.
+    ScanLimitExceeded { offset: u32 },
+}
+
+/// The result of a channel integrity check. See [verify_integrity](struct.ShmReader.html#method.verify_integrity).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Number of records found in the channel, heartbeats excluded
+    pub records: u32,
+    /// Number of heartbeats found in the channel
+    pub heartbeats: u32,
+    /// Offset of the terminal marker, if one was found
+    pub frontier: Option<u32>,
+    /// True if the terminal marker is `CLOSE`
+    pub closed: bool,
+    /// All the anomalies found
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl IntegrityReport {
+    /// Returns true if no anomaly was found
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+impl ShmReader {
+    /// Walks the whole channel, from its beginning, and checks that every length word is either a valid
+    /// record length or a known marker, that exactly one terminal marker(`WATERMARK` or `CLOSE`) exists
+    /// at the channel frontier, and that the reader position is the beginning of a record.
+    /// The walk stops at the first length word which cannot be trusted, as the rest of the channel
+    /// cannot be reached. It never changes the reader state.
+    ///
+    /// This method is meant for tests and debugging, e.g. after a suspected crash. The channel
+    /// should not be written while checked, otherwise spurious anomalies may be reported.
+    ///
+    /// # Errors
+    ///
+    /// `StorageVanished` if the channel storage was truncated, or `VersionChanged` if the storage version had
+    /// changed since the reader was created, as the channel cannot be safely walked anymore. Like any read
+    /// failure, these errors mark the channel as exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let report = reader.verify_integrity().unwrap();
+    /// assert!(report.is_ok());
+    /// ```
+    pub fn verify_integrity(&mut self) -> Result<IntegrityReport, ReadError> {
+        if !self.validate_backing() {
+            return Err(self.record_failure(ReadError::StorageVanished));
+        }
+        if !self.validate_version() {
+            return Err(self.record_failure(ReadError::VersionChanged));
+        }
+        let capacity = self.metadata().capacity();
+        let max_msg_len = self.metadata().max_msg_len() as u64;
+        let position = self.position();
+        let rec_header_len = self.metadata().rec_header_len();
+        let mut report = IntegrityReport::default();
+        let mut position_found = false;
+        let mut offset = 0u32;
+        let scan_bound = self.scan_bound();
+        loop {
+            if offset > capacity {
+                report.anomalies.push(Anomaly::MissingTerminal);
+                break;
+            }
+            if scan_bound.exceeded(report.records + report.heartbeats, offset) {
+                report.anomalies.push(Anomaly::ScanLimitExceeded { offset });
+                break;
+            }
+            position_found |= offset == position;
+            let rec_len = strip_flags(self.word_at(offset));
+            match rec_len {
+                HEARTBEAT => {
+                    report.heartbeats += 1;
+                    offset += rec_header_len;
+                }
+                WATERMARK | CLOSE => {
+                    report.frontier = Some(offset);
+                    report.closed = rec_len == CLOSE;
+                    break;
+                }
+                len if len <= max_msg_len => {
+                    let next = offset + align(rec_header_len + len as u32);
+                    if next > capacity {
+                        report.anomalies.push(Anomaly::Overflow { offset, len: len as u32 });
+                        break;
+                    }
+                    report.records += 1;
+                    offset = next;
+                }
+                len => {
+                    report.anomalies.push(Anomaly::ImpossibleLength { offset, len });
+                    break;
+                }
+            }
+        }
+        if let Some(frontier) = report.frontier {
+            let mut tail = frontier + U64_SIZE as u32;
+            while tail as usize + U64_SIZE <= capacity as usize {
+                if self.word_at(tail) == CLOSE {
+                    report.anomalies.push(Anomaly::ExtraCloseMarker { offset: tail });
+                }
+                tail += U64_SIZE as u32;
+            }
+        }
+        if !is_aligned(position) || (!position_found && position <= offset) {
+            report.anomalies.push(Anomaly::MisalignedPosition { offset: position });
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Reader, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, storage_path, Metadata};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+    //the channel data starts right after the metadata
+    const DATA_OFFSET: u64 = 128;
+
+    fn corrupt(tmp_dir: &TempDir, channel_id: u64, offset: u32, value: u64) {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(tmp_dir.path(), channel_id))
+            .unwrap();
+        file.seek(SeekFrom::Start(DATA_OFFSET + offset as u64)).unwrap();
+        file.write_all(&value.to_le_bytes()).unwrap();
+    }
+
+    #[test]
+    fn clean_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..10u64 {
+            writer.write(&i).unwrap();
+        }
+        writer.heartbeat().unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        reader.try_read().unwrap();
+        let report = reader.verify_integrity().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.records, 10);
+        assert_eq!(report.heartbeats, 1);
+        assert_eq!(report.frontier, Some(writer.write_offset()));
+        assert!(!report.closed);
+        std::mem::drop(writer);
+        let report = reader.verify_integrity().unwrap();
+        assert!(report.is_ok());
+        assert!(report.closed);
+    }
+
+    #[test]
+    fn corrupted_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..10u64 {
+            writer.write(&i).unwrap();
+        }
+        let frontier = writer.write_offset();
+        corrupt(&test_tmp_dir, 1000, frontier + 64, u64::MAX);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(
+            reader.verify_integrity().unwrap().anomalies,
+            vec![Anomaly::ExtraCloseMarker { offset: frontier + 64 }]
+        );
+        //every u64 record takes 16 bytes
+        corrupt(&test_tmp_dir, 1000, 32, 0x1234_5678);
+        let report = reader.verify_integrity().unwrap();
+        assert_eq!(report.records, 2);
+        assert_eq!(report.frontier, None);
+        assert_eq!(
+            report.anomalies,
+            vec![Anomaly::ImpossibleLength {
+                offset: 32,
+                len: 0x1234_5678
+            }]
+        );
+        std::mem::drop(writer);
+    }
+
+    #[test]
+    fn truncated_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&1u64).unwrap();
+        std::mem::drop(writer);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        assert!(reader.verify_integrity().unwrap().is_ok());
+        let file = OpenOptions::new()
+            .write(true)
+            .open(storage_path(test_tmp_dir.path(), 1000))
+            .unwrap();
+        file.set_len(DATA_OFFSET).unwrap();
+        assert_eq!(reader.verify_integrity(), Err(ReadError::StorageVanished));
+        assert_eq!(reader.exhausted(), Some(ReadError::StorageVanished));
+    }
+}
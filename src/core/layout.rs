@@ -0,0 +1,191 @@
+//! Provides helpers to access the fields of records with a fixed binary layout.
+use super::ShmReader;
+use crate::api::Reader;
+use std::convert::TryInto;
+
+/// Views a record as a fixed size array.
+///
+/// Returns the array view, or None if the record length is not exactly `N`.
+///
+/// # Arguments
+///
+/// * `record` - A record read from a channel
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::*;
+///
+/// let record = [1u8, 2, 3, 4];
+/// assert_eq!(as_array::<4>(&record), Some(&[1, 2, 3, 4]));
+/// assert_eq!(as_array::<8>(&record), None);
+/// ```
+#[inline]
+pub fn as_array<const N: usize>(record: &[u8]) -> Option<&[u8; N]> {
+    record.try_into().ok()
+}
+
+/// Views `N` bytes of a record, starting at the given offset, as a fixed size array.
+///
+/// Returns the array view, or None if the record is too short.
+///
+/// # Arguments
+///
+/// * `record` - A record read from a channel
+/// * `offset` - The offset of the first byte of the array
+#[inline]
+pub fn array_at<const N: usize>(record: &[u8], offset: usize) -> Option<&[u8; N]> {
+    record
+        .get(offset..offset.checked_add(N)?)
+        .map(|bytes| bytes.try_into().unwrap())
+}
+
+macro_rules! read_num_at {
+    ($($name:ident: $num:ty),*) => {
+        $(
+            #[doc = concat!("Reads a little endian `", stringify!($num), "` from a record, starting at the given offset.")]
+            ///
+            /// Returns the number, or None if the record is too short to hold it.
+            ///
+            /// # Arguments
+            ///
+            /// * `record` - A record read from a channel
+            /// * `offset` - The offset of the first byte of the number
+            #[inline]
+            pub fn $name(record: &[u8], offset: usize) -> Option<$num> {
+                array_at(record, offset).map(|bytes| <$num>::from_le_bytes(*bytes))
+            }
+        )*
+    };
+}
+
+read_num_at!(
+    read_u16_at: u16,
+    read_u32_at: u32,
+    read_u64_at: u64,
+    read_i16_at: i16,
+    read_i32_at: i32,
+    read_i64_at: i64,
+    read_f32_at: f32,
+    read_f64_at: f64
+);
+
+const TIMESTAMP_FLAG: u32 = 1;
+const SEQUENCE_FLAG: u32 = 2;
+
+/// Declares the prefixes added by the handlers of a channel writer in front of every record body, so readers
+/// could strip them without knowing their byte offsets. The layout is stored in the channel
+/// [metadata](struct.Metadata.html#method.with_record_layout). The prefixes follow the order of a
+/// [HandlerChain](struct.HandlerChain.html) which adds a [TimestampHandler](struct.TimestampHandler.html)
+/// before a [SequenceHandler](struct.SequenceHandler.html): the timestamp comes first, then the sequence number,
+/// each one stored as a little endian `u64`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordLayout {
+    /// Every record starts with a timestamp.
+    pub timestamp: bool,
+    /// Every record holds a sequence number, right after the timestamp if there is one.
+    pub sequence: bool,
+}
+
+impl RecordLayout {
+    /// Returns the length of the prefixes which precede the body of every record.
+    #[inline]
+    pub fn prefix_len(&self) -> usize {
+        (self.timestamp as usize + self.sequence as usize) * 8
+    }
+
+    #[inline]
+    pub(super) fn from_bits(bits: u32) -> RecordLayout {
+        RecordLayout {
+            timestamp: bits & TIMESTAMP_FLAG != 0,
+            sequence: bits & SEQUENCE_FLAG != 0,
+        }
+    }
+
+    #[inline]
+    pub(super) fn bits(&self) -> u32 {
+        (if self.timestamp { TIMESTAMP_FLAG } else { 0 }) | (if self.sequence { SEQUENCE_FLAG } else { 0 })
+    }
+
+    /// Splits a record into the fields declared by this layout and its body.
+    ///
+    /// Returns the structured record. A field is None if it is not declared, or the record is too short to hold it.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - A record read from a channel
+    #[inline]
+    pub fn split<'a>(&self, record: &'a [u8]) -> StructuredRecord<'a> {
+        let timestamp = if self.timestamp { read_u64_at(record, 0) } else { None };
+        let sequence = if self.sequence {
+            read_u64_at(record, self.timestamp as usize * 8)
+        } else {
+            None
+        };
+        StructuredRecord {
+            timestamp,
+            sequence,
+            body: record.get(self.prefix_len()..).unwrap_or(&[]),
+        }
+    }
+}
+
+/// A record split into the fields declared by the [record layout](struct.RecordLayout.html) of its channel, and its body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructuredRecord<'a> {
+    /// The timestamp of the record, if the channel declares one.
+    pub timestamp: Option<u64>,
+    /// The sequence number of the record, if the channel declares one.
+    pub sequence: Option<u64>,
+    /// The record without the declared prefixes.
+    pub body: &'a [u8],
+}
+
+///A non-blocking iterator over the records currently available in the channel, split according to the
+///record layout of the channel. See [structured_iter](struct.ShmReader.html#method.structured_iter).
+///The iterator ends when no more records are available or the channel is exhausted.
+pub struct StructuredIter<'a> {
+    pub(super) inner: &'a mut ShmReader,
+    pub(super) layout: RecordLayout,
+}
+
+impl<'a> Iterator for StructuredIter<'a> {
+    type Item = StructuredRecord<'a>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(Some(record)) => Some(self.layout.split(record)),
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed_layout_record() {
+        //a request id, a quantity, a price and a side flag
+        let mut record = Vec::new();
+        record.extend_from_slice(&42u64.to_le_bytes());
+        record.extend_from_slice(&(-7i32).to_le_bytes());
+        record.extend_from_slice(&99.5f64.to_le_bytes());
+        record.extend_from_slice(&1u16.to_le_bytes());
+        assert_eq!(record.len(), 22);
+        assert_eq!(read_u64_at(&record, 0), Some(42));
+        assert_eq!(read_i32_at(&record, 8), Some(-7));
+        assert_eq!(read_f64_at(&record, 12), Some(99.5));
+        assert_eq!(read_u16_at(&record, 20), Some(1));
+        assert_eq!(read_u16_at(&record, 21), None);
+        assert_eq!(read_u64_at(&record, usize::MAX), None);
+        assert_eq!(array_at::<4>(&record, 8), Some(&(-7i32).to_le_bytes()));
+        let fixed: &[u8; 22] = as_array(&record).unwrap();
+        assert_eq!(fixed[..8], 42u64.to_le_bytes());
+        assert!(as_array::<21>(&record).is_none());
+        assert!(as_array::<23>(&record).is_none());
+    }
+}
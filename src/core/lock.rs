@@ -0,0 +1,35 @@
+//! Provides the guard of the lock which hides a channel from readers while it is initialized.
+use std::fs::{remove_file, File};
+use std::path::PathBuf;
+
+/// Holds the lock file of a channel created by [shm_writer_locked](fn.shm_writer_locked.html).
+/// While the lock is held, readers which try to attach to the channel fail with
+/// [StorageNotReady](../api/enum.ChannelError.html#variant.StorageNotReady). The lock is
+/// released, and the channel becomes visible to readers, when the guard is [released](#method.release)
+/// or dropped. The guard keeps the lock file locked, so a lock file left behind by a writer which crashed
+/// is known to be stale, and it does not block the channel.
+#[derive(Debug)]
+pub struct LockGuard {
+    path: PathBuf,
+    _file: File,
+}
+
+impl LockGuard {
+    #[inline]
+    pub(super) fn new(path: PathBuf, file: File) -> LockGuard {
+        LockGuard { path, _file: file }
+    }
+
+    /// Releases the lock, so the channel becomes visible to readers.
+    #[inline]
+    pub fn release(self) {}
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        match remove_file(&self.path) {
+            Ok(_) => info!("Kekbit lock file {:?} removed", self.path),
+            Err(err) => error!("Could not remove kekbit lock file {:?}: {}", self.path, err),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Provides a reader which measures the latency and the throughput of a channel.
+use super::{read_u64_at, TickUnit};
+use crate::api::{ReadError, Reader};
+use hdrhistogram::Histogram;
+use std::time::Instant;
+
+/// A snapshot of the measurements taken by a [MeasuringReader](struct.MeasuringReader.html).
+/// All the latencies are expressed in the tick unit of the measuring reader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    /// Number of records measured
+    pub records: u64,
+    /// Minimum latency
+    pub min: u64,
+    /// Maximum latency
+    pub max: u64,
+    /// Mean latency
+    pub mean: f64,
+    /// Median latency
+    pub p50: u64,
+    /// 99th percentile latency
+    pub p99: u64,
+    /// 99.9th percentile latency
+    pub p999: u64,
+    /// Records read per second, between the first and the last measured record
+    pub throughput: f64,
+}
+
+/// The distribution of the gaps between the timestamps embedded into consecutive records, as measured by a
+/// [MeasuringReader](struct.MeasuringReader.html). Large gaps usually reveal producer stalls.
+/// All the gaps are expressed in the tick unit of the measuring reader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JitterReport {
+    /// Number of gaps measured, one less than the number of records measured
+    pub gaps: u64,
+    /// Minimum gap
+    pub min: u64,
+    /// Maximum gap
+    pub max: u64,
+    /// Mean gap
+    pub mean: f64,
+    /// Standard deviation of the gaps
+    pub std_dev: f64,
+}
+
+/// A [Reader](../api/trait.Reader.html) which measures the end-to-end latency of the records read from a channel,
+/// as the difference between the time a record is read and the timestamp embedded into the record by a
+/// [TimestampHandler](struct.TimestampHandler.html). The latencies are recorded into a histogram, and
+/// the read throughput is tracked as well. The gaps between the timestamps of consecutive records are also
+/// recorded, see [jitter_report](#method.jitter_report). Records too short to hold a timestamp are not measured.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Micros;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Micros);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let handler = ChainedHandler::link(EncoderHandler::default(), TimestampHandler::new(Micros));
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, handler).unwrap();
+/// writer.write(&"Measured").unwrap();
+/// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let mut measuring_reader = MeasuringReader::new(reader, Micros);
+/// measuring_reader.try_read().unwrap();
+/// assert_eq!(measuring_reader.snapshot().records, 1);
+/// ```
+#[derive(Debug)]
+pub struct MeasuringReader<R: Reader> {
+    inner: R,
+    tick: TickUnit,
+    timestamp_offset: usize,
+    latencies: Histogram<u64>,
+    gaps: Histogram<u64>,
+    last_timestamp: Option<u64>,
+    first_read: Option<Instant>,
+    last_read: Option<Instant>,
+}
+
+impl<R: Reader> MeasuringReader<R> {
+    /// Creates a reader which measures the records which start with a timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be measured
+    /// * `tick` - The tick unit used by the timestamps embedded into the records
+    #[inline]
+    pub fn new(reader: R, tick: TickUnit) -> MeasuringReader<R> {
+        MeasuringReader::with_timestamp_offset(reader, tick, 0)
+    }
+
+    /// Creates a reader which measures the records which hold a timestamp at a given offset. The offset depends on
+    /// the handlers which decorated the record before the [TimestampHandler](struct.TimestampHandler.html).
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be measured
+    /// * `tick` - The tick unit used by the timestamps embedded into the records
+    /// * `timestamp_offset` - The offset of the timestamp in every record
+    #[inline]
+    pub fn with_timestamp_offset(reader: R, tick: TickUnit, timestamp_offset: usize) -> MeasuringReader<R> {
+        MeasuringReader {
+            inner: reader,
+            tick,
+            timestamp_offset,
+            latencies: Histogram::new(3).unwrap(),
+            gaps: Histogram::new(3).unwrap(),
+            last_timestamp: None,
+            first_read: None,
+            last_read: None,
+        }
+    }
+
+    /// Returns the measurements taken so far.
+    pub fn snapshot(&self) -> LatencyReport {
+        let records = self.latencies.len();
+        let throughput = match (self.first_read, self.last_read) {
+            (Some(first), Some(last)) if last > first => (records - 1) as f64 / (last - first).as_secs_f64(),
+            _ => 0.0,
+        };
+        LatencyReport {
+            records,
+            min: self.latencies.min(),
+            max: self.latencies.max(),
+            mean: self.latencies.mean(),
+            p50: self.latencies.value_at_quantile(0.5),
+            p99: self.latencies.value_at_quantile(0.99),
+            p999: self.latencies.value_at_quantile(0.999),
+            throughput,
+        }
+    }
+
+    /// Returns the distribution of the gaps between the timestamps of the records measured so far.
+    /// A record stamped earlier than its predecessor counts as a zero gap.
+    pub fn jitter_report(&self) -> JitterReport {
+        JitterReport {
+            gaps: self.gaps.len(),
+            min: self.gaps.min(),
+            max: self.gaps.max(),
+            mean: self.gaps.mean(),
+            std_dev: self.gaps.stdev(),
+        }
+    }
+
+    /// Discards the measurements taken so far.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.latencies.reset();
+        self.gaps.reset();
+        self.last_timestamp = None;
+        self.first_read = None;
+        self.last_read = None;
+    }
+
+    ///Returns the measured reader
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Reader> Reader for MeasuringReader<R> {
+    #[inline]
+    fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        let res = self.inner.try_read()?;
+        if let Some(timestamp) = res.and_then(|record| read_u64_at(record, self.timestamp_offset)) {
+            let now = self.tick.nix_time();
+            let latency = now.saturating_sub(timestamp);
+            self.latencies.saturating_record(latency);
+            if let Some(last_timestamp) = self.last_timestamp.replace(timestamp) {
+                self.gaps.saturating_record(timestamp.saturating_sub(last_timestamp));
+            }
+            let read_time = Instant::now();
+            self.first_read.get_or_insert(read_time);
+            self.last_read = Some(read_time);
+        }
+        Ok(res)
+    }
+
+    #[inline]
+    fn exhausted(&self) -> Option<ReadError> {
+        self.inner.exhausted()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Millis;
+    use crate::core::{shm_reader, shm_writer, ChainedHandler, Metadata, SequenceHandler, TimestampHandler};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn measure_latency() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let handler = ChainedHandler::link(
+            ChainedHandler::link(EncoderHandler::default(), TimestampHandler::new(Millis)),
+            SequenceHandler::default(),
+        );
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, handler).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        //the sequence number is written before the timestamp
+        let mut measuring_reader = MeasuringReader::with_timestamp_offset(reader, Millis, 8);
+        assert_eq!(measuring_reader.snapshot().records, 0);
+        for _ in 0..3 {
+            writer.write(&"Delayed").unwrap();
+        }
+        sleep(Duration::from_millis(50));
+        assert_eq!(measuring_reader.drain(|_| ()).unwrap(), 3);
+        writer.write(&"Delayed").unwrap();
+        sleep(Duration::from_millis(100));
+        assert!(measuring_reader.try_read().unwrap().is_some());
+        let report = measuring_reader.snapshot();
+        assert_eq!(report.records, 4);
+        assert!(report.min >= 50 && report.min < 1000, "{:?}", report);
+        assert!(report.max >= 100 && report.max < 1000, "{:?}", report);
+        assert!(report.p50 >= 50 && report.p50 <= report.max);
+        assert!(report.throughput > 0.0);
+        measuring_reader.reset();
+        assert_eq!(measuring_reader.snapshot().records, 0);
+    }
+
+    #[test]
+    fn measure_gaps() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let mut measuring_reader = MeasuringReader::new(reader, Millis);
+        for timestamp in [1000u64, 1010, 1020, 1050, 1060].iter() {
+            writer.write(&(*timestamp, "Stamped")).unwrap();
+        }
+        assert_eq!(measuring_reader.drain(|_| ()).unwrap(), 5);
+        let report = measuring_reader.jitter_report();
+        assert_eq!(report.gaps, 4);
+        assert_eq!(report.min, 10);
+        assert_eq!(report.max, 30);
+        assert!((report.mean - 15.0).abs() < 1e-9, "{:?}", report);
+        assert!((report.std_dev - 75f64.sqrt()).abs() < 1e-9, "{:?}", report);
+        measuring_reader.reset();
+        assert_eq!(measuring_reader.jitter_report().gaps, 0);
+    }
+}
@@ -0,0 +1,203 @@
+//! Provides a reader which merges several timestamped channels in global time order.
+use super::{read_u64_at, CoalescedIter, ShmReader, TryIter};
+use crate::api::{ReadError, Reader};
+
+/// A [Reader](../api/trait.Reader.html) which merges the records of several channels, yielding them in the order
+/// of the timestamps embedded into the records, e.g. by a [TimestampHandler](struct.TimestampHandler.html).
+/// Every channel must be ordered by timestamp. Records with the same timestamp are yielded in the order of
+/// their readers. A record too short to hold a timestamp is considered to be stamped with zero.
+///
+/// A record is yielded only when every channel which is not yet closed has a record available, as a channel which
+/// is temporarily behind may still get an earlier record. Until then no record is available. A closed channel is
+/// no longer waited for, and once all the channels are closed the merging reader is closed too. Any other error
+/// of a channel fails the merge.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let other_metadata = Metadata::new(1850, 43, 300_000, 1000, FOREVER, Nanos);
+/// let handler = ChainedHandler::link(EncoderHandler::default(), TimestampHandler::new(Nanos));
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, handler).unwrap();
+/// let handler = ChainedHandler::link(EncoderHandler::default(), TimestampHandler::new(Nanos));
+/// let mut other_writer = shm_writer(&test_tmp_dir.path(), &other_metadata, handler).unwrap();
+/// writer.write(&"First").unwrap();
+/// other_writer.write(&"Second").unwrap();
+/// let readers = vec![shm_reader(&test_tmp_dir.path(), 42).unwrap(), shm_reader(&test_tmp_dir.path(), 43).unwrap()];
+/// let mut merging_reader = MergingReader::new(readers);
+/// assert_eq!(&merging_reader.try_read().unwrap().unwrap()[8..], b"First");
+/// ```
+#[derive(Debug)]
+pub struct MergingReader {
+    readers: Vec<ShmReader>,
+    closed: Vec<bool>,
+    timestamp_offset: usize,
+    failure: Option<ReadError>,
+}
+
+impl MergingReader {
+    /// Creates a reader which merges channels whose records start with a timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `readers` - The readers of the merged channels
+    #[inline]
+    pub fn new(readers: Vec<ShmReader>) -> MergingReader {
+        MergingReader::with_timestamp_offset(readers, 0)
+    }
+
+    /// Creates a reader which merges channels whose records hold a timestamp at a given offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `readers` - The readers of the merged channels
+    /// * `timestamp_offset` - The offset of the timestamp in every record
+    #[inline]
+    pub fn with_timestamp_offset(readers: Vec<ShmReader>, timestamp_offset: usize) -> MergingReader {
+        let closed = vec![false; readers.len()];
+        MergingReader {
+            readers,
+            closed,
+            timestamp_offset,
+            failure: None,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over messages in the channel.
+    #[inline]
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel,
+    /// which ends after it yields a single `Nothing`.
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Consumes the merging reader, returning the readers of the merged channels.
+    #[inline]
+    pub fn into_inner(self) -> Vec<ShmReader> {
+        self.readers
+    }
+}
+
+impl Reader for MergingReader {
+    /// Reads the record with the earliest timestamp, if every channel which is not closed has a record available.
+    ///
+    /// # Errors
+    ///
+    /// `Closed` once all the channels are closed, or the first error, other than `Closed`, returned by any channel.
+    fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        let mut earliest: Option<(usize, u64)> = None;
+        for (idx, reader) in self.readers.iter_mut().enumerate() {
+            if self.closed[idx] {
+                continue;
+            }
+            match reader.peek() {
+                Ok(Some(record)) => {
+                    let timestamp = read_u64_at(record, self.timestamp_offset).unwrap_or(0);
+                    if earliest.is_none_or(|(_, min_timestamp)| timestamp < min_timestamp) {
+                        earliest = Some((idx, timestamp));
+                    }
+                }
+                //this channel may still get an earlier record
+                Ok(None) => return Ok(None),
+                Err(ReadError::Closed) => self.closed[idx] = true,
+                Err(err) => {
+                    self.failure = Some(err);
+                    return Err(err);
+                }
+            }
+        }
+        match earliest {
+            Some((idx, _)) => {
+                let reader = &mut self.readers[idx];
+                let record = reader.peek();
+                reader.commit();
+                record
+            }
+            None => {
+                self.failure = Some(ReadError::Closed);
+                Err(ReadError::Closed)
+            }
+        }
+    }
+
+    #[inline]
+    fn exhausted(&self) -> Option<ReadError> {
+        self.failure
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn merge_by_timestamp() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata_a = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let metadata_b = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let mut writer_a = shm_writer(test_tmp_dir.path(), &metadata_a, EncoderHandler::default()).unwrap();
+        let mut writer_b = shm_writer(test_tmp_dir.path(), &metadata_b, EncoderHandler::default()).unwrap();
+        for timestamp in [1u64, 4, 5, 9].iter() {
+            writer_a.write(&(*timestamp, "A")).unwrap();
+        }
+        for timestamp in [2u64, 3].iter() {
+            writer_b.write(&(*timestamp, "B")).unwrap();
+        }
+        let readers = vec![
+            shm_reader(test_tmp_dir.path(), 1000).unwrap(),
+            shm_reader(test_tmp_dir.path(), 2000).unwrap(),
+        ];
+        let mut merging_reader = MergingReader::new(readers);
+        let mut merged = Vec::new();
+        while let Some(record) = merging_reader.try_read().unwrap() {
+            merged.push((read_u64_at(record, 0).unwrap(), record[8]));
+        }
+        assert_eq!(merged, vec![(1, b'A'), (2, b'B'), (3, b'B')]);
+        //channel B is behind, so nothing is read from channel A
+        assert_eq!(merging_reader.try_read(), Ok(None));
+        for timestamp in [6u64, 7, 8].iter() {
+            writer_b.write(&(*timestamp, "B")).unwrap();
+        }
+        drop(writer_a);
+        drop(writer_b);
+        merged.clear();
+        loop {
+            match merging_reader.try_read() {
+                Ok(Some(record)) => merged.push((read_u64_at(record, 0).unwrap(), record[8])),
+                Ok(None) => panic!("All the records should be available"),
+                Err(err) => {
+                    assert_eq!(err, ReadError::Closed);
+                    break;
+                }
+            }
+        }
+        assert_eq!(merged, vec![(4, b'A'), (5, b'A'), (6, b'B'), (7, b'B'), (8, b'B'), (9, b'A')]);
+        assert_eq!(merging_reader.exhausted(), Some(ReadError::Closed));
+        assert_eq!(merging_reader.into_inner().len(), 2);
+    }
+}
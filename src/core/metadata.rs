@@ -1,22 +1,51 @@
 //!Provides access to metadata associated with a channel.
-use super::utils::{align, is_aligned, REC_HEADER_LEN};
+use super::utils::{align, is_aligned, FOOTER_LEN, REC_HEADER_LEN};
 use super::version::Version;
-use super::TickUnit;
+use super::{RecordLayout, TickUnit};
 use crate::api::ChannelError;
-use crate::api::ChannelError::{IncompatibleVersion, InvalidCapacity, InvalidMaxMessageLength, InvalidSignature};
+use crate::api::ChannelError::{
+    IncompatibleVersion, InvalidCapacity, InvalidMaxMessageLength, InvalidMetadataLength, InvalidRecordHeaderLength,
+    InvalidSignature,
+};
 use std::cmp::max;
 use std::cmp::min;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const MIN_CAPACITY: u32 = 1024 * 16;
-const METADATA_LEN: usize = 128;
+const MIN_SMALL_CAPACITY: u32 = 1024;
+pub(crate) const METADATA_LEN: usize = 128;
+const MAX_REC_HEADER_LEN: u32 = 64;
 const SIGNATURE: u64 = 0x2A54_4942_4B45_4B2A; //"*KEKBIT*" as bytes as u64
+const NO_APP_ID: u64 = 0; //channels which do not belong to any application
+const APP_ID_OFFSET: usize = 72;
+const METADATA_LEN_OFFSET: usize = 80;
+const MAX_METADATA_LEN: u32 = 4096;
+const SMALL_OFFSET: usize = 84;
+const RECORD_LAYOUT_OFFSET: usize = 104;
 
+//A record takes at most 1/128th of the capacity, but small channels still allow records up to 128 bytes.
+//Returns None if the capacity is too small to hold any record besides its header.
 #[inline]
-const fn compute_max_msg_len(capacity: u32) -> u32 {
-    //if you reduce MIN_CAPACITY this may underflow!
-    (capacity >> 7) - (REC_HEADER_LEN as u32)
+const fn compute_max_msg_len(capacity: u32) -> Option<u32> {
+    let max_rec_len = capacity >> 7;
+    let small_rec_len = if capacity >> 3 < 128 { capacity >> 3 } else { 128 };
+    let max_rec_len = if max_rec_len < small_rec_len {
+        small_rec_len
+    } else {
+        max_rec_len
+    };
+    match max_rec_len.checked_sub(REC_HEADER_LEN) {
+        Some(len) if len > 0 => Some(len),
+        _ => None,
+    }
 }
 
+//The minimum capacities are large enough to always allow records, so the check is done at compile time.
+const MIN_MAX_MSG_LEN: u32 = match compute_max_msg_len(MIN_SMALL_CAPACITY) {
+    Some(len) => len,
+    None => panic!("The minimum capacity of a small channel does not allow any record"),
+};
+
 /// Defines and validates the metadata associated with a channel.
 #[derive(PartialEq, Eq, Debug)]
 pub struct Metadata {
@@ -27,6 +56,11 @@ pub struct Metadata {
     timeout: u64,
     creation_time: u64,
     tick_unit: TickUnit,
+    rec_header_len: u32,
+    app_id: u64,
+    len: u32,
+    small: bool,
+    record_layout: RecordLayout,
     version: Version,
 }
 
@@ -41,9 +75,9 @@ impl Metadata {
     /// * `writer_id` - Channel's writer identifier
     /// * `channel_id` - Channel's identifier
     /// * `capacity_hint` - Hint for the size of the channel - the maximum amount of data that can be wrote into the channel.
-    ///                  Usually a successfully created channel will have a size very close to this hint, probably a little larger.
+    ///   Usually a successfully created channel will have a size very close to this hint, probably a little larger.
     /// * `max_msg_len_hint` - Hint for the maximum size of a message wrote into the channel. This cannot be larger than a certain fraction.
-    ///        of the channel's capacity(1/128th), so the new created channel may have max message length value smaller than this hint.
+    ///   of the channel's capacity(1/128th), so the new created channel may have max message length value smaller than this hint.
     /// * `timeout` - Specifies the write inactivity time interval after each the reader will consider the channel abandoned by the writer.
     /// * `tick_unit` - Time unit used by the timeout and creation time attributes.        
     ///
@@ -74,7 +108,8 @@ impl Metadata {
         tick_unit: TickUnit,
     ) -> Metadata {
         let capacity = max(MIN_CAPACITY, align(capacity_hint));
-        let max_msg_len = align(min(max_msg_len_hint + REC_HEADER_LEN, compute_max_msg_len(capacity)) as u32);
+        let max_msg_len = compute_max_msg_len(capacity).unwrap_or(MIN_MAX_MSG_LEN);
+        let max_msg_len = align(min(max_msg_len_hint + REC_HEADER_LEN, max_msg_len));
         let creation_time = tick_unit.nix_time();
         Metadata {
             writer_id,
@@ -84,9 +119,198 @@ impl Metadata {
             timeout,
             creation_time,
             tick_unit,
+            rec_header_len: REC_HEADER_LEN,
+            app_id: NO_APP_ID,
+            len: METADATA_LEN as u32,
+            small: false,
+            record_layout: RecordLayout::default(),
             version: Version::latest(),
         }
     }
+
+    /// Defines a new metadata for a small channel, meant for constrained environments where even the
+    /// 16KB minimum capacity of a regular channel is too much. The capacity of a small channel
+    /// cannot go below 1KB, and its maximum message length is at least 120 bytes, even if that is
+    /// more than 1/128th of its capacity.
+    ///
+    /// Return a struct that contains all the metadata required to be associated with a new channel.
+    ///
+    /// # Arguments
+    ///
+    /// The same arguments as [new](#method.new).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// let metadata = Metadata::new_small(111, 101, 2048, 100, 10_000, Nanos);
+    /// assert_eq!(metadata.capacity(), 2048);
+    /// assert_eq!(metadata.max_msg_len(), 112);
+    /// ```
+    #[inline]
+    pub fn new_small(
+        writer_id: u64,
+        channel_id: u64,
+        capacity_hint: u32,
+        max_msg_len_hint: u32,
+        timeout: u64,
+        tick_unit: TickUnit,
+    ) -> Metadata {
+        let capacity = max(MIN_SMALL_CAPACITY, align(capacity_hint));
+        let max_msg_len = compute_max_msg_len(capacity).unwrap_or(MIN_MAX_MSG_LEN);
+        let max_msg_len = align(min(max_msg_len_hint + REC_HEADER_LEN, max_msg_len));
+        Metadata {
+            capacity,
+            max_msg_len,
+            small: capacity < MIN_CAPACITY,
+            ..Metadata::new(writer_id, channel_id, MIN_CAPACITY, 0, timeout, tick_unit)
+        }
+    }
+
+    /// Defines a new channel metadata sized to hold a given number of messages.
+    /// The capacity is computed such that exactly `record_count` messages of the maximum length fit into the channel.
+    ///
+    /// Return a struct that contains all the metadata required to be associated with a new channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer_id` - Channel's writer identifier
+    /// * `channel_id` - Channel's identifier
+    /// * `max_msg_len_hint` - Hint for the maximum size of a message wrote into the channel, handled as in [new](#method.new).
+    /// * `record_count` - How many messages of the maximum length the channel should hold. As a channel cannot be smaller
+    ///   than 16KB, and its maximum message length cannot exceed 1/128th of its capacity, a channel sized for few messages
+    ///   may hold more messages than requested or may have a smaller maximum message length.
+    /// * `timeout` - Specifies the write inactivity time interval after each the reader will consider the channel abandoned by the writer.
+    /// * `tick_unit` - Time unit used by the timeout and creation time attributes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// //room for 1000 messages of up to 1KB
+    /// let metadata = Metadata::for_messages(111, 101, 1024, 1000, 10_000, Nanos);
+    /// assert_eq!(metadata.estimated_record_capacity(metadata.max_msg_len()), 1000);
+    /// ````
+    #[inline]
+    pub fn for_messages(
+        writer_id: u64,
+        channel_id: u64,
+        max_msg_len_hint: u32,
+        record_count: u32,
+        timeout: u64,
+        tick_unit: TickUnit,
+    ) -> Metadata {
+        let max_msg_len = align(max_msg_len_hint + REC_HEADER_LEN);
+        let rec_len = align(max_msg_len + REC_HEADER_LEN) as u64;
+        let capacity_hint = min(rec_len * record_count as u64, (u32::MAX - 7) as u64) as u32;
+        Metadata::new(writer_id, channel_id, capacity_hint, max_msg_len_hint, timeout, tick_unit)
+    }
+
+    /// Sets the length of the record header. Every record starts with a header which holds the
+    /// record length in its first 8 bytes, the rest of the header is reserved for inline record attributes.
+    /// By default the record header is 8 bytes long.
+    ///
+    /// Returns the metadata with the new record header length.
+    ///
+    /// # Arguments
+    ///
+    /// * `rec_header_len_hint` - Hint for the length of the record header. It will be aligned to 8 bytes and
+    ///   it cannot be larger than 64 bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// let metadata = Metadata::new(111, 101, 10_001, 100, 10_000, Nanos).with_rec_header_len(12);
+    /// assert_eq!(metadata.rec_header_len(), 16);
+    /// ```
+    #[inline]
+    pub fn with_rec_header_len(mut self, rec_header_len_hint: u32) -> Metadata {
+        self.rec_header_len = align(rec_header_len_hint).clamp(REC_HEADER_LEN, MAX_REC_HEADER_LEN);
+        self
+    }
+
+    /// Sets the identifier of the application which owns the channel. Readers which
+    /// [open the channel for an application](fn.shm_reader_for_app.html) will reject it, unless it was
+    /// created for the same application. This prevents unrelated applications which share the same
+    /// storage from reading each other channels. By default a channel does not belong to any application.
+    ///
+    /// Returns the metadata with the new application identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_id` - The application identifier. It should not be 0, which means no application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// let metadata = Metadata::new(111, 101, 10_001, 100, 10_000, Nanos).with_app_id(0xA11CE);
+    /// assert_eq!(metadata.app_id(), 0xA11CE);
+    /// ```
+    #[inline]
+    pub fn with_app_id(mut self, app_id: u64) -> Metadata {
+        self.app_id = app_id;
+        self
+    }
+
+    /// Declares the prefixes the channel writer adds in front of every record body, such a timestamp or a sequence number.
+    /// Readers use the declared layout to split the records, see [structured_iter](struct.ShmReader.html#method.structured_iter).
+    /// The declaration is not enforced, so it must match the handlers used by the writer.
+    ///
+    /// Returns the metadata with the new record layout.
+    ///
+    /// # Arguments
+    ///
+    /// * `record_layout` - The record prefixes added by the writer handlers
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// let layout = RecordLayout { timestamp: true, sequence: true };
+    /// let metadata = Metadata::new(111, 101, 10_001, 100, 10_000, Nanos).with_record_layout(layout);
+    /// assert_eq!(metadata.record_layout().prefix_len(), 16);
+    /// ```
+    #[inline]
+    pub fn with_record_layout(mut self, record_layout: RecordLayout) -> Metadata {
+        self.record_layout = record_layout;
+        self
+    }
+
+    /// Sets the length of the metadata block. The channel data starts right after it, so a larger
+    /// block leaves room for fields added by future versions. The length is rounded up to 8 bytes,
+    /// and clamped between the default 128 bytes and 4KB.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The length of the metadata block
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    ///
+    /// let metadata = Metadata::new(111, 101, 10_001, 100, 10_000, Nanos).with_len(250);
+    /// assert_eq!(metadata.len(), 256);
+    /// ```
+    #[inline]
+    pub fn with_len(mut self, len: u32) -> Metadata {
+        self.len = align(len).clamp(METADATA_LEN as u32, MAX_METADATA_LEN);
+        self
+    }
+
     ///Reads and `validates` the metadata from an existing memory mapped channel.
     ///
     ///Returns the metadata associated with the channel.
@@ -94,7 +318,7 @@ impl Metadata {
     /// # Arguments
     ///
     /// * `metadata` - Reference to a  byte array which should contain metadata associated with a given channel.
-    ///              Usually points at the beginning of a memory mapped file used as storage for a kekbit channel.
+    ///   Usually points at the beginning of a memory mapped file used as storage for a kekbit channel.
     ///
     /// # Errors
     ///     
@@ -130,7 +354,12 @@ impl Metadata {
     ///  ```
     ///    
     pub fn read(metadata: &[u8]) -> Result<Metadata, ChannelError> {
-        assert!(metadata.len() >= METADATA_LEN);
+        if metadata.len() < METADATA_LEN {
+            return Err(InvalidMetadataLength {
+                metadata_len: metadata.len() as u32,
+                msg: "Not enough data to hold the metadata",
+            });
+        }
         let mut offset = 0;
         let signature = Metadata::read_u64(metadata, offset);
         if signature != SIGNATURE {
@@ -154,13 +383,21 @@ impl Metadata {
         let channel_id = Metadata::read_u64(metadata, offset);
         offset += 8;
         let capacity = Metadata::read_u32(metadata, offset);
-        if capacity < MIN_CAPACITY {
+        //only the channels explicitly created as small may go below the regular minimum capacity
+        let small = Metadata::read_u32(metadata, SMALL_OFFSET) != 0;
+        if small && capacity < MIN_SMALL_CAPACITY {
+            return Err(InvalidCapacity {
+                capacity,
+                msg: "Capacity below minimum allowed of 1KB for a small channel",
+            });
+        }
+        if !small && capacity < MIN_CAPACITY {
             return Err(InvalidCapacity {
                 capacity,
-                msg: "Capacity below minimum allowed of 10KB",
+                msg: "Capacity below minimum allowed of 16KB",
             });
         }
-        if !is_aligned(MIN_CAPACITY) {
+        if !is_aligned(capacity) {
             return Err(InvalidCapacity {
                 capacity,
                 msg: "Capacity is not 8 bytes aligned",
@@ -168,12 +405,21 @@ impl Metadata {
         }
         offset += 4;
         let max_msg_len = Metadata::read_u32(metadata, offset);
-        if max_msg_len > align(compute_max_msg_len(capacity)) {
+        if max_msg_len == 0 {
             return Err(InvalidMaxMessageLength {
                 msg_len: max_msg_len,
-                msg: "Max message lenght is too large",
+                msg: "Max message length must be positive",
             });
         }
+        match compute_max_msg_len(capacity) {
+            Some(limit) if max_msg_len <= align(limit) => (),
+            _ => {
+                return Err(InvalidMaxMessageLength {
+                    msg_len: max_msg_len,
+                    msg: "Max message lenght is too large",
+                })
+            }
+        }
         if !is_aligned(max_msg_len) {
             return Err(InvalidMaxMessageLength {
                 msg_len: max_msg_len,
@@ -186,7 +432,30 @@ impl Metadata {
         let creation_time = Metadata::read_u64(metadata, offset);
         offset += 8;
         let tick_unit = TickUnit::from_id(metadata[offset]);
-        //offset += 1;
+        offset += 4;
+        let rec_header_len = Metadata::read_u32(metadata, offset);
+        if !is_aligned(rec_header_len) || !(REC_HEADER_LEN..=MAX_REC_HEADER_LEN).contains(&rec_header_len) {
+            return Err(InvalidRecordHeaderLength {
+                rec_header_len,
+                msg: "Record header length must be 8 bytes aligned and between 8 and 64 bytes",
+            });
+        }
+        //the ready flag is stored between the record header length and the application id
+        let app_id = Metadata::read_u64(metadata, APP_ID_OFFSET);
+        let len = Metadata::read_u32(metadata, METADATA_LEN_OFFSET);
+        if !is_aligned(len) || len < METADATA_LEN as u32 || len > MAX_METADATA_LEN {
+            return Err(InvalidMetadataLength {
+                metadata_len: len,
+                msg: "Metadata length must be 8 bytes aligned and between 128 bytes and 4KB",
+            });
+        }
+        if len as usize > metadata.len() {
+            return Err(InvalidMetadataLength {
+                metadata_len: len,
+                msg: "Metadata length exceeds the available data",
+            });
+        }
+        let record_layout = RecordLayout::from_bits(Metadata::read_u32(metadata, RECORD_LAYOUT_OFFSET));
         Ok(Metadata {
             writer_id,
             channel_id,
@@ -195,6 +464,11 @@ impl Metadata {
             timeout,
             creation_time,
             tick_unit,
+            rec_header_len,
+            app_id,
+            len,
+            small,
+            record_layout,
             version,
         })
     }
@@ -205,7 +479,7 @@ impl Metadata {
     /// # Arguments
     ///
     /// * `metadata` - Reference to a byte slice where metadata must be written.
-    ///              Usually points at the beginning of a memory mapped file used as storage for a kekbit channel.
+    ///   Usually points at the beginning of a memory mapped file used as storage for a kekbit channel.
     ///
     /// # Example
     ///
@@ -256,10 +530,18 @@ impl Metadata {
         metadata[40..48].clone_from_slice(&self.timeout.to_le_bytes());
         metadata[48..56].clone_from_slice(&self.creation_time.to_le_bytes());
         metadata[56] = self.tick_unit.id();
-        let last = 57;
-        for item in metadata.iter_mut().take(METADATA_LEN).skip(last) {
+        metadata[57..60].clone_from_slice(&[0u8; 3]);
+        metadata[60..64].clone_from_slice(&self.rec_header_len.to_le_bytes());
+        metadata[64..72].clone_from_slice(&[0u8; 8]);
+        metadata[72..80].clone_from_slice(&self.app_id.to_le_bytes());
+        metadata[80..84].clone_from_slice(&self.len.to_le_bytes());
+        metadata[84..88].clone_from_slice(&(self.small as u32).to_le_bytes());
+        //the control word and its generation, at 88 and 96, start zeroed
+        let last = 88;
+        for item in metadata.iter_mut().take(self.len()).skip(last) {
             *item = 0u8;
         }
+        metadata[RECORD_LAYOUT_OFFSET..RECORD_LAYOUT_OFFSET + 4].clone_from_slice(&self.record_layout.bits().to_le_bytes());
         self.len()
     }
 
@@ -329,19 +611,129 @@ impl Metadata {
     pub fn creation_time(&self) -> u64 {
         self.creation_time
     }
+    ///Returns the channel creation time as a wall-clock time.
+    #[inline]
+    pub fn creation_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + self.tick_unit.duration(self.creation_time)
+    }
+
+    ///Returns the time elapsed since the channel was created, measured with the precision of the channel
+    ///tick unit. If the system clock went backwards since the channel was created, the age is zero.
+    #[inline]
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.creation_system_time())
+            .unwrap_or_default()
+    }
     ///Returns the time unit used by the channel creation time and the timeout attributes.
     #[inline]
     pub fn tick_unit(&self) -> TickUnit {
         self.tick_unit
     }
+
+    ///Returns the number of bytes a record with the given payload takes in the channel,
+    ///including the record header and the alignment padding.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The size of the record payload
+    #[inline]
+    pub fn bytes_per_record(&self, payload: u32) -> u32 {
+        align(self.rec_header_len + payload)
+    }
+
+    ///Returns how many records with the given payload fit into the channel. It returns 0 if the
+    ///payload is empty or larger than the maximum message size allowed. The space required by the
+    ///channel end marker is reserved in the footer, so it is not taken from the channel capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `typical_payload` - The size of the payload of every record
+    #[inline]
+    pub fn estimated_record_capacity(&self, typical_payload: u32) -> u32 {
+        if typical_payload == 0 || typical_payload > self.max_msg_len || self.rec_header_len + typical_payload > self.capacity {
+            return 0;
+        }
+        //the last record must fit in the space left, without its padding
+        (self.capacity - self.rec_header_len - typical_payload) / self.bytes_per_record(typical_payload) + 1
+    }
+
+    ///Returns the length of the record header
+    #[inline]
+    pub fn rec_header_len(&self) -> u32 {
+        self.rec_header_len
+    }
+
+    ///Returns the prefixes the channel writer adds in front of every record body
     #[inline]
-    ///Returns  the length of the metadata. For any given version the length is the same.
-    ///In the current version it is 128 bytes.
+    pub fn record_layout(&self) -> RecordLayout {
+        self.record_layout
+    }
+
+    ///Returns the identifier of the application which owns the channel, 0 if the channel does not belong to any application
+    #[inline]
+    pub fn app_id(&self) -> u64 {
+        self.app_id
+    }
+
+    #[inline]
+    ///Returns the length of the metadata, which is also the offset where the channel data starts.
+    ///The length is stored in the metadata itself, so readers could skip a metadata block larger
+    ///than the one they know about. By default it is 128 bytes.
     pub const fn len(&self) -> usize {
-        METADATA_LEN
+        self.len as usize
     }
 }
 
+/// Recommends the capacity of a channel which should hold a given workload. The space taken by every
+/// record, including its header and alignment padding, is added up, together with the channel footer,
+/// and the total is scaled by the headroom factor. The capacity is also large enough to allow the largest record,
+/// as the maximum message length of a channel is limited to 1/128th of its capacity.
+///
+/// Returns the recommended capacity, aligned and never below the minimum capacity of a channel.
+///
+/// # Arguments
+///
+/// * `record_sizes` - The sizes of the records expected to be written into the channel
+/// * `headroom` - Factor applied to the space required by the records, e.g. 1.5 for 50% spare room.
+///   Values below 1 are treated as 1.
+///
+/// # Example
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+///
+/// let sizes = vec![100; 1000];
+/// let capacity = recommend_capacity(&sizes, 1.0);
+/// let metadata = Metadata::new(111, 101, capacity, recommend_max_msg_len(&sizes), 10_000, Nanos);
+/// assert!(metadata.estimated_record_capacity(100) >= 1000);
+/// ```
+pub fn recommend_capacity(record_sizes: &[u32], headroom: f32) -> u32 {
+    let used: u64 = record_sizes
+        .iter()
+        .map(|size| align(REC_HEADER_LEN.saturating_add(*size).min(u32::MAX - 7)) as u64)
+        .sum::<u64>()
+        + FOOTER_LEN as u64;
+    let scaled = (used as f64 * headroom.max(1.0) as f64).ceil() as u64;
+    let largest = record_sizes.iter().copied().max().unwrap_or(0) as u64;
+    let for_largest = (largest + REC_HEADER_LEN as u64) << 7;
+    let capacity = min(max(scaled, for_largest), (u32::MAX - 7) as u64) as u32;
+    max(MIN_CAPACITY, align(capacity))
+}
+
+/// Recommends the maximum message length of a channel which should hold a given workload.
+///
+/// Returns the size of the largest record, or 0 if there are no records.
+///
+/// # Arguments
+///
+/// * `record_sizes` - The sizes of the records expected to be written into the channel
+#[inline]
+pub fn recommend_max_msg_len(record_sizes: &[u32]) -> u32 {
+    record_sizes.iter().copied().max().unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,4 +756,176 @@ mod tests {
         assert_eq!(head.len(), 128);
         assert_eq!(head.writer_id(), producer_id);
     }
+
+    #[test]
+    fn check_rec_header_len() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        assert_eq!(head.rec_header_len(), REC_HEADER_LEN);
+        let head = head.with_rec_header_len(16);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        let read_head = Metadata::read(&data).unwrap();
+        assert_eq!(read_head.rec_header_len(), 16);
+        assert_eq!(read_head.bytes_per_record(8), 24);
+        assert_eq!(head.with_rec_header_len(1000).rec_header_len(), MAX_REC_HEADER_LEN);
+        data[60..64].clone_from_slice(&12u32.to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidRecordHeaderLength { rec_header_len: 12, .. })
+        ));
+        data[60..64].clone_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidRecordHeaderLength { rec_header_len: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn check_incompatible_version() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        assert_eq!(Metadata::read(&data).unwrap(), head);
+        //the data region of version 1 channels starts at byte 1024, so they cannot be read any more
+        let old_version: u64 = Version::new(1, 0, 0).into();
+        data[8..16].clone_from_slice(&old_version.to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(IncompatibleVersion { expected, actual }) if expected == *Version::latest() && actual == old_version
+        ));
+    }
+
+    #[test]
+    fn check_legacy_secs() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Secs);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        data[56] = 1;
+        let read_head = Metadata::read(&data).unwrap();
+        assert_eq!(read_head.tick_unit(), TickUnit::Secs);
+        assert_eq!(read_head, head);
+    }
+
+    #[test]
+    fn check_app_id() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        assert_eq!(head.app_id(), NO_APP_ID);
+        let head = head.with_app_id(0xA);
+        let mut data = vec![0xFFu8; METADATA_LEN];
+        head.write_to(&mut data);
+        assert_eq!(&data[64..72], &[0u8; 8]);
+        assert_eq!(Metadata::read(&data).unwrap(), head);
+    }
+
+    #[test]
+    fn check_metadata_len() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        let mut data = vec![0xFFu8; 512];
+        head.write_to(&mut data);
+        //every channel of the current version stores its metadata length
+        data[80..84].clone_from_slice(&[0u8; 4]);
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidMetadataLength { metadata_len: 0, .. })
+        ));
+        head.write_to(&mut data);
+        assert_eq!(Metadata::read(&data).unwrap().len(), METADATA_LEN);
+        let head = head.with_len(300);
+        assert_eq!(head.write_to(&mut data), 304);
+        assert_eq!(&data[84..304], &[0u8; 220][..]);
+        let read_head = Metadata::read(&data).unwrap();
+        assert_eq!(read_head.len(), 304);
+        assert_eq!(read_head, head);
+        assert!(matches!(
+            Metadata::read(&data[..256]),
+            Err(InvalidMetadataLength { metadata_len: 304, .. })
+        ));
+        data[80..84].clone_from_slice(&100u32.to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidMetadataLength { metadata_len: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn check_min_capacity() {
+        let head = Metadata::new(111, 101, 1000, 100, 10_000, TickUnit::Nanos);
+        assert_eq!(head.capacity(), MIN_CAPACITY);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        data[32..36].clone_from_slice(&(MIN_CAPACITY - 8).to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidCapacity {
+                msg: "Capacity below minimum allowed of 16KB",
+                ..
+            })
+        ));
+        data[32..36].clone_from_slice(&(MIN_CAPACITY + 4).to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidCapacity {
+                msg: "Capacity is not 8 bytes aligned",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn check_max_msg_len_underflow() {
+        //below 64 bytes the record header alone takes more than the 1/8th share of a small channel
+        assert_eq!(compute_max_msg_len(0), None);
+        assert_eq!(compute_max_msg_len(56), None);
+        assert_eq!(compute_max_msg_len(64), None);
+        assert_eq!(compute_max_msg_len(72), Some(1));
+        let head = Metadata::new_small(111, 101, 0, 0, 10_000, TickUnit::Nanos);
+        assert!(head.max_msg_len() > 0);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        data[36..40].clone_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            Metadata::read(&data),
+            Err(InvalidMaxMessageLength { msg_len: 0, .. })
+        ));
+        data[36..40].clone_from_slice(&u32::MAX.wrapping_sub(7).to_le_bytes());
+        assert!(matches!(Metadata::read(&data), Err(InvalidMaxMessageLength { .. })));
+    }
+
+    #[test]
+    fn check_small_capacity() {
+        assert_eq!(compute_max_msg_len(MIN_SMALL_CAPACITY), Some(120));
+        assert_eq!(compute_max_msg_len(MIN_CAPACITY), Some(120));
+        assert_eq!(compute_max_msg_len(MIN_CAPACITY * 2), Some(248));
+        let head = Metadata::new_small(111, 101, 10, 1000, 10_000, TickUnit::Nanos);
+        assert_eq!(head.capacity(), MIN_SMALL_CAPACITY);
+        assert_eq!(head.max_msg_len(), 120);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        assert_eq!(Metadata::read(&data).unwrap(), head);
+        data[32..36].clone_from_slice(&512u32.to_le_bytes());
+        assert!(matches!(Metadata::read(&data), Err(InvalidCapacity { capacity: 512, .. })));
+        //a small hint for a large capacity still gives a regular channel
+        let head = Metadata::new_small(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        let mut data = vec![0u8; METADATA_LEN];
+        head.write_to(&mut data);
+        assert_eq!(&data[84..88], &[0u8; 4]);
+        assert_eq!(
+            head,
+            Metadata {
+                creation_time: head.creation_time,
+                ..Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos)
+            }
+        );
+    }
+
+    #[test]
+    fn check_record_footprint() {
+        let head = Metadata::new(111, 101, 20_000, 100, 10_000, TickUnit::Nanos);
+        assert_eq!(head.bytes_per_record(1), 16);
+        assert_eq!(head.bytes_per_record(8), 16);
+        assert_eq!(head.bytes_per_record(9), 24);
+        assert_eq!(head.estimated_record_capacity(0), 0);
+        assert_eq!(head.estimated_record_capacity(head.max_msg_len() + 1), 0);
+        assert_eq!(head.estimated_record_capacity(8), head.capacity() / 16);
+    }
 }
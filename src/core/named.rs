@@ -0,0 +1,271 @@
+//! Provides channels stored in POSIX named shared memory objects instead of files. Only unix is supported,
+//! named file mappings(see `CreateFileMapping`) are not available on Windows.
+use super::utils::{file_free_space, lock_file, FOOTER_LEN};
+use super::{check_space, sizing_error, storage_state, Metadata, ShmReader, ShmWriter};
+use crate::api::ChannelError::*;
+use crate::api::{ChannelError, ChannelState, Handler};
+use memmap::MmapOptions;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind::NotFound;
+use std::os::unix::io::FromRawFd;
+
+/// A storage for channels backed by POSIX shared memory objects(see `shm_open`), rather than files.
+/// Every channel is identified by a name derived from the storage namespace and the channel id,
+/// so no file will be left in a user visible directory. The name of a shared memory object is unlinked
+/// when the writer of its channel is dropped, while the readers which already attached to the channel
+/// keep reading it until they are dropped too. A channel whose writer crashed outlives it, until it is
+/// explicitly [unlinked](#method.unlink).
+///
+/// This storage is available on unix only.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let storage = NamedShmStorage::new(&format!("kekdoc_{}", std::process::id()));
+/// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+/// let mut writer = storage.shm_writer(&metadata, EncoderHandler::default()).unwrap();
+/// let reader = storage.shm_reader(42).unwrap();
+/// drop(writer);
+/// assert!(storage.shm_reader(42).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct NamedShmStorage {
+    namespace: String,
+}
+
+impl NamedShmStorage {
+    /// Creates a storage which names its channels using the given namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - Prefix for the name of all channels in this storage. It must not contain any `/`.
+    #[inline]
+    pub fn new(namespace: &str) -> NamedShmStorage {
+        assert!(!namespace.contains('/'));
+        NamedShmStorage {
+            namespace: namespace.to_string(),
+        }
+    }
+
+    /// Returns the name of the shared memory object associated with a channel.
+    ///
+    /// # Arguments
+    ///
+    ///  * `channel_id` - Channel for which the name will be returned
+    #[inline]
+    pub fn name(&self, channel_id: u64) -> String {
+        format!("/{}_{:016x}", self.namespace, channel_id)
+    }
+
+    /// Creates a channel stored in a named shared memory object and a writer associated with it.
+    ///
+    /// Returns a ready to use writer to the new created channel or an error if the operation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - a structure of type [Metadata](struct.Metadata.html) which contains the complete information required to create a channel.
+    /// * `rec_handler` - The handler used by the writer to push records into the channel
+    ///
+    /// # Errors
+    ///
+    /// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+    pub fn shm_writer<H: Handler>(&self, metadata: &Metadata, rec_handler: H) -> Result<ShmWriter<H>, ChannelError> {
+        let name = self.name(metadata.channel_id());
+        let lock_name = lock_name(&name);
+        open_shm(&lock_name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR).map_err(|err| CouldNotAccessStorage {
+            file_name: err.to_string(),
+        })?;
+        info!("Kekbit lock {} created", lock_name);
+        let res = create_channel(&name, metadata, rec_handler);
+        if unlink_shm(&lock_name).is_err() {
+            error!("Could not remove kekbit lock {}", lock_name);
+        }
+        info!("Kekbit lock {} removed", lock_name);
+        res
+    }
+
+    /// Creates a kekbit reader associated to a channel stored in a named shared memory object.
+    ///
+    /// Returns a ready to use reader which points to the beginning of a kekbit channel if succeeds, or an error if the operation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_id` - The channel identifier.
+    ///
+    /// # Errors
+    ///
+    /// Various [errors](../api/enum.ChannelError.html) may occur if the operation fails.
+    pub fn shm_reader(&self, channel_id: u64) -> Result<ShmReader, ChannelError> {
+        let name = self.name(channel_id);
+        if open_shm(&lock_name(&name), libc::O_RDONLY).is_ok() {
+            return Err(StorageNotReady { file_name: name });
+        }
+        let kek_file = open_shm(&name, libc::O_RDWR).map_err(|err| {
+            if err.kind() == NotFound {
+                StorageNotFound { file_name: name.clone() }
+            } else {
+                CouldNotAccessStorage {
+                    file_name: err.to_string(),
+                }
+            }
+        })?;
+        info!("Kekbit shared memory {} opened for read.", name);
+        let mmap =
+            unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })?;
+        ShmReader::new(mmap).map(|reader| reader.with_backing(kek_file))
+    }
+
+    /// Removes the name of the shared memory object associated with a channel, e.g. of a channel whose writer
+    /// crashed. The memory is released as soon as all the writers and readers which use the channel are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_id` - The channel identifier.
+    ///
+    /// # Errors
+    ///
+    /// If no such channel exists or it cannot be removed.
+    pub fn unlink(&self, channel_id: u64) -> Result<(), ChannelError> {
+        let name = self.name(channel_id);
+        unlink_shm(&name).map_err(|err| {
+            if err.kind() == NotFound {
+                StorageNotFound { file_name: name.clone() }
+            } else {
+                CouldNotAccessStorage {
+                    file_name: err.to_string(),
+                }
+            }
+        })?;
+        info!("Kekbit shared memory {} unlinked", name);
+        Ok(())
+    }
+}
+
+fn create_channel<H: Handler>(name: &str, metadata: &Metadata, rec_handler: H) -> Result<ShmWriter<H>, ChannelError> {
+    let kek_file = open_shm(name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR).map_err(|err| {
+        if err.kind() == std::io::ErrorKind::AlreadyExists {
+            StorageAlreadyExists {
+                file_name: name.to_string(),
+                existing_state: open_shm(name, libc::O_RDWR)
+                    .map(|kek_file| storage_state(&kek_file))
+                    .unwrap_or(ChannelState::Unknown),
+            }
+        } else {
+            CouldNotAccessStorage {
+                file_name: err.to_string(),
+            }
+        }
+    })?;
+    if lock_file(&kek_file).is_err() {
+        let _ = unlink_shm(name);
+        return Err(AlreadyLocked {
+            file_name: name.to_string(),
+        });
+    }
+    let total_len = (metadata.capacity() + metadata.len() as u32 + FOOTER_LEN) as u64;
+    let res = check_space(total_len, file_free_space(&kek_file))
+        .and_then(|_| {
+            kek_file
+                .set_len(total_len)
+                .map_err(|err| sizing_error(&err, total_len, file_free_space(&kek_file)))
+        })
+        .and_then(|_| {
+            unsafe { MmapOptions::new().map_mut(&kek_file) }.map_err(|err| MemoryMappingFailed { reason: err.to_string() })
+        })
+        .and_then(|mut mmap| {
+            metadata.write_to(&mut mmap[..]);
+            ShmWriter::new(mmap, rec_handler)
+        });
+    if res.is_err() {
+        error!("Kekbit writer creation error. The shared memory {} will be removed!", name);
+        let _ = unlink_shm(name);
+    } else {
+        info!("Kekbit channel with shared memory {} successfully initialized", name);
+    }
+    res.map(|writer| writer.with_storage(kek_file).with_unlink_on_close(name.to_string()))
+}
+
+#[inline]
+fn lock_name(name: &str) -> String {
+    format!("{}_lock", name)
+}
+
+fn open_shm(name: &str, flags: libc::c_int) -> Result<File, Error> {
+    let c_name = CString::new(name)?;
+    let fd = unsafe { libc::shm_open(c_name.as_ptr(), flags, 0o600 as libc::c_uint) };
+    if fd < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+pub(super) fn unlink_shm(name: &str) -> Result<(), Error> {
+    let c_name = CString::new(name)?;
+    if unsafe { libc::shm_unlink(c_name.as_ptr()) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, ReadError, Reader, Writer};
+    use crate::core::TickUnit::Nanos;
+    use assert_matches::assert_matches;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn named_channel() {
+        let storage = NamedShmStorage::new(&format!("kektest_{}", std::process::id()));
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        assert_matches!(storage.shm_reader(1000), Err(StorageNotFound { .. }));
+        let mut writer = storage.shm_writer(&metadata, EncoderHandler::default()).unwrap();
+        assert_matches!(
+            storage.shm_writer(&metadata, EncoderHandler::default()).err(),
+            Some(StorageAlreadyExists { .. })
+        );
+        writer.write(&"Hello named channel".as_bytes()).unwrap();
+        let mut reader = storage.shm_reader(1000).unwrap();
+        assert_eq!(reader.metadata(), writer.metadata());
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Hello named channel");
+        assert_matches!(reader.try_read(), Ok(None));
+        std::mem::drop(writer);
+        //the writer unlinks the channel when it closes it, an attached reader still reads it
+        assert_matches!(reader.try_read(), Err(ReadError::Closed));
+        assert_matches!(storage.shm_reader(1000), Err(StorageNotFound { .. }));
+        assert_matches!(storage.unlink(1000), Err(StorageNotFound { .. }));
+    }
+
+    #[test]
+    fn abandoned_named_channel() {
+        let storage = NamedShmStorage::new(&format!("kektest_abandoned_{}", std::process::id()));
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = storage.shm_writer(&metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"Left behind".as_bytes()).unwrap();
+        //a writer which crashed never closes nor unlinks its channel
+        std::mem::forget(writer);
+        let mut reader = storage.shm_reader(1000).unwrap();
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Left behind");
+        storage.unlink(1000).unwrap();
+        assert_matches!(storage.shm_reader(1000), Err(StorageNotFound { .. }));
+    }
+
+    #[test]
+    fn named_channel_space() {
+        let storage = NamedShmStorage::new(&format!("kektest_space_{}", std::process::id()));
+        let kek_file = open_shm(&storage.name(1000), libc::O_CREAT | libc::O_EXCL | libc::O_RDWR).unwrap();
+        //the space is the one of the file system which holds the shared memory objects
+        assert!(file_free_space(&kek_file).is_some());
+        unlink_shm(&storage.name(1000)).unwrap();
+    }
+}
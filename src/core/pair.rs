@@ -0,0 +1,163 @@
+//! Provides a sender/receiver pair over a kekbit channel, in the spirit of `std::sync::mpsc`.
+use super::{shm_reader_at, ChannelBuilder, Metadata, ShmReader, ShmWriter, TimeoutReader};
+use crate::api::{ChannelError, Encodable, Handler, Reader, RecvError, WriteError, Writer};
+use crate::retry::AdaptivePoller;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static NEXT_PAIR: AtomicU64 = AtomicU64::new(0);
+
+/// Creates a kekbit channel used only inside the current process, and returns its two ends, a sender and a
+/// receiver. It is an alternative to `std::sync::mpsc::sync_channel` for the applications which prefer
+/// kekbit's memory layout, e.g. to avoid allocations. The channel is bounded by its capacity, as it does not
+/// reuse the space of the records already received. The channel is backed by a temporary file which is
+/// removed when the sender is dropped.
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata of the channel
+/// * `rec_handler` - The handler used by the sender to push records into the channel
+///
+/// # Errors
+///
+/// Various [errors](../api/enum.ChannelError.html) may occur if the channel cannot be created.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+/// let (mut tx, mut rx) = channel(&metadata, EncoderHandler::default()).unwrap();
+/// let handle = std::thread::spawn(move || tx.send(&"Hello from a thread").unwrap());
+/// assert_eq!(rx.recv().unwrap(), b"Hello from a thread");
+/// handle.join().unwrap();
+/// assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+/// ```
+pub fn channel<H: Handler>(metadata: &Metadata, rec_handler: H) -> Result<(KekSender<H>, KekReceiver), ChannelError> {
+    let kek_file_path = std::env::temp_dir()
+        .join(format!("kekbit-{}", std::process::id()))
+        .join(format!(
+            "{:016x}_{}.kekbit",
+            metadata.channel_id(),
+            NEXT_PAIR.fetch_add(1, Ordering::Relaxed)
+        ));
+    let writer = ChannelBuilder::new()
+        .delete_on_close(true)
+        .shm_writer_at(&kek_file_path, metadata, rec_handler)?;
+    let reader = shm_reader_at(&kek_file_path)?;
+    Ok((KekSender { writer }, KekReceiver::new(reader, kek_file_path)))
+}
+
+/// The sending end of a [channel](fn.channel.html). Dropping the sender closes the channel.
+pub struct KekSender<H: Handler> {
+    writer: ShmWriter<H>,
+}
+
+impl<H: Handler> KekSender<H> {
+    /// Sends a record into the channel. It never blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to be sent
+    ///
+    /// # Errors
+    ///
+    /// If the record cannot be written, e.g. when the channel is full.
+    #[inline]
+    pub fn send<E: Encodable>(&mut self, data: &E) -> Result<(), WriteError> {
+        self.writer.write(data).map(|_| ())
+    }
+}
+
+/// The receiving end of a [channel](fn.channel.html).
+pub struct KekReceiver {
+    poller: AdaptivePoller<TimeoutReader<ShmReader>>,
+    path: PathBuf,
+}
+
+//The receiver owns its reader, which is never exposed, so no thread bound callback could be set on it.
+unsafe impl Send for KekReceiver {}
+
+impl KekReceiver {
+    #[inline]
+    fn new(reader: ShmReader, path: PathBuf) -> KekReceiver {
+        let poller = AdaptivePoller::new(reader.into(), Duration::from_micros(50), Duration::from_millis(10));
+        KekReceiver { poller, path }
+    }
+
+    /// Waits until a record is available, then returns a copy of it.
+    ///
+    /// # Errors
+    ///
+    /// `Disconnected` once the sender was dropped and all its records were received, or `Failed` if the channel
+    /// cannot be read anymore, e.g. if the sender did not send anything for longer than the channel timeout.
+    pub fn recv(&mut self) -> Result<Vec<u8>, RecvError> {
+        loop {
+            if let Some(record) = self.poller.next_or_wait()? {
+                return Ok(record.to_vec());
+            }
+        }
+    }
+
+    /// Returns a copy of the next record, if one is available, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [recv](#method.recv).
+    #[inline]
+    pub fn try_recv(&mut self) -> Result<Option<Vec<u8>>, RecvError> {
+        Ok(self.poller.reader_mut().try_read()?.map(|record| record.to_vec()))
+    }
+
+    /// Returns an iterator which blocks waiting for records, and ends once the channel is disconnected or failed.
+    #[inline]
+    pub fn iter(&mut self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+}
+
+impl std::fmt::Debug for KekReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KekReceiver").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::EncoderHandler;
+    use crate::core::read_u64_at;
+    use crate::core::TickUnit::Nanos;
+    use std::thread;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn sender_and_receiver_threads() {
+        let metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let (mut tx, mut rx) = channel(&metadata, EncoderHandler::default()).unwrap();
+        let path = rx.path.clone();
+        let sender = thread::spawn(move || {
+            for i in 0..1000u64 {
+                tx.send(&i).unwrap();
+                if i % 100 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        let receiver = thread::spawn(move || {
+            let received: Vec<u64> = rx.iter().map(|record| read_u64_at(&record, 0).unwrap()).collect();
+            assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+            assert_eq!(rx.try_recv(), Err(RecvError::Disconnected));
+            received
+        });
+        sender.join().unwrap();
+        assert_eq!(receiver.join().unwrap(), (0..1000).collect::<Vec<u64>>());
+        //the channel file is gone with the sender
+        assert!(!path.exists());
+    }
+}
@@ -1,16 +1,116 @@
-use super::utils::{align, load_atomic_u64, CLOSE, REC_HEADER_LEN, U64_SIZE, WATERMARK};
-use super::Metadata;
+#[cfg(feature = "digest")]
+use super::checksum::digest_record;
+use super::utils::{
+    align, is_ptr_aligned, load_atomic_u64, read_slot, strip_flags, Slot, BATCHED, CLOSE, COMPRESSED, CONTINUED,
+    CONTROL_GENERATION_OFFSET, CONTROL_OFFSET, DIGEST_LEN, FOOTER_COMMITTED_OFFSET, FOOTER_DIGEST_GENERATION_OFFSET,
+    FOOTER_DIGEST_OFFSET, FOOTER_LAST_WRITE_OFFSET, FOOTER_LEN, HEARTBEAT, READY, READY_OFFSET, REC_HEADER_LEN, U64_SIZE,
+    VERSION_OFFSET, WATERMARK,
+};
+use super::{Metadata, StructuredIter, VerifiedIter};
 use crate::api::ReadError::*;
-use crate::api::{ChannelError, ReadError, Reader};
+use crate::api::{ChannelError, DataFormat, Decodable, DecodeError, ReadError, Reader};
 use crate::core::TickUnit;
-use log::{error, info, warn};
+use crossbeam_utils::Backoff;
 use memmap::MmapMut;
+#[cfg(feature = "digest")]
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
 use std::iter::FusedIterator;
 use std::iter::Iterator;
+use std::marker::PhantomData;
+use std::ops::Range;
 use std::result::Result;
-use std::sync::atomic::Ordering;
+use std::str::Utf8Error;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
-const END_OF_TIME: u64 = std::u64::MAX; //this should be good for any time unit including nanos
+const END_OF_TIME: u64 = u64::MAX; //this should be good for any time unit including nanos
+const DEFAULT_MARKER_RETRIES: u32 = 16; //enough for a racing writer to publish a word, too few to delay a corruption report
+const DIGEST_READ_ATTEMPTS: u32 = 1024; //a digest still unstable after so many attempts was left by a dead writer
+
+/// Default maximum number of records, including heartbeats, walked by a single channel scan.
+/// It is the largest number of records a channel could ever hold.
+pub const MAX_SCAN_RECORDS: u32 = u32::MAX / REC_HEADER_LEN;
+/// Default maximum number of bytes walked by a single channel scan.
+pub const MAX_SCAN_BYTES: u32 = u32::MAX;
+
+/// Bounds every forward scan of a channel, such as skipping heartbeats, looking for a record
+/// boundary in [recovery mode](struct.ShmReader.html#method.scan_recover), or searching the replay start
+/// position. A scan which exceeds its bound fails with [Failed](../api/enum.ReadError.html#variant.Failed),
+/// so a pathologically corrupted channel could never keep a reader busy forever.
+/// Regardless of the bound, a scan never goes beyond the channel capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanBound {
+    /// Maximum number of records, including heartbeats, a scan may walk.
+    pub max_records: u32,
+    /// Maximum number of bytes a scan may walk.
+    pub max_bytes: u32,
+}
+
+impl ScanBound {
+    //Checks if a scan which walked the given number of records and bytes went too far.
+    #[inline]
+    pub(super) fn exceeded(&self, records: u32, bytes: u32) -> bool {
+        records > self.max_records || bytes > self.max_bytes
+    }
+}
+
+/// The writer progress, as published in the footer of a channel. The footer lies right after
+/// the channel capacity, and it is updated by the writer after every record or heartbeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FooterView {
+    /// The length of the channel space used by the writer, the position where the next record will be written.
+    pub committed_len: u32,
+    /// The time of the last write, in the channel [tick unit](struct.Metadata.html#method.tick_unit).
+    /// Until anything is written, it is the time the writer was created.
+    pub last_write: u64,
+    /// The running BLAKE3 digest of the records written so far, or None if the writer does not compute it.
+    /// The digest is updated after every record, so it covers all the records only once the channel is closed.
+    /// It is also None if the writer died in the middle of an update. See
+    /// [ChannelBuilder::digest](struct.ChannelBuilder.html#method.digest).
+    pub digest: Option<[u8; 32]>,
+}
+
+//Reads the digest published in a channel footer. Like the control word, the digest is updated by the writer
+//under a generation, which is 0 if the writer does not compute a digest.
+#[allow(clippy::cast_ptr_alignment)]
+fn footer_digest(footer_ptr: *const u8) -> Option<[u8; DIGEST_LEN]> {
+    let generation_ptr = unsafe { footer_ptr.add(FOOTER_DIGEST_GENERATION_OFFSET) } as *mut u64;
+    for _ in 0..DIGEST_READ_ATTEMPTS {
+        let generation = load_atomic_u64(generation_ptr, Ordering::Acquire);
+        if generation == 0 {
+            return None;
+        }
+        if generation & 1 == 1 {
+            //the writer is updating the digest
+            std::hint::spin_loop();
+            continue;
+        }
+        let mut digest = [0u8; DIGEST_LEN];
+        for (i, word) in digest.chunks_exact_mut(U64_SIZE).enumerate() {
+            let word_ptr = unsafe { footer_ptr.add(FOOTER_DIGEST_OFFSET + i * U64_SIZE) } as *mut u64;
+            word.copy_from_slice(&load_atomic_u64(word_ptr, Ordering::Relaxed).to_ne_bytes());
+        }
+        fence(Ordering::Acquire);
+        if load_atomic_u64(generation_ptr, Ordering::Relaxed) == generation {
+            return Some(digest);
+        }
+    }
+    None
+}
+
+impl Default for ScanBound {
+    #[inline]
+    fn default() -> ScanBound {
+        ScanBound {
+            max_records: MAX_SCAN_RECORDS,
+            max_bytes: MAX_SCAN_BYTES,
+        }
+    }
+}
 
 /// An implementation of the [Reader](trait.Reader.html) which access a persistent channel through
 /// memory mapping. A `ShmReader` must be created using the [shm_reader](fn.shm_reader.html) function.
@@ -31,82 +131,1165 @@ const END_OF_TIME: u64 = std::u64::MAX; //this should be good for any time unit
 /// println!("{:?}", reader.metadata());
 ///
 /// ```
-#[derive(Debug)]
 pub struct ShmReader {
     metadata: Metadata,
     data_ptr: *const u8,
     read_index: u32,
+    record_offset: u32,
     failure: Option<ReadError>,
-    _mmap: MmapMut,
+    on_exhausted: Option<Box<dyn FnOnce(ReadError)>>,
+    recover: bool,
+    skipped: Vec<Range<u32>>,
+    corruption_budget: u64,
+    heartbeats: u64,
+    last_heartbeat: Option<u64>,
+    peeked: Option<u32>,
+    marks: Vec<u32>,
+    last_header: u64,
+    reassembled: Vec<u8>,
+    scan_bound: ScanBound,
+    max_record: u32,
+    marker_retries: u32,
+    raw: bool,
+    #[cfg(feature = "compression")]
+    inflated: Vec<u8>,
+    #[cfg(feature = "compression")]
+    inflating: bool,
+    backing: Option<File>,
+    guarded: bool,
+    version: u64,
+    version_guarded: bool,
+    mmap: Arc<MmapMut>,
+}
+
+impl Debug for ShmReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShmReader")
+            .field("metadata", &self.metadata)
+            .field("data_ptr", &self.data_ptr)
+            .field("read_index", &self.read_index)
+            .field("record_offset", &self.record_offset)
+            .field("failure", &self.failure)
+            .field("on_exhausted", &self.on_exhausted.is_some())
+            .field("recover", &self.recover)
+            .field("skipped", &self.skipped)
+            .field("corruption_budget", &self.corruption_budget)
+            .field("heartbeats", &self.heartbeats)
+            .field("last_heartbeat", &self.last_heartbeat)
+            .field("peeked", &self.peeked)
+            .field("marks", &self.marks)
+            .field("scan_bound", &self.scan_bound)
+            .field("max_record", &self.max_record)
+            .field("marker_retries", &self.marker_retries)
+            .field("backing", &self.backing)
+            .field("guarded", &self.guarded)
+            .field("version", &self.version)
+            .field("version_guarded", &self.version_guarded)
+            .finish()
+    }
 }
 
-impl ShmReader {
-    #[allow(clippy::cast_ptr_alignment)]
-    pub(super) fn new(mut mmap: MmapMut) -> Result<ShmReader, ChannelError> {
-        let buf = &mut mmap[..];
-        let metadata = Metadata::read(buf)?;
-        let metadata_ptr = buf.as_ptr() as *mut u64;
-        let data_ptr = unsafe { metadata_ptr.add(metadata.len() as usize) } as *const u8;
-        info!("Kekbit Reader successfully created");
-        Ok(ShmReader {
-            metadata,
-            data_ptr,
-            read_index: 0,
-            failure: None,
-            _mmap: mmap,
-        })
+impl ShmReader {
+    #[inline]
+    pub(super) fn new(mmap: MmapMut) -> Result<ShmReader, ChannelError> {
+        let reader = ShmReader::from_shared(Arc::new(mmap))?;
+        info!(
+            "[channel {} writer {}] Kekbit Reader successfully created",
+            reader.metadata.channel_id(),
+            reader.metadata.writer_id()
+        );
+        Ok(reader)
+    }
+
+    #[allow(clippy::cast_ptr_alignment)]
+    fn from_shared(mmap: Arc<MmapMut>) -> Result<ShmReader, ChannelError> {
+        if !is_ptr_aligned(mmap.as_ptr()) {
+            return Err(ChannelError::MisalignedBuffer {
+                address: mmap.as_ptr() as usize,
+            });
+        }
+        let metadata = Metadata::read(&mmap[..])?;
+        let data_ptr = unsafe { mmap.as_ptr().add(metadata.len()) };
+        let version = load_atomic_u64(unsafe { mmap.as_ptr().add(VERSION_OFFSET) } as *mut u64, Ordering::Acquire);
+        Ok(ShmReader {
+            metadata,
+            data_ptr,
+            read_index: 0,
+            record_offset: 0,
+            failure: None,
+            on_exhausted: None,
+            recover: false,
+            skipped: Vec::new(),
+            corruption_budget: u64::MAX,
+            heartbeats: 0,
+            last_heartbeat: None,
+            peeked: None,
+            marks: Vec::new(),
+            last_header: 0,
+            reassembled: Vec::new(),
+            scan_bound: ScanBound::default(),
+            max_record: u32::MAX,
+            marker_retries: DEFAULT_MARKER_RETRIES,
+            raw: false,
+            #[cfg(feature = "compression")]
+            inflated: Vec::new(),
+            #[cfg(feature = "compression")]
+            inflating: false,
+            backing: None,
+            guarded: false,
+            version,
+            version_guarded: false,
+            mmap,
+        })
+    }
+
+    //Keeps the file which backs the memory map, so its length could be checked later.
+    #[inline]
+    pub(super) fn with_backing(mut self, backing: File) -> ShmReader {
+        self.backing = Some(backing);
+        self
+    }
+
+    //Returns the file which backs the memory map, if the channel is stored in a file.
+    #[inline]
+    pub(super) fn backing(&self) -> Option<&File> {
+        self.backing.as_ref()
+    }
+
+    /// Creates a new reader of the same channel, which shares the memory map and the backing storage
+    /// of this one rather than opening the channel again. The new reader has its own cursor, starting
+    /// from the beginning of the channel, and the default settings of a freshly created reader.
+    /// It is the only way to get another reader of a channel whose storage file was unlinked, so
+    /// it cannot be opened anymore by [shm_reader](fn.shm_reader.html).
+    ///
+    /// # Errors
+    ///
+    /// If the handle of the backing storage cannot be duplicated, or the channel metadata is no longer valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let other_reader = reader.try_clone().unwrap();
+    /// assert_eq!(other_reader.position(), 0);
+    /// ```
+    pub fn try_clone(&self) -> Result<ShmReader, std::io::Error> {
+        let mut reader = ShmReader::from_shared(self.mmap.clone())
+            .map_err(|err| std::io::Error::other(format!("Invalid channel metadata {:?}", err)))?;
+        if let Some(file) = &self.backing {
+            reader.backing = Some(file.try_clone()?);
+        }
+        info!(
+            "[channel {} writer {}] Kekbit Reader successfully cloned",
+            reader.metadata.channel_id(),
+            reader.metadata.writer_id()
+        );
+        Ok(reader)
+    }
+
+    ///Returns a reference to the [Metadata](struct.Metadata.html) associated with this channel
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Checks that the channel was created by the expected writer. A channel could be recreated, using the same
+    /// channel id, by a different writer, so a consumer may use this method to verify that it is connected
+    /// to the intended producer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer_id` - The identifier of the writer which is expected to have created the channel
+    ///
+    /// # Errors
+    ///
+    /// [WrongWriter](../api/enum.ChannelError.html#variant.WrongWriter) if the channel was created by another writer.
+    #[inline]
+    pub fn expect_writer(&self, writer_id: u64) -> Result<(), ChannelError> {
+        let actual = self.metadata.writer_id();
+        if actual != writer_id {
+            return Err(ChannelError::WrongWriter {
+                expected: writer_id,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the reader is bound to the expected channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_id` - The identifier of the expected channel
+    ///
+    /// # Errors
+    ///
+    /// [WrongChannel](../api/enum.ChannelError.html#variant.WrongChannel) if the reader is bound to another channel.
+    #[inline]
+    pub fn expect_channel(&self, channel_id: u64) -> Result<(), ChannelError> {
+        let actual = self.metadata.channel_id();
+        if actual != channel_id {
+            return Err(ChannelError::WrongChannel {
+                expected: channel_id,
+                actual,
+            });
+        }
+        Ok(())
+    }
+    ///Returns the current read position. It is also the `total` amount of bytes read
+    ///so far(including bytes from record headers and the one used for record padding)
+    pub fn position(&self) -> u32 {
+        self.read_index
+    }
+
+    /// Provides a *non-blocking* iterator over messages in the channel.
+    ///
+    /// Each call to [`next`] returns a message if there is one ready available. The iterator
+    /// will never block waiting for a message to be available.
+    ///
+    /// [`next`]: https://doc.rust-lang.org/std/iter/trait.Iterator.html#tymethod.next
+    ///
+    /// A non-blocking iterator is the most convenient method to read from a channel. The `size_hint` method could
+    /// be used to find if new records will ever be available to read from the channel.
+    ///
+    /// #Example
+    ///
+    /// let channel_id = 77977;
+    /// let tmp_dir = std::env::temp_dir().join("kekbit").join("echo_sample");
+    /// let mut reader = try_shm_reader(&tmp_dir, channel_id, 20_000, 60).unwrap();
+    /// let mut stop = false;
+    /// while !stop {
+    ///    let mut msg_iter = reader.try_iter();
+    ///    for msg in &mut msg_iter {
+    ///        let msg_str = std::str::from_utf8(&msg).unwrap();
+    ///        println!("Got message {}", msg_str);
+    ///    }
+    ///    if msg_iter.size_hint().1 == Some(0) {
+    ///        println!("Nothing more to read. Will stop");
+    ///        stop = true;
+    ///    } else {
+    ///        //wait, spin or do other work....
+    ///        std::thread::sleep(std::time::Duration::from_millis(200));
+    ///    }
+    ///}
+    ///
+    #[inline]
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator which yields every read result together with a channel position.
+    /// For a record the position is the offset where the record starts, which could be used later to
+    /// move back to that record, e.g. when building an index of the channel. For any other result it is
+    /// the current read position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// # let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// # writer.write(&"Indexed").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let index: Vec<u32> = reader
+    ///     .try_iter_positioned()
+    ///     .take_while(|(_, res)| matches!(res, ReadResult::Record(_)))
+    ///     .map(|(offset, _)| offset)
+    ///     .collect();
+    /// assert_eq!(index, vec![0]);
+    /// ```
+    #[inline]
+    pub fn try_iter_positioned(&mut self) -> PositionedIter<'_> {
+        PositionedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Checks if the last record read is part of an [atomic batch](struct.ShmWriter.html#method.write_batch)
+    /// which continues with the next record. As a batch is published all at once, the rest of the batch is already
+    /// available to this reader.
+    #[inline]
+    pub fn batch_continues(&self) -> bool {
+        self.last_header & BATCHED != 0
+    }
+
+    /// Provides a *non-blocking* iterator over the records currently available in the channel, as they are stored.
+    /// Every record is yielded together with its length word, including the flag bits set in its high bits, and
+    /// its position. The body is the stored one, so compressed records are not inflated and chunks are not
+    /// reassembled. It is meant for debugging and inspection tools. The iterator ends once no more records
+    /// are available, or if the channel is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Raw").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let raw = reader.raw_iter().next().unwrap();
+    /// assert_eq!((raw.length_word, raw.offset, raw.body), (3, 0, &b"Raw"[..]));
+    /// ```
+    #[inline]
+    pub fn raw_iter(&mut self) -> RawIter<'_> {
+        RawIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the text records currently available in the channel, such as
+    /// the records of a channel written using the `PlainTextDataFormat`. Every record is validated as UTF-8
+    /// and yielded as a string slice, or as an error if it is not valid text. The iterator ends once no more
+    /// records are available, or if the channel is exhausted, which could be checked with `exhausted`.
+    #[inline]
+    pub fn text_iter(&mut self) -> TextIter<'_, Self> {
+        TextIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the records currently available in the channel, which yields every record
+    /// together with the wall-clock time it was read at. It allows tracing the arrival of records, e.g. to log the gaps
+    /// between them, for channels which carry no producer timestamps. The clock is read once for every yielded record.
+    /// The iterator ends once no more records are available, or if the channel is exhausted.
+    #[inline]
+    pub fn timed_read_iter(&mut self) -> TimedIter<'_, Self> {
+        TimedIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the records currently available in the channel, split according to
+    /// the [record layout](struct.Metadata.html#method.with_record_layout) declared by the channel. The prefixes
+    /// added by the writer handlers, such timestamps and sequence numbers, are yielded apart from the record body.
+    /// The iterator ends once no more records are available, or if the channel is exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// let layout = RecordLayout { timestamp: false, sequence: true };
+    /// let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos).with_record_layout(layout);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let handler = ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default());
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, handler).unwrap();
+    /// writer.write(&"First").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let record = reader.structured_iter().next().unwrap();
+    /// assert_eq!(record.sequence, Some(1));
+    /// assert_eq!(record.body, b"First");
+    /// ```
+    #[inline]
+    pub fn structured_iter(&mut self) -> StructuredIter<'_> {
+        let layout = self.metadata.record_layout();
+        StructuredIter { inner: self, layout }
+    }
+
+    /// Provides a *non-blocking* iterator over the records of a channel written using a
+    /// [ChecksumHandler](struct.ChecksumHandler.html). Every record payload is yielded together with the
+    /// outcome of its checksum validation, so a corrupted record does not stop the iteration. The iterator
+    /// ends once no more records are available, or if the channel is exhausted.
+    #[inline]
+    pub fn verified_iter(&mut self) -> VerifiedIter<'_, Self> {
+        VerifiedIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel.
+    /// Once all the available messages are consumed, the iterator yields a single `Nothing` and
+    /// than ends. A new iterator should be created for the next polling round.
+    ///
+    /// #Example
+    ///
+    /// let mut reader = try_shm_reader(&tmp_dir, channel_id, 20_000, 60).unwrap();
+    /// while reader.exhausted().is_none() {
+    ///    for read_res in reader.coalesced_iter() {
+    ///        if let ReadResult::Record(msg) = read_res {
+    ///            println!("Got message {}", std::str::from_utf8(&msg).unwrap());
+    ///        }
+    ///    }
+    ///    std::thread::sleep(std::time::Duration::from_millis(200));
+    ///}
+    ///
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Registers a callback which will be invoked exactly once, when this reader
+    /// finds the channel exhausted. The callback receives the reason of the exhaustion.
+    /// If the reader is already exhausted the callback is invoked immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The callback to invoke on channel exhaustion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// reader.on_exhausted(Box::new(|err| println!("Channel exhausted {:?}", err)));
+    /// ```
+    #[inline]
+    pub fn on_exhausted(&mut self, cb: Box<dyn FnOnce(ReadError)>) {
+        match self.failure {
+            Some(failure) => cb(failure),
+            None => self.on_exhausted = Some(cb),
+        }
+    }
+
+    /// Returns true if the writer had declared the channel ready to be read.
+    /// See [set_ready](struct.ShmWriter.html#method.set_ready).
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        let ready_ptr = unsafe { self.mmap.as_ptr().add(READY_OFFSET) } as *mut u64;
+        load_atomic_u64(ready_ptr, Ordering::Acquire) == READY
+    }
+
+    /// Returns the latest control word published by the writer, together with its generation. The
+    /// generation is 0 if the writer never published a control word, and it is incremented by every update.
+    /// See [set_control_word](struct.ShmWriter.html#method.set_control_word).
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn control_word(&self) -> (u64, u64) {
+        let generation_ptr = unsafe { self.mmap.as_ptr().add(CONTROL_GENERATION_OFFSET) } as *mut u64;
+        let control_ptr = unsafe { self.mmap.as_ptr().add(CONTROL_OFFSET) } as *mut u64;
+        loop {
+            let generation = load_atomic_u64(generation_ptr, Ordering::Acquire);
+            if generation & 1 == 1 {
+                //the writer is updating the control word
+                std::hint::spin_loop();
+                continue;
+            }
+            let control = load_atomic_u64(control_ptr, Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if load_atomic_u64(generation_ptr, Ordering::Relaxed) == generation {
+                return (control, generation >> 1);
+            }
+        }
+    }
+
+    /// Returns the writer progress published in the channel footer, or `None` if the channel has no footer,
+    /// as it happens for the channels [archived](fn.archive_channel.html) before they were full.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Hello").unwrap();
+    /// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(reader.footer().unwrap().committed_len, 16);
+    /// ```
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn footer(&self) -> Option<FooterView> {
+        let footer_offset = self.metadata.len() + self.metadata.capacity() as usize;
+        if self.mmap.len() < footer_offset + FOOTER_LEN as usize {
+            return None;
+        }
+        let footer_ptr = unsafe { self.mmap.as_ptr().add(footer_offset) };
+        let committed_len = load_atomic_u64(
+            unsafe { footer_ptr.add(FOOTER_COMMITTED_OFFSET) } as *mut u64,
+            Ordering::Acquire,
+        );
+        let last_write = load_atomic_u64(
+            unsafe { footer_ptr.add(FOOTER_LAST_WRITE_OFFSET) } as *mut u64,
+            Ordering::Relaxed,
+        );
+        Some(FooterView {
+            committed_len: committed_len as u32,
+            last_write,
+            digest: footer_digest(footer_ptr),
+        })
+    }
+
+    /// Recomputes the digest of all the records committed into the channel, and compares it with the digest
+    /// published by the writer in the channel footer. Any change of the records data after it was written, even of a
+    /// single byte, is detected. As the records are read while the writer may still be active, the check is meant
+    /// for channels which are no longer written, such as closed channels. The reader position is not changed.
+    /// Requires the `digest` feature.
+    ///
+    /// Returns None if the writer does not compute a digest, otherwise true if the records match the digest.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = ChannelBuilder::new()
+    ///     .digest(true)
+    ///     .shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default())
+    ///     .unwrap();
+    /// writer.write(&"Signed").unwrap();
+    /// drop(writer);
+    /// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(reader.verify_digest(), Some(true));
+    /// ```
+    #[cfg(feature = "digest")]
+    pub fn verify_digest(&self) -> Option<bool> {
+        let footer = self.footer()?;
+        let expected = footer.digest?;
+        let rec_header_len = self.metadata.rec_header_len();
+        let end = min(footer.committed_len, self.metadata.capacity());
+        let mut hasher = blake3::Hasher::new();
+        let mut offset = 0;
+        while offset < end {
+            match strip_flags(self.word_at(offset)) {
+                WATERMARK | CLOSE => break,
+                HEARTBEAT => offset += rec_header_len,
+                len if len <= self.metadata.max_msg_len() as u64 && offset + rec_header_len + len as u32 <= end => {
+                    let record = unsafe {
+                        std::slice::from_raw_parts(self.data_ptr.add((offset + rec_header_len) as usize), len as usize)
+                    };
+                    digest_record(&mut hasher, record);
+                    offset += align(rec_header_len + len as u32);
+                }
+                _ => return Some(false),
+            }
+        }
+        Some(hasher.finalize() == expected)
+    }
+
+    /// Checks that the storage which backs the channel still covers the whole memory mapped region.
+    /// If the storage is truncated by another process, accessing the pages which are not backed anymore
+    /// raises a `SIGBUS` signal, which aborts the reader. Calling this method before a read guards
+    /// against such a failure, however the storage could still be truncated between the check and the read.
+    ///
+    /// Returns false if the storage was truncated or it cannot be accessed anymore.
+    #[inline]
+    pub fn validate_backing(&self) -> bool {
+        match &self.backing {
+            Some(file) => file
+                .metadata()
+                .map(|file_metadata| file_metadata.len() >= self.mmap.len() as u64)
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Switches this reader into the guarded mode. In this mode the backing storage is
+    /// [validated](#method.validate_backing) before every read, and if the storage was truncated
+    /// the read fails with `StorageVanished` rather than raising `SIGBUS`. As every validation
+    /// requires a system call, this mode should be used only if the storage could be
+    /// truncated by another process, e.g. for channels shared through `/dev/shm`.
+    #[inline]
+    pub fn guard_backing(&mut self) {
+        self.guarded = true;
+    }
+
+    /// Checks that the storage version of the channel is still the one found when this reader was created.
+    /// The version could change if the channel is reused by a writer built with a newer kekbit version,
+    /// in which case the records may not be readable anymore.
+    ///
+    /// Returns false if the storage version had changed.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub fn validate_version(&self) -> bool {
+        let version_ptr = unsafe { self.mmap.as_ptr().add(VERSION_OFFSET) } as *mut u64;
+        load_atomic_u64(version_ptr, Ordering::Acquire) == self.version
+    }
+
+    /// Switches this reader into the version guarded mode. In this mode the storage version is
+    /// [validated](#method.validate_version) before every read, and if it had changed
+    /// the read fails with `VersionChanged`. A validation is just an atomic load, so this mode
+    /// is cheap enough for long lived readers of channels which could be reused by other writers.
+    #[inline]
+    pub fn guard_version(&mut self) {
+        self.version_guarded = true;
+    }
+
+    /// Switches this reader into the corruption recovery mode. In this mode, rather than marking the
+    /// channel as failed when a corrupted record is found, the reader scans forward for the next
+    /// plausible record boundary, an aligned offset which holds a valid record length or a known marker,
+    /// and resumes reading from there. The skipped byte ranges are available through
+    /// [skipped](#method.skipped).
+    ///
+    /// This mode is meant for forensic recovery of the records which follow a corrupted region.
+    /// As a plausible record boundary is only a guess, the records read after a skipped range
+    /// should not be trusted blindly.
+    #[inline]
+    pub fn scan_recover(&mut self) {
+        self.recover = true;
+    }
+
+    /// Returns the byte ranges, relative to the beginning of the channel data, skipped so far
+    /// while reading in [recovery mode](#method.scan_recover).
+    #[inline]
+    pub fn skipped(&self) -> &[Range<u32>] {
+        &self.skipped
+    }
+
+    /// Returns the number of heartbeats seen so far. Heartbeats are never returned as records, but they prove
+    /// the writer is alive even when it has nothing to write.
+    #[inline]
+    pub fn heartbeats(&self) -> u64 {
+        self.heartbeats
+    }
+
+    /// Returns the writer's time of its last write, as published in the channel [footer](#method.footer) when this
+    /// reader last came across a heartbeat, in the channel's [tick unit](struct.Metadata.html#method.tick_unit)
+    /// since the epoch, or None if no heartbeat was seen yet. As the time is taken from the writer, a reader catching
+    /// up with an old backlog sees how long ago the writer was last alive. A liveness monitor could tell a writer
+    /// which is alive but idle, from a writer which is gone, by how recent the last heartbeat is.
+    #[inline]
+    pub fn last_heartbeat_at(&self) -> Option<u64> {
+        self.last_heartbeat
+    }
+
+    /// Returns the total number of bytes skipped so far while reading in [recovery mode](#method.scan_recover).
+    #[inline]
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped.iter().map(|range| (range.end - range.start) as u64).sum()
+    }
+
+    /// Provides a *non-blocking* iterator which reads in [recovery mode](#method.scan_recover), but tolerates
+    /// only a limited amount of corruption. Corrupted regions are skipped as long as the total number of skipped
+    /// bytes stays within the budget. The first corrupted region which would exceed the budget fails the read
+    /// with `Failed`, and the channel is marked as exhausted.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_corrupt_bytes` - The maximum number of corrupted bytes skipped over the lifetime of this reader
+    #[inline]
+    pub fn resilient_iter(&mut self, max_corrupt_bytes: u32) -> TryIter<'_, Self> {
+        self.recover = true;
+        self.corruption_budget = max_corrupt_bytes as u64;
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Sets the bound of every forward scan performed by this reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `scan_bound` - How far a single scan may walk the channel before it fails
+    #[inline]
+    pub fn set_scan_bound(&mut self, scan_bound: ScanBound) {
+        self.scan_bound = scan_bound;
+    }
+
+    ///Returns the bound of the forward scans performed by this reader.
+    #[inline]
+    pub fn scan_bound(&self) -> ScanBound {
+        self.scan_bound
+    }
+
+    /// Limits the length of the records this reader accepts, regardless of the maximum message length of the channel.
+    /// A reader of an untrusted channel may use it to bound the memory spent on a single record. Once a longer
+    /// record is found, or a compressed record which inflates beyond the limit, the read fails with
+    /// `RecordTooLarge` and the channel is marked as exhausted. By default there is no limit.
+    ///
+    /// Returns the reader with the new limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The maximum record length accepted by the reader, in bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"A rather long record").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap().with_max_record(8);
+    /// assert_eq!(reader.try_read(), Err(ReadError::RecordTooLarge(20)));
+    /// ```
+    #[inline]
+    pub fn with_max_record(mut self, max: u32) -> ShmReader {
+        self.max_record = max;
+        self
+    }
+
+    ///Returns the maximum record length accepted by this reader.
+    #[inline]
+    pub fn max_record(&self) -> u32 {
+        self.max_record
+    }
+
+    /// Sets how many times an unknown marker is loaded again, with a short backoff in between, before the
+    /// channel is declared corrupted. A reader which races a writer may see a word while it is still
+    /// being published, which settles soon into a valid record length or marker. By default an unknown
+    /// marker is loaded again 16 times, while 0 declares the corruption right away.
+    ///
+    /// Returns the reader with the new setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - How many times an unknown marker is loaded again
+    #[inline]
+    pub fn with_marker_retries(mut self, retries: u32) -> ShmReader {
+        self.marker_retries = retries;
+        self
+    }
+
+    ///Returns how many times an unknown marker is loaded again before the channel is declared corrupted.
+    #[inline]
+    pub fn marker_retries(&self) -> u32 {
+        self.marker_retries
+    }
+
+    //Loads again an unknown marker, until it settles into a record length or a known marker, or the retries are over.
+    #[allow(clippy::cast_ptr_alignment)]
+    fn marker_settled(&self, index: usize) -> bool {
+        let backoff = Backoff::new();
+        for _ in 0..self.marker_retries {
+            backoff.snooze();
+            match strip_flags(unsafe { load_atomic_u64(self.data_ptr.add(index) as *mut u64, Ordering::Acquire) }) {
+                WATERMARK | CLOSE => return true,
+                rec_len if rec_len <= self.metadata.max_msg_len() as u64 => return true,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    //Fails the read of a record longer than the reader limit.
+    #[inline]
+    fn check_max_record(&mut self, rec_len: u32) -> Result<(), ReadError> {
+        if rec_len <= self.max_record {
+            return Ok(());
+        }
+        error!(
+            "[channel {} writer {}] Record of {} bytes at position {} exceeds the reader limit of {} bytes",
+            self.metadata.channel_id(),
+            self.metadata.writer_id(),
+            rec_len,
+            self.read_index,
+            self.max_record
+        );
+        Err(self.record_failure(RecordTooLarge(rec_len)))
+    }
+
+    /// Reads the next record, like [try_read](trait.Reader.html#tymethod.try_read), but it also reads the records
+    /// compressed by a [CompressionHandler](struct.CompressionHandler.html), which `try_read` refuses with
+    /// `CompressedRecord`. A compressed record is decompressed into a buffer owned by the reader, so the
+    /// returned record is borrowed from the reader until the next read. Requires the `compression` feature.
+    ///
+    /// Returns the next record available in the channel, decompressed if needed, or None if there is no record.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [try_read](trait.Reader.html#tymethod.try_read), except `CompressedRecord`. A record which
+    /// cannot be decompressed is considered corrupted.
+    #[cfg(feature = "compression")]
+    pub fn read_inflated(&mut self) -> Result<Option<&[u8]>, ReadError> {
+        self.inflating = true;
+        let read = self.try_read();
+        self.inflating = false;
+        match read? {
+            Some(_) if self.last_header & COMPRESSED != 0 => Ok(Some(&self.inflated)),
+            record => Ok(record),
+        }
+    }
+
+    /// Reads the next record, like [try_read](trait.Reader.html#tymethod.try_read), but puts back together
+    /// the payloads written with [write_chunked](struct.ShmWriter.html#method.write_chunked). Other records
+    /// are returned unchanged. A reassembled payload is put together in a buffer owned by the reader, so it is
+    /// borrowed from the reader until the next read.
+    ///
+    /// Returns the next payload available in the channel, if there is one, None otherwise.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [try_read](trait.Reader.html#tymethod.try_read). If the chunks of a payload
+    /// end abruptly the channel is considered corrupted.
+    pub fn read_reassembled(&mut self) -> Result<Option<&[u8]>, ReadError> {
+        match self.try_read()? {
+            Some(record) if self.last_header & CONTINUED != 0 => {
+                let mut reassembled = std::mem::take(&mut self.reassembled);
+                reassembled.clear();
+                reassembled.extend_from_slice(record);
+                while self.last_header & CONTINUED != 0 {
+                    match self.try_read()? {
+                        Some(chunk) => reassembled.extend_from_slice(chunk),
+                        None => {
+                            error!(
+                                "[channel {} writer {}] Channel corrupted. Chunked record incomplete at position {}",
+                                self.metadata.channel_id(),
+                                self.metadata.writer_id(),
+                                self.read_index
+                            );
+                            return Err(self.record_failure(Failed));
+                        }
+                    }
+                }
+                self.reassembled = reassembled;
+                Ok(Some(&self.reassembled))
+            }
+            res => Ok(res),
+        }
+    }
+
+    /// Returns the next record available in the channel, without advancing the reader past it.
+    /// Until [commit](#method.commit) is called, any further read returns the same record,
+    /// so a consumer could process a record and acknowledge it only after the processing succeeded.
+    /// If the consumer crashes before the commit, the record will be read again.
+    ///
+    /// Returns the next record available in the channel, if there is one, None otherwise.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [try_read](trait.Reader.html#tymethod.try_read).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Process me once").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let record = reader.peek().unwrap().unwrap();
+    /// assert_eq!(record, reader.peek().unwrap().unwrap());
+    /// assert!(reader.commit());
+    /// assert_eq!(reader.peek().unwrap(), None);
+    /// ```
+    pub fn peek<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        let res = self.try_read();
+        //only the record is read again, the heartbeats and the corrupted regions which precede it stay
+        //behind the reader, so they are not counted again
+        if let Ok(Some(_)) = res {
+            self.peeked = Some(self.read_index);
+            self.read_index = self.record_offset;
+        }
+        res
+    }
+
+    /// Advances the reader past the record returned by the last [peek](#method.peek).
+    ///
+    /// Returns `true` if the reader was advanced, `false` if there was no peeked record to commit.
+    #[inline]
+    pub fn commit(&mut self) -> bool {
+        match self.peeked.take() {
+            Some(next) => {
+                self.read_index = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Saves the current position of the reader on a stack of marks, so the reader could read ahead
+    /// and later rewind to it using [pop_mark](#method.pop_mark). Marks could be nested.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Header").unwrap();
+    /// writer.write(&"Body").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// reader.push_mark();
+    /// assert_eq!(reader.try_read().unwrap().unwrap(), b"Header");
+    /// //the body is not there yet, so try again later
+    /// assert!(reader.pop_mark());
+    /// assert_eq!(reader.try_read().unwrap().unwrap(), b"Header");
+    /// ```
+    #[inline]
+    pub fn push_mark(&mut self) {
+        self.marks.push(self.read_index);
+    }
+
+    /// Rewinds the reader to the position saved by the last [push_mark](#method.push_mark), and removes that mark.
+    /// The records read since the mark was saved will be read again. A channel failure met after the mark was
+    /// saved is not undone.
+    ///
+    /// Returns `true` if the reader was rewound, `false` if there was no mark.
+    #[inline]
+    pub fn pop_mark(&mut self) -> bool {
+        match self.marks.pop() {
+            Some(mark) => {
+                self.seek(mark);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the mark saved by the last [push_mark](#method.push_mark), keeping the current position of the reader.
+    ///
+    /// Returns `true` if a mark was removed, `false` if there was no mark.
+    #[inline]
+    pub fn commit_mark(&mut self) -> bool {
+        self.marks.pop().is_some()
     }
-    ///Returns a reference to the [Metadata](struct.Metadata.html) associated with this channel
+
+    /// Returns the number of saved marks.
     #[inline]
-    pub fn metadata(&self) -> &Metadata {
-        &self.metadata
+    pub fn marks(&self) -> usize {
+        self.marks.len()
     }
-    ///Returns the current read position. It is also the `total` amount of bytes read
-    ///so far(including bytes from record headers and the one used for record padding)
-    pub fn position(&self) -> u32 {
-        self.read_index
+
+    /// Skips all the records currently available in the channel but the last one, and returns it. The next
+    /// reads will return only the records written after it. It should be used by the consumers which care
+    /// only about the most recent value(e.g. the latest price), as it spares them replaying the channel history.
+    ///
+    /// Returns the latest record, or `None` if no record is available.
+    ///
+    /// # Errors
+    ///
+    /// If the channel is corrupted, or if the channel was closed without any record available.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Old price").unwrap();
+    /// writer.write(&"Latest price").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(reader.seek_to_latest().unwrap().unwrap(), b"Latest price");
+    /// ```
+    pub fn seek_to_latest<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        let mut latest = None;
+        loop {
+            let start = self.read_index;
+            match self.try_read() {
+                Ok(Some(_)) => latest = Some(start),
+                Ok(None) => break,
+                Err(Closed) if latest.is_some() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        match latest {
+            //read the latest record again, as the buffers of a decompressed or reassembled record were reused meanwhile
+            Some(start) => {
+                self.seek(start);
+                self.try_read()
+            }
+            None => Ok(None),
+        }
     }
 
-    /// Provides a *non-blocking* iterator over messages in the channel.
+    /// Reads all the records currently available in the channel, and returns the last `n` of them together with
+    /// their offsets, the newest first. As the records have variable length they cannot be walked backwards, so
+    /// the records are read forward up to the channel frontier, and the next reads will return only the
+    /// records written afterwards. It is meant for inspecting the tail of a channel, as `tail -r` would.
+    /// As for [try_read](#method.try_read), a compressed record is valid only until the next read, so it
+    /// should not be used on channels holding compressed records.
     ///
-    /// Each call to [`next`] returns a message if there is one ready available. The iterator
-    /// will never block waiting for a message to be available.
+    /// # Arguments
     ///
-    /// [`next`]: https://doc.rust-lang.org/std/iter/trait.Iterator.html#tymethod.next
+    /// * `n` - The maximum number of records to return
     ///
-    /// A non-blocking iterator is the most convenient method to read from a channel. The `size_hint` method could
-    /// be used to find if new records will ever be available to read from the channel.
+    /// # Errors
     ///
-    /// #Example
+    /// If the channel is corrupted, or if the channel was closed without any record available.
     ///
-    /// let channel_id = 77977;
-    /// let tmp_dir = std::env::temp_dir().join("kekbit").join("echo_sample");
-    /// let mut reader = try_shm_reader(&tmp_dir, channel_id, 20_000, 60).unwrap();
-    /// let mut stop = false;
-    /// while !stop {
-    ///    let mut msg_iter = reader.try_iter();
-    ///    for msg in &mut msg_iter {
-    ///        let msg_str = std::str::from_utf8(&msg).unwrap();
-    ///        println!("Got message {}", msg_str);
-    ///    }
-    ///    if msg_iter.size_hint().1 == Some(0) {
-    ///        println!("Nothing more to read. Will stop");
-    ///        stop = true;
-    ///    } else {
-    ///        //wait, spin or do other work....
-    ///        std::thread::sleep(std::time::Duration::from_millis(200));
-    ///    }
-    ///}
+    /// # Examples
     ///
+    /// ```
+    /// # use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// # const FOREVER: u64 = 99_999_999_999;
+    /// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"First").unwrap();
+    /// writer.write(&"Second").unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// let records: Vec<&[u8]> = reader.recent(5).unwrap().into_iter().map(|(_, record)| record).collect();
+    /// assert_eq!(records, vec![&b"Second"[..], &b"First"[..]]);
+    /// ```
+    pub fn recent<'a>(&mut self, n: usize) -> Result<Vec<(u32, &'a [u8])>, ReadError> {
+        let mut recent = VecDeque::with_capacity(n);
+        let mut found = false;
+        loop {
+            match self.try_read() {
+                Ok(Some(record)) => {
+                    found = true;
+                    if n > 0 {
+                        if recent.len() == n {
+                            recent.pop_front();
+                        }
+                        recent.push_back((self.record_offset, record));
+                    }
+                }
+                Ok(None) => break,
+                Err(Closed) if found => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(recent.into_iter().rev().collect())
+    }
+
+    //Returns the first aligned offset after the given one which looks like a record boundary.
+    //A zero word is not trusted as a boundary, as zeroed garbage would look like a run of heartbeats.
+    fn resync(&self, offset: u32) -> Option<u32> {
+        let capacity = self.metadata.capacity();
+        let max_msg_len = self.metadata.max_msg_len() as u64;
+        let rec_header_len = self.metadata.rec_header_len();
+        let mut crt = offset + U64_SIZE as u32;
+        while crt <= capacity && !self.scan_bound.exceeded(0, crt - offset) {
+            match strip_flags(self.word_at(crt)) {
+                HEARTBEAT => crt += U64_SIZE as u32,
+                WATERMARK | CLOSE => return Some(crt),
+                len if len <= max_msg_len && crt + align(rec_header_len + len as u32) <= capacity => return Some(crt),
+                _ => crt += U64_SIZE as u32,
+            }
+        }
+        None
+    }
+
+    //Reads the slot which starts at the given offset. See read_slot.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    fn slot_at(&self, offset: u32) -> Slot {
+        let data_ptr = self.data_ptr;
+        read_slot(
+            |offset, order| unsafe { load_atomic_u64(data_ptr.add(offset as usize) as *mut u64, order) },
+            offset,
+            self.metadata.rec_header_len(),
+            self.metadata.max_msg_len(),
+            self.metadata.capacity(),
+        )
+    }
+
+    //Returns the offset of the record which follows the record stored at the given offset,
+    //or None if there is no record available at that offset. It never changes the reader state.
+    //The record length is also returned, so heartbeats can be told apart.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub(super) fn next_record_offset(&self, offset: u32) -> Option<(u32, u32)> {
+        if offset as usize + U64_SIZE >= self.metadata.capacity() as usize {
+            return None;
+        }
+        let rec_len = strip_flags(unsafe { load_atomic_u64(self.data_ptr.add(offset as usize) as *mut u64, Ordering::Acquire) });
+        if rec_len <= self.metadata.max_msg_len() as u64 {
+            Some((
+                offset + align(self.metadata.rec_header_len() + rec_len as u32),
+                rec_len as u32,
+            ))
+        } else {
+            None
+        }
+    }
+
+    //Returns the u64 word stored at the given offset of the data region.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub(super) fn word_at(&self, offset: u32) -> u64 {
+        debug_assert!(offset as usize + U64_SIZE <= self.metadata.capacity() as usize + FOOTER_LEN as usize);
+        unsafe { load_atomic_u64(self.data_ptr.add(offset as usize) as *mut u64, Ordering::Acquire) }
+    }
+
+    //Moves the reader to the given offset, which must point to the beginning of a record.
     #[inline]
-    pub fn try_iter(&mut self) -> TryIter<Self> {
-        TryIter { inner: self }
+    pub(super) fn seek(&mut self, offset: u32) {
+        self.peeked = None;
+        self.read_index = offset;
+    }
+
+    //Decompresses a compressed record into the reader buffer. It is done only for the reads which borrow the
+    //record from the reader, every other read stops at the compressed record.
+    #[cfg(feature = "compression")]
+    fn inflate(&mut self, record: &[u8]) -> Result<(), ReadError> {
+        if !self.inflating {
+            trace!(
+                "[channel {} writer {}] Compressed record at position {} must be read with read_inflated",
+                self.metadata.channel_id(),
+                self.metadata.writer_id(),
+                self.read_index
+            );
+            return Err(CompressedRecord);
+        }
+        //the inflated length is prepended to the compressed record, so it is checked before anything is allocated
+        if let Some(inflated_len) = super::read_u32_at(record, 0) {
+            self.check_max_record(inflated_len)?;
+        }
+        match lz4_flex::decompress_size_prepended(record) {
+            Ok(data) => {
+                self.inflated = data;
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "[channel {} writer {}] Channel corrupted. Compressed record at position {} failed: {}",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    self.read_index,
+                    err
+                );
+                Err(self.record_failure(Failed))
+            }
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn inflate(&mut self, _record: &[u8]) -> Result<(), ReadError> {
+        error!(
+            "[channel {} writer {}] Compressed record found at position {}, but the compression feature is not enabled",
+            self.metadata.channel_id(),
+            self.metadata.writer_id(),
+            self.read_index
+        );
+        Err(self.record_failure(Failed))
     }
 
     #[inline]
-    fn record_failure(&mut self, failure: ReadError) -> ReadError {
+    pub(super) fn record_failure(&mut self, failure: ReadError) -> ReadError {
         if self.failure.is_none() {
             self.failure = Some(failure);
+            if let Some(cb) = self.on_exhausted.take() {
+                cb(failure);
+            }
         }
         failure
     }
@@ -122,6 +1305,10 @@ impl Reader for ShmReader {
     /// # Errors
     /// Various [errors](enum.ReadError.html) may occur such: end of channel is reached, channel is closed or channel data is corrupted.
     /// Once an error occurs tha channle will be *marked as exhausted* so *any future read operation will fail*.
+    /// Heartbeats are skipped, they are never returned as records.
+    /// A record which is not followed by a marker or by another record is not trusted, as its writer
+    /// died while publishing it, so no record is returned. Readers in [recovery](#method.scan_recover) mode
+    /// return such a record, and treat the bytes which follow it as corruption.
     ///
     /// # Examples
     ///
@@ -147,35 +1334,137 @@ impl Reader for ShmReader {
     ///
     #[allow(clippy::cast_ptr_alignment)]
     fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
-        let crt_index = self.read_index as usize;
-        debug_assert!(crt_index + U64_SIZE < self.metadata.capacity() as usize);
-        let rec_len: u64 = unsafe { load_atomic_u64(self.data_ptr.add(crt_index) as *mut u64, Ordering::Acquire) };
-        if rec_len <= self.metadata.max_msg_len() as u64 {
-            let rec_size = align(REC_HEADER_LEN + rec_len as u32);
-            debug_assert!((crt_index + rec_size as usize) < self.metadata.capacity() as usize);
-            self.read_index += rec_size;
-            debug_assert!(rec_len > 0);
-            unsafe {
-                Ok(Some(std::slice::from_raw_parts(
-                    self.data_ptr.add(crt_index + REC_HEADER_LEN as usize),
-                    rec_len as usize,
-                )))
+        if self.guarded && !self.validate_backing() {
+            error!(
+                "[channel {} writer {}] Channel storage was truncated. The channel cannot be read anymore",
+                self.metadata.channel_id(),
+                self.metadata.writer_id()
+            );
+            return Err(self.record_failure(StorageVanished));
+        }
+        if self.version_guarded && !self.validate_version() {
+            error!(
+                "[channel {} writer {}] Channel storage version had changed. The channel cannot be read anymore",
+                self.metadata.channel_id(),
+                self.metadata.writer_id()
+            );
+            return Err(self.record_failure(VersionChanged));
+        }
+        //a corrupted region or a settled marker restarts the read from the new position
+        loop {
+            self.peeked = None;
+            //a record may end exactly at the capacity, the marker which follows it is kept in the footer
+            debug_assert!(self.read_index <= self.metadata.capacity());
+            let mut slot = self.slot_at(self.read_index);
+            let mut heartbeats = 0u32;
+            while slot == Slot::Heartbeat {
+                self.read_index += self.metadata.rec_header_len();
+                heartbeats += 1;
+                if self.read_index > self.metadata.capacity()
+                    || self
+                        .scan_bound
+                        .exceeded(heartbeats, heartbeats * self.metadata.rec_header_len())
+                {
+                    error!(
+                        "[channel {} writer {}] Channel corrupted. Too many heartbeats, {} skipped up to position {}",
+                        self.metadata.channel_id(),
+                        self.metadata.writer_id(),
+                        heartbeats,
+                        self.read_index
+                    );
+                    return Err(self.record_failure(Failed));
+                }
+                slot = self.slot_at(self.read_index);
             }
-        } else {
-            match rec_len {
-                WATERMARK => Ok(None),
-                CLOSE => {
-                    info!("Producer closed channel");
-                    Err(self.record_failure(Closed))
+            if heartbeats > 0 {
+                self.heartbeats += heartbeats as u64;
+                //the writer's own clock, a reader catching up with a backlog must not report stale heartbeats as recent
+                if let Some(footer) = self.footer() {
+                    self.last_heartbeat = Some(footer.last_write);
+                }
+            }
+            let crt_index = self.read_index as usize;
+            let (header, next_index) = match slot {
+                Slot::Record { header, next } => (header, next),
+                //a recovering reader treats the bytes which follow an unpublished record as corruption to be skipped
+                Slot::Unpublished { header, next } if self.recover => (header, next),
+                Slot::Unpublished { .. } => {
+                    trace!(
+                        "[channel {} writer {}] Unpublished record at position {}. No valid data follows it",
+                        self.metadata.channel_id(),
+                        self.metadata.writer_id(),
+                        crt_index
+                    );
+                    return Ok(None);
+                }
+                //the heartbeats were skipped above
+                Slot::Watermark | Slot::Heartbeat => return Ok(None),
+                Slot::Close => {
+                    info!(
+                        "[channel {} writer {}] Producer closed channel",
+                        self.metadata.channel_id(),
+                        self.metadata.writer_id()
+                    );
+                    return Err(self.record_failure(Closed));
                 }
-                _ => {
+                Slot::Unknown(marker) => {
+                    if self.marker_settled(crt_index) {
+                        trace!(
+                            "[channel {} writer {}] Marker {:#016X} at position {} settled after a retry",
+                            self.metadata.channel_id(),
+                            self.metadata.writer_id(),
+                            marker,
+                            self.read_index
+                        );
+                        continue;
+                    }
                     error!(
-                        "Channel corrupted. Unknown Marker {:#016X} at position {} ",
-                        rec_len, self.read_index,
+                        "[channel {} writer {}] Channel corrupted. Unknown Marker {:#016X} at position {} ",
+                        self.metadata.channel_id(),
+                        self.metadata.writer_id(),
+                        marker,
+                        self.read_index,
                     );
-                    Err(self.record_failure(Failed))
+                    if self.recover {
+                        if let Some(next) = self.resync(self.read_index) {
+                            if self.skipped_bytes() + (next - self.read_index) as u64 > self.corruption_budget {
+                                error!(
+                                    "[channel {} writer {}] Corruption budget of {} bytes exceeded at position {}",
+                                    self.metadata.channel_id(),
+                                    self.metadata.writer_id(),
+                                    self.corruption_budget,
+                                    self.read_index
+                                );
+                                return Err(self.record_failure(Failed));
+                            }
+                            warn!(
+                                "[channel {} writer {}] Skipping corrupted bytes from {} to {}",
+                                self.metadata.channel_id(),
+                                self.metadata.writer_id(),
+                                self.read_index,
+                                next
+                            );
+                            self.skipped.push(self.read_index..next);
+                            self.read_index = next;
+                            continue;
+                        }
+                    }
+                    return Err(self.record_failure(Failed));
                 }
+            };
+            let rec_len = strip_flags(header) as u32;
+            debug_assert!(next_index <= self.metadata.capacity());
+            self.check_max_record(rec_len)?;
+            let rec_header_len = self.metadata.rec_header_len();
+            let record =
+                unsafe { std::slice::from_raw_parts(self.data_ptr.add(crt_index + rec_header_len as usize), rec_len as usize) };
+            if header & COMPRESSED != 0 && !self.raw {
+                self.inflate(record)?;
             }
+            self.last_header = header;
+            self.record_offset = crt_index as u32;
+            self.read_index = next_index;
+            return Ok(Some(record));
         }
     }
 
@@ -199,6 +1488,7 @@ pub struct TimeoutReader<R: Reader> {
     to_interval: u64,
     expiration: u64,
     expired: Option<ReadError>,
+    deadline: u64,
 }
 
 impl<R: Reader> TimeoutReader<R> {
@@ -209,7 +1499,7 @@ impl<R: Reader> TimeoutReader<R> {
     /// * `reader` - The reader which will be decorated
     /// * `tick` - The tick unit used to measure time
     /// * `timeout` - The time interval in *ticks* after which this reader will
-    /// consider the channel exhausted if no new records were pushed into
+    ///   consider the channel exhausted if no new records were pushed into
     ///
     ///
     #[inline]
@@ -220,13 +1510,79 @@ impl<R: Reader> TimeoutReader<R> {
             to_interval: timeout,
             expiration: END_OF_TIME,
             expired: None,
+            deadline: END_OF_TIME,
         }
     }
 
     /// Provides a *non-blocking* iterator over messages in the channel.
     #[inline]
-    pub fn try_iter(&mut self) -> TryIter<Self> {
-        TryIter { inner: self }
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Attempts to read a message from the channel without blocking, using a deadline tighter than the channel timeout.
+    /// The deadline starts when this method first finds no record available, and it is cleared as soon as a record is read.
+    /// Unlike the channel timeout, a missed deadline does not mark the channel as exhausted, so it is
+    /// well suited for request/reply patterns where a fast reply is expected.
+    ///
+    /// Returns the next message available from the channel, if there is one, None otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticks` - The time interval in *ticks* in which a record is expected
+    ///
+    /// # Errors
+    ///
+    /// `Timeout` if no record arrived within the given interval, or any error returned by [try_read](#method.try_read).
+    pub fn try_read_within<'b>(&mut self, ticks: u64) -> Result<Option<&'b [u8]>, ReadError> {
+        let read_res = self.try_read()?;
+        if read_res.is_some() {
+            self.deadline = END_OF_TIME;
+            return Ok(read_res);
+        }
+        let crt_time = self.tick.nix_time();
+        if self.deadline == END_OF_TIME {
+            self.deadline = crt_time + ticks;
+        } else if self.deadline <= crt_time {
+            let deadline = self.deadline;
+            self.deadline = END_OF_TIME;
+            return Err(Timeout(deadline));
+        }
+        Ok(None)
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel,
+    /// which ends after it yields a single `Nothing`.
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over the text records currently available in the channel.
+    /// See [ShmReader::text_iter](struct.ShmReader.html#method.text_iter).
+    #[inline]
+    pub fn text_iter(&mut self) -> TextIter<'_, Self> {
+        TextIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the records currently available in the channel, paired with their read time.
+    /// See [ShmReader::timed_read_iter](struct.ShmReader.html#method.timed_read_iter).
+    #[inline]
+    pub fn timed_read_iter(&mut self) -> TimedIter<'_, Self> {
+        TimedIter { inner: self }
+    }
+
+    /// Provides a *non-blocking* iterator over the checksummed records currently available in the channel.
+    /// See [ShmReader::verified_iter](struct.ShmReader.html#method.verified_iter).
+    #[inline]
+    pub fn verified_iter(&mut self) -> VerifiedIter<'_, Self> {
+        VerifiedIter { inner: self }
     }
 }
 
@@ -283,21 +1639,26 @@ pub enum ReadResult<'a> {
 
 ///A non-blocking iterator over messages in the channel.
 ///Each call to `next` returns a message if there is one ready to be received.
-///The iterator never blocks waiting for a message.
-#[repr(transparent)]
+///The iterator never blocks waiting for a message. It ends once the channel is exhausted, or after it
+///yields a failure which leaves the reader in place, such as `CompressedRecord`, as the same failure
+///would be yielded again and again.
 pub struct TryIter<'a, R: Reader> {
-    inner: &'a mut R,
+    pub(super) inner: &'a mut R,
+    pub(super) done: bool,
 }
 
 impl<'a, R: Reader> Iterator for TryIter<'a, R> {
     type Item = ReadResult<'a>;
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.inner.exhausted().is_none() {
+        if !self.done && self.inner.exhausted().is_none() {
             match self.inner.try_read() {
                 Ok(None) => Some(ReadResult::Nothing),
                 Ok(Some(record)) => Some(ReadResult::Record(record)),
-                Err(fault) => Some(ReadResult::Failed(fault)),
+                Err(fault) => {
+                    self.done = true;
+                    Some(ReadResult::Failed(fault))
+                }
             }
         } else {
             None
@@ -307,7 +1668,7 @@ impl<'a, R: Reader> Iterator for TryIter<'a, R> {
     ///the channel is exhausted. Use this method if you want to know if future `next` calls will ever produce more items.
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        if self.inner.exhausted().is_none() {
+        if !self.done && self.inner.exhausted().is_none() {
             (0, None)
         } else {
             (0, Some(0))
@@ -316,3 +1677,242 @@ impl<'a, R: Reader> Iterator for TryIter<'a, R> {
 }
 
 impl<'a, R: Reader> FusedIterator for TryIter<'a, R> {}
+
+impl<'a, R: Reader> TryIter<'a, R> {
+    /// Turns this iterator into an iterator over the decoded records currently available in the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The data format used to encode the records
+    #[inline]
+    pub fn decoded<D: DataFormat, T: Decodable<D>>(self, format: D) -> DecodedIter<'a, R, D, T> {
+        DecodedIter {
+            inner: self.inner,
+            format,
+            done: false,
+            _decoded: PhantomData,
+        }
+    }
+}
+
+///A non-blocking iterator over the records currently available in the channel, decoded using a given data format.
+///A record which cannot be decoded is yielded as an error, and the iteration goes on with the next record.
+///The iterator ends when no more records are available, when the channel is closed, or after it yields
+///a read error, such as the one which exhausted the channel or a `CompressedRecord` which it cannot decode.
+pub struct DecodedIter<'a, R: Reader, D: DataFormat, T: Decodable<D>> {
+    inner: &'a mut R,
+    format: D,
+    done: bool,
+    _decoded: PhantomData<T>,
+}
+
+impl<'a, R: Reader, D: DataFormat, T: Decodable<D>> DecodedIter<'a, R, D, T> {
+    /// Consumes the iterator, splitting the successfully decoded records from the failures.
+    ///
+    /// Returns the decoded records and the failures, both in the order they occurred.
+    #[inline]
+    pub fn partition_results(self) -> (Vec<T>, Vec<DecodeError>) {
+        let mut decoded = Vec::new();
+        let mut failures = Vec::new();
+        for res in self {
+            match res {
+                Ok(value) => decoded.push(value),
+                Err(failure) => failures.push(failure),
+            }
+        }
+        (decoded, failures)
+    }
+}
+
+impl<'a, R: Reader, D: DataFormat, T: Decodable<D>> Iterator for DecodedIter<'a, R, D, T> {
+    type Item = Result<T, DecodeError>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(Some(mut record)) => Some(T::decode(&self.format, &mut record).map_err(DecodeError::Decoding)),
+            Ok(None) | Err(Closed) => {
+                self.done = true;
+                None
+            }
+            Err(fault) => {
+                self.done = true;
+                Some(Err(DecodeError::Read(fault)))
+            }
+        }
+    }
+}
+
+impl<'a, R: Reader, D: DataFormat, T: Decodable<D>> FusedIterator for DecodedIter<'a, R, D, T> {}
+
+///A non-blocking iterator over the text records currently available in the channel.
+///Every record is yielded as a string slice, or as an error if it is not valid UTF-8.
+///The iterator ends when no more records are available, the channel is exhausted, or at a record
+///which cannot be read, such as a compressed one, which is left in place.
+pub struct TextIter<'a, R: Reader> {
+    inner: &'a mut R,
+}
+
+impl<'a, R: Reader> Iterator for TextIter<'a, R> {
+    type Item = Result<&'a str, Utf8Error>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(Some(record)) => Some(std::str::from_utf8(record)),
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+///A non-blocking iterator over the records currently available in the channel, which yields every record
+///together with the wall-clock time it was read at. The iterator ends when no more records are available, the channel
+///is exhausted, or at a record which cannot be read, such as a compressed one, which is left in place.
+pub struct TimedIter<'a, R: Reader> {
+    inner: &'a mut R,
+}
+
+impl<'a, R: Reader> Iterator for TimedIter<'a, R> {
+    type Item = (SystemTime, &'a [u8]);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(Some(record)) => Some((SystemTime::now(), record)),
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+/// A record as it is stored in the channel. See [raw_iter](struct.ShmReader.html#method.raw_iter).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawRecord<'a> {
+    /// The length word stored in the record header, with all its flag bits.
+    pub length_word: u64,
+    /// The offset of the record, relative to the beginning of the channel data.
+    pub offset: u32,
+    /// The stored record body.
+    pub body: &'a [u8],
+}
+
+///A non-blocking iterator over the records currently available in the channel, as they are stored.
+///See [raw_iter](struct.ShmReader.html#method.raw_iter).
+///The iterator ends when no more records are available or the channel is exhausted.
+pub struct RawIter<'a> {
+    inner: &'a mut ShmReader,
+}
+
+impl<'a> Iterator for RawIter<'a> {
+    type Item = RawRecord<'a>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.exhausted().is_some() {
+            return None;
+        }
+        //the stored body is yielded, so a compressed record is not inflated
+        self.inner.raw = true;
+        let read = self.inner.try_read();
+        self.inner.raw = false;
+        match read {
+            Ok(Some(_)) => {
+                let length_word = self.inner.last_header;
+                let offset = self.inner.record_offset;
+                let body_offset = (offset + self.inner.metadata.rec_header_len()) as usize;
+                let body = unsafe {
+                    std::slice::from_raw_parts(self.inner.data_ptr.add(body_offset), strip_flags(length_word) as usize)
+                };
+                Some(RawRecord {
+                    length_word,
+                    offset,
+                    body,
+                })
+            }
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+///A non-blocking iterator over messages in the channel, which yields every read result together with
+///the position it was found at. See [try_iter_positioned](struct.ShmReader.html#method.try_iter_positioned).
+///Like [TryIter](struct.TryIter.html), it ends after it yields a failure.
+pub struct PositionedIter<'a> {
+    inner: &'a mut ShmReader,
+    done: bool,
+}
+
+impl<'a> Iterator for PositionedIter<'a> {
+    type Item = (u32, ReadResult<'a>);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.done && self.inner.exhausted().is_none() {
+            match self.inner.try_read() {
+                Ok(None) => Some((self.inner.read_index, ReadResult::Nothing)),
+                Ok(Some(record)) => Some((self.inner.record_offset, ReadResult::Record(record))),
+                Err(fault) => {
+                    self.done = true;
+                    Some((self.inner.read_index, ReadResult::Failed(fault)))
+                }
+            }
+        } else {
+            None
+        }
+    }
+    ///Returns (0, None) if records may be still available in the channel or (0, Some(0)) if
+    ///the channel is exhausted.
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if !self.done && self.inner.exhausted().is_none() {
+            (0, None)
+        } else {
+            (0, Some(0))
+        }
+    }
+}
+
+impl<'a> FusedIterator for PositionedIter<'a> {}
+
+///A non-blocking iterator over the messages currently available in the channel.
+///Once no more messages are available, it yields exactly one `Nothing` and than it ends,
+///so a `for` loop over it stops at the current end of the channel. It also ends after it yields a failure.
+pub struct CoalescedIter<'a, R: Reader> {
+    pub(super) inner: &'a mut R,
+    pub(super) done: bool,
+}
+
+impl<'a, R: Reader> Iterator for CoalescedIter<'a, R> {
+    type Item = ReadResult<'a>;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.inner.exhausted().is_some() {
+            return None;
+        }
+        match self.inner.try_read() {
+            Ok(None) => {
+                self.done = true;
+                Some(ReadResult::Nothing)
+            }
+            Ok(Some(record)) => Some(ReadResult::Record(record)),
+            Err(fault) => {
+                self.done = true;
+                Some(ReadResult::Failed(fault))
+            }
+        }
+    }
+    ///Returns (0, Some(0)) if the current polling round or the channel are over, (0, None) otherwise.
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done || self.inner.exhausted().is_some() {
+            (0, Some(0))
+        } else {
+            (0, None)
+        }
+    }
+}
+
+impl<'a, R: Reader> FusedIterator for CoalescedIter<'a, R> {}
@@ -0,0 +1,249 @@
+//! Provides a reader which follows a channel across its re-creations.
+use super::{shm_reader, storage_path, CoalescedIter, ShmReader, TryIter};
+use crate::api::{ChannelError, ReadError, Reader};
+use std::path::{Path, PathBuf};
+
+/// A [Reader](../api/trait.Reader.html) of a channel which may be deleted and created again with the same id,
+/// e.g. by a producer which rotates its channels. When the channel is closed, or its storage file is replaced,
+/// the reader opens the new channel and continues with its records. Until the new channel is created no record
+/// is available, so a reconnecting reader is never closed.
+///
+/// By default every new channel is read from its beginning, but the reader could also skip the records
+/// already written into a new channel, and read only the records written after it reconnected.
+/// As the storage of the previous channel is released on reconnection, a record is valid only until the next read.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Old channel").unwrap();
+/// let mut reader = ReconnectingReader::new(&test_tmp_dir.path(), 42).unwrap();
+/// assert_eq!(reader.try_read().unwrap().unwrap(), b"Old channel");
+/// drop(writer);
+/// std::fs::remove_file(storage_path(&test_tmp_dir.path(), 42)).unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"New channel").unwrap();
+/// assert_eq!(reader.try_read().unwrap().unwrap(), b"New channel");
+/// ```
+#[derive(Debug)]
+pub struct ReconnectingReader {
+    root_path: PathBuf,
+    channel_id: u64,
+    reader: ShmReader,
+    skip_history: bool,
+    reconnects: u64,
+}
+
+impl ReconnectingReader {
+    /// Creates a reader which follows the channel with the given id. The current channel is read from its beginning.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_path` - The path to the folder where all the channels will be stored grouped by writer's id.
+    /// * `channel_id` - The channel identifier.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by [shm_reader](fn.shm_reader.html) if the current channel cannot be opened.
+    #[inline]
+    pub fn new(root_path: &Path, channel_id: u64) -> Result<ReconnectingReader, ChannelError> {
+        let reader = shm_reader(root_path, channel_id)?;
+        Ok(ReconnectingReader {
+            root_path: root_path.to_path_buf(),
+            channel_id,
+            reader,
+            skip_history: false,
+            reconnects: 0,
+        })
+    }
+
+    /// Sets whether the records written into a new channel before the reader reconnects to it are skipped.
+    ///
+    /// Returns the reader with the new setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `skip_history` - If true only the records written after a reconnection are read
+    #[inline]
+    pub fn skip_history(mut self, skip_history: bool) -> ReconnectingReader {
+        self.skip_history = skip_history;
+        self
+    }
+
+    /// Returns how many times the reader had moved to a new channel.
+    #[inline]
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Returns the reader of the channel currently followed.
+    #[inline]
+    pub fn reader(&self) -> &ShmReader {
+        &self.reader
+    }
+
+    /// Provides a *non-blocking* iterator over messages in the channel.
+    #[inline]
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel,
+    /// which ends after it yields a single `Nothing`.
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    //Checks if the storage file of the channel is not the one read anymore, as it was removed or replaced.
+    #[cfg(unix)]
+    fn replaced(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let current = match self.reader.backing().map(|file| file.metadata()) {
+            Some(Ok(current)) => current,
+            _ => return false,
+        };
+        match std::fs::metadata(storage_path(&self.root_path, self.channel_id)) {
+            Ok(latest) => latest.dev() != current.dev() || latest.ino() != current.ino(),
+            Err(_) => true,
+        }
+    }
+
+    //Without file identities a replaced storage is noticed only once the channel it held is closed.
+    #[cfg(not(unix))]
+    fn replaced(&self) -> bool {
+        self.reader.exhausted() == Some(ReadError::Closed)
+    }
+
+    //Moves to the channel which replaced the current one, if it exists and it is a new channel.
+    fn reconnect(&mut self) -> bool {
+        let mut reader = match shm_reader(&self.root_path, self.channel_id) {
+            Ok(reader) => reader,
+            Err(_) => return false,
+        };
+        if same_channel(&reader, &self.reader) {
+            return false;
+        }
+        if self.skip_history {
+            while let Ok(Some(_)) = reader.try_read() {}
+        }
+        self.reader = reader;
+        self.reconnects += 1;
+        info!(
+            "[channel {}] Reconnected to the new channel, created at {}",
+            self.channel_id,
+            self.reader.metadata().creation_time()
+        );
+        true
+    }
+}
+
+//Checks if two readers read the same storage file.
+#[cfg(unix)]
+fn same_channel(reader: &ShmReader, other: &ShmReader) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (reader.backing(), other.backing()) {
+        (Some(file), Some(other_file)) => match (file.metadata(), other_file.metadata()) {
+            (Ok(meta), Ok(other_meta)) => meta.dev() == other_meta.dev() && meta.ino() == other_meta.ino(),
+            _ => true,
+        },
+        _ => reader.metadata() == other.metadata(),
+    }
+}
+
+//Without file identities, the channels are told apart by their metadata, which holds their creation time.
+#[cfg(not(unix))]
+fn same_channel(reader: &ShmReader, other: &ShmReader) -> bool {
+    reader.metadata() == other.metadata()
+}
+
+impl Reader for ReconnectingReader {
+    /// Reads the next record of the channel currently followed, moving to a new channel once the current
+    /// one is closed or replaced.
+    ///
+    /// # Errors
+    ///
+    /// Any error, other than `Closed`, of the channel currently followed, unless that channel was replaced.
+    fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        match self.reader.try_read() {
+            Ok(Some(record)) => Ok(Some(record)),
+            Ok(None) if self.replaced() && self.reconnect() => self.reader.try_read(),
+            Ok(None) => Ok(None),
+            Err(err) => {
+                if self.replaced() && self.reconnect() {
+                    self.reader.try_read()
+                } else if err == ReadError::Closed {
+                    Ok(None)
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    ///A reconnecting reader is exhausted only if the channel currently followed failed for other reason than
+    ///being closed, and it was not replaced yet.
+    #[inline]
+    fn exhausted(&self) -> Option<ReadError> {
+        self.reader.exhausted().filter(|err| *err != ReadError::Closed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{read_u64_at, shm_writer, Metadata};
+    use std::fs::remove_file;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn follow_recreated_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&1u64).unwrap();
+        let mut reader = ReconnectingReader::new(test_tmp_dir.path(), 1000).unwrap();
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(1));
+        //the channel is closed, then created again
+        drop(writer);
+        assert_eq!(reader.try_read(), Ok(None));
+        assert_eq!(reader.exhausted(), None);
+        remove_file(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        assert_eq!(reader.try_read(), Ok(None));
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&2u64).unwrap();
+        writer.write(&3u64).unwrap();
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(2));
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(3));
+        assert_eq!(reader.reconnects(), 1);
+        //the storage is replaced while its writer is still alive, the history of the new channel is skipped
+        let mut reader = reader.skip_history(true);
+        remove_file(storage_path(test_tmp_dir.path(), 1000)).unwrap();
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut new_writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        new_writer.write(&4u64).unwrap();
+        writer.write(&100u64).unwrap();
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(100));
+        assert_eq!(reader.try_read(), Ok(None));
+        assert_eq!(reader.reconnects(), 2);
+        new_writer.write(&5u64).unwrap();
+        assert_eq!(read_u64_at(reader.try_read().unwrap().unwrap(), 0), Some(5));
+        assert_eq!(reader.reader().metadata(), new_writer.metadata());
+    }
+}
@@ -0,0 +1,213 @@
+//! Provides a reader which replays the most recent history of a channel to late subscribers.
+use super::utils::HEARTBEAT;
+use super::{CoalescedIter, Metadata, ShmReader, TryIter};
+use crate::api::{ReadError, Reader};
+use std::collections::VecDeque;
+
+/// A [Reader](../api/trait.Reader.html) which starts reading a channel from a given depth
+/// back in history, rather than from the beginning of the channel. A late subscriber
+/// gets the last records written into the channel followed by the live updates.
+///
+/// In order to find its starting position, the reader scans the channel forward from
+/// the position of the decorated reader up to the current end of the channel, keeping track only of
+/// the offsets it may need, so its memory usage is bounded by the required history depth.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// //the last 10 records will be read first
+/// let mut replay_reader = ReplayReader::with_records(reader, 10);
+/// ```
+#[derive(Debug)]
+pub struct ReplayReader {
+    inner: ShmReader,
+}
+
+impl ReplayReader {
+    /// Creates a reader which will start with the last `depth` records available in the channel.
+    /// If the channel holds fewer records, all of them will be replayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be repositioned
+    /// * `depth` - How many records back from the current end of the channel the reader should start
+    #[inline]
+    pub fn with_records(reader: ShmReader, depth: usize) -> ReplayReader {
+        let mut offsets: VecDeque<u32> = VecDeque::with_capacity(depth + 1);
+        ReplayReader::rewind(reader, |offset, _end| {
+            offsets.push_back(offset);
+            if offsets.len() > depth {
+                offsets.pop_front();
+            }
+            offsets.front().copied()
+        })
+    }
+
+    /// Creates a reader which will start with the oldest records which fit, including their headers and padding,
+    /// into the last `depth` bytes of data available in the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be repositioned
+    /// * `depth` - How many bytes back from the current end of the channel the reader should start
+    #[inline]
+    pub fn with_bytes(reader: ShmReader, depth: u32) -> ReplayReader {
+        let mut offsets: VecDeque<u32> = VecDeque::new();
+        ReplayReader::rewind(reader, |offset, end| {
+            offsets.push_back(offset);
+            while offsets.front().is_some_and(|front| end - *front > depth) {
+                offsets.pop_front();
+            }
+            offsets.front().copied()
+        })
+    }
+
+    //scans forward the available records, and moves the reader at the start offset computed by the given function
+    fn rewind(mut reader: ShmReader, mut start_at: impl FnMut(u32, u32) -> Option<u32>) -> ReplayReader {
+        let frontier_start = reader.position();
+        let mut offset = frontier_start;
+        let mut start = None;
+        let mut records = 0u32;
+        let scan_bound = reader.scan_bound();
+        while let Some((next_offset, rec_len)) = reader.next_record_offset(offset) {
+            if rec_len as u64 != HEARTBEAT {
+                start = start_at(offset, next_offset);
+            }
+            offset = next_offset;
+            records += 1;
+            if scan_bound.exceeded(records, offset - frontier_start) {
+                error!(
+                    "Replay scan exceeded its bound after {} records at position {}",
+                    records, offset
+                );
+                reader.record_failure(ReadError::Failed);
+                return ReplayReader { inner: reader };
+            }
+        }
+        let start = start.unwrap_or(offset);
+        info!(
+            "Replay reader will start at position {}, {} bytes before the end of channel",
+            start,
+            offset - start
+        );
+        debug_assert!(start >= frontier_start);
+        reader.seek(start);
+        ReplayReader { inner: reader }
+    }
+
+    ///Returns a reference to the [Metadata](struct.Metadata.html) associated with this channel
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    ///Returns the current read position.
+    #[inline]
+    pub fn position(&self) -> u32 {
+        self.inner.position()
+    }
+
+    /// Provides a *non-blocking* iterator over messages in the channel.
+    #[inline]
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel,
+    /// which ends after it yields a single `Nothing`.
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    ///Returns the decorated reader
+    #[inline]
+    pub fn into_inner(self) -> ShmReader {
+        self.inner
+    }
+}
+
+impl Reader for ReplayReader {
+    #[inline]
+    fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        match self.exhausted() {
+            Some(err) => Err(err),
+            None => self.inner.try_read(),
+        }
+    }
+
+    #[inline]
+    fn exhausted(&self) -> Option<ReadError> {
+        self.inner.exhausted()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, ReadResult};
+    use assert_matches::assert_matches;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn replay_last_records() {
+        let metadata = Metadata::new(100, 1000, 20000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 1..=100u64 {
+            writer.write(&i).unwrap();
+        }
+        let mut replay_reader = ReplayReader::with_records(shm_reader(test_tmp_dir.path(), 1000).unwrap(), 10);
+        for i in 91..=100u64 {
+            assert_eq!(replay_reader.try_read().unwrap().unwrap(), &i.to_le_bytes());
+        }
+        assert_matches!(replay_reader.try_read(), Ok(None));
+        writer.write(&101u64).unwrap();
+        assert_eq!(replay_reader.try_read().unwrap().unwrap(), &101u64.to_le_bytes());
+        std::mem::drop(writer);
+        let mut replay_reader = ReplayReader::with_records(shm_reader(test_tmp_dir.path(), 1000).unwrap(), 1000);
+        assert_eq!(replay_reader.position(), 0);
+        assert_eq!(
+            replay_reader
+                .try_iter()
+                .filter(|res| matches!(res, ReadResult::Record(_)))
+                .count(),
+            101
+        );
+        assert_eq!(replay_reader.exhausted(), Some(ReadError::Closed));
+    }
+
+    #[test]
+    fn replay_last_bytes() {
+        let metadata = Metadata::new(100, 1000, 20000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 1..=100u64 {
+            writer.write(&i).unwrap();
+        }
+        //every record takes 16 bytes
+        let mut replay_reader = ReplayReader::with_bytes(shm_reader(test_tmp_dir.path(), 1000).unwrap(), 50);
+        assert_eq!(replay_reader.try_read().unwrap().unwrap(), &98u64.to_le_bytes());
+        let mut replay_reader = ReplayReader::with_bytes(shm_reader(test_tmp_dir.path(), 1000).unwrap(), 0);
+        assert_matches!(replay_reader.try_read(), Ok(None));
+        let mut replay_reader = ReplayReader::with_records(shm_reader(test_tmp_dir.path(), 1000).unwrap(), 0);
+        assert_matches!(replay_reader.try_read(), Ok(None));
+    }
+}
@@ -0,0 +1,246 @@
+//! Provides a helper which implements the request/reply pattern over a pair of channels.
+use super::{read_u64_at, ShmWriter};
+use crate::api::{Encodable, Handler, ReadError, Reader, WriteError, Writer};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const ID_LEN: usize = std::mem::size_of::<u64>();
+
+/// A requester which sends requests into a channel and matches them with the replies read from
+/// another channel. Every request gets an unique id, which is written in front of the request payload.
+/// A replier is expected to send back the same id in front of its reply, as done by
+/// [write_reply](fn.write_reply.html). Replies for unknown, already matched, or expired requests are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use std::time::Duration;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let req_metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+/// let rep_metadata = Metadata::new(1851, 43, 30_000, 100, FOREVER, Nanos);
+/// let req_writer = shm_writer(&test_tmp_dir.path(), &req_metadata, EncoderHandler::default()).unwrap();
+/// let mut rep_writer = shm_writer(&test_tmp_dir.path(), &rep_metadata, EncoderHandler::default()).unwrap();
+/// let rep_reader = shm_reader(&test_tmp_dir.path(), 43).unwrap();
+/// let mut requester = ReqRep::new(req_writer, rep_reader, Duration::from_secs(5));
+/// let id = requester.send_request(&"ping").unwrap();
+/// //the replier side
+/// let mut req_reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let (req_id, payload) = split_request(req_reader.try_read().unwrap().unwrap()).unwrap();
+/// assert_eq!(payload, b"ping");
+/// write_reply(&mut rep_writer, req_id, &"pong").unwrap();
+/// //back to the requester
+/// requester.poll_replies(|rep_id, reply| assert_eq!((rep_id, reply), (id, &b"pong"[..]))).unwrap();
+/// assert_eq!(requester.pending(), 0);
+/// ```
+pub struct ReqRep<H: Handler, R: Reader> {
+    writer: ShmWriter<H>,
+    reader: R,
+    timeout: Duration,
+    next_id: u64,
+    waiting_for: HashMap<u64, Instant>,
+}
+
+impl<H: Handler, R: Reader> ReqRep<H, R> {
+    /// Creates a requester.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer of the channel where the requests are sent
+    /// * `reader` - The reader of the channel where the replies come from
+    /// * `timeout` - How long to wait for the reply of a request before giving up on it
+    #[inline]
+    pub fn new(writer: ShmWriter<H>, reader: R, timeout: Duration) -> ReqRep<H, R> {
+        ReqRep {
+            writer,
+            reader,
+            timeout,
+            next_id: 0,
+            waiting_for: HashMap::new(),
+        }
+    }
+
+    /// Sends a request and starts waiting for its reply.
+    ///
+    /// Returns the id assigned to the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The request payload
+    ///
+    /// # Errors
+    ///
+    /// If the request cannot be written into the requests channel.
+    pub fn send_request<E: Encodable + ?Sized>(&mut self, payload: &E) -> Result<u64, WriteError> {
+        let id = self.next_id;
+        self.writer.write(&(id, payload))?;
+        self.next_id += 1;
+        self.waiting_for.insert(id, Instant::now());
+        Ok(id)
+    }
+
+    /// Reads all the replies currently available, and invokes the given callback for every reply
+    /// which matches a pending request. Requests which waited longer than the timeout are dropped
+    /// before the replies are read, see [take_expired](#method.take_expired).
+    ///
+    /// Returns the number of replies matched.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_reply` - Invoked with the request id and the reply payload
+    ///
+    /// # Errors
+    ///
+    /// If the replies channel cannot be read. A closed replies channel is also an error,
+    /// as no more replies will ever come.
+    pub fn poll_replies<F: FnMut(u64, &[u8])>(&mut self, mut on_reply: F) -> Result<usize, ReadError> {
+        self.take_expired();
+        let mut matched = 0;
+        while let Some(record) = self.reader.try_read()? {
+            match split_request(record) {
+                Some((id, reply)) if self.waiting_for.remove(&id).is_some() => {
+                    on_reply(id, reply);
+                    matched += 1;
+                }
+                Some((id, _)) => warn!("Reply for unknown request {} ignored", id),
+                None => warn!("Malformed reply of {} bytes ignored", record.len()),
+            }
+        }
+        Ok(matched)
+    }
+
+    /// Stops waiting for the requests which waited longer than the timeout.
+    ///
+    /// Returns the ids of the expired requests.
+    pub fn take_expired(&mut self) -> Vec<u64> {
+        let timeout = self.timeout;
+        let mut expired: Vec<u64> = self
+            .waiting_for
+            .iter()
+            .filter(|(_, sent)| sent.elapsed() > timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        expired.sort_unstable();
+        for id in expired.iter() {
+            self.waiting_for.remove(id);
+        }
+        expired
+    }
+
+    /// Returns the number of requests still waiting for a reply.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.waiting_for.len()
+    }
+
+    /// Returns true if the given request is still waiting for a reply.
+    #[inline]
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.waiting_for.contains_key(&id)
+    }
+
+    /// Consumes the requester, returning the requests writer and the replies reader.
+    #[inline]
+    pub fn into_inner(self) -> (ShmWriter<H>, R) {
+        (self.writer, self.reader)
+    }
+}
+
+/// Splits a request, or a reply, into its id and its payload.
+///
+/// Returns `None` if the record is too short to hold an id.
+///
+/// # Arguments
+///
+/// * `record` - A record read from a requests or replies channel
+#[inline]
+pub fn split_request(record: &[u8]) -> Option<(u64, &[u8])> {
+    read_u64_at(record, 0).map(|id| (id, &record[ID_LEN..]))
+}
+
+/// Writes the reply of a request, so it could be matched by a [ReqRep](struct.ReqRep.html).
+///
+/// Returns the total amount of bytes wrote into the channel.
+///
+/// # Arguments
+///
+/// * `writer` - The writer of the replies channel
+/// * `id` - The id of the request
+/// * `reply` - The reply payload
+///
+/// # Errors
+///
+/// If the reply cannot be written.
+#[inline]
+pub fn write_reply<W: Writer, E: Encodable + ?Sized>(writer: &mut W, id: u64, reply: &E) -> Result<u32, WriteError> {
+    writer.write(&(id, reply))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{Decodable, EncoderHandler, RawBinDataFormat};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn summing_replier() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let req_metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let rep_metadata = Metadata::new(200, 2000, 100_000, 1000, FOREVER, Nanos);
+        let req_writer = shm_writer(test_tmp_dir.path(), &req_metadata, EncoderHandler::default()).unwrap();
+        let mut rep_writer = shm_writer(test_tmp_dir.path(), &rep_metadata, EncoderHandler::default()).unwrap();
+        let mut req_reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let rep_reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        let mut requester = ReqRep::new(req_writer, rep_reader, Duration::from_secs(60));
+        let mut expected = HashMap::new();
+        let mut replies = HashMap::new();
+        for i in 0..50u64 {
+            let id = requester.send_request(&(i, i * 3)).unwrap();
+            expected.insert(id, i * 4);
+            //the replier answers every other round
+            if i % 2 == 1 {
+                while let Some(record) = req_reader.try_read().unwrap() {
+                    let (id, mut payload) = split_request(record).unwrap();
+                    let (first, second) = <(u64, u64)>::decode(&RawBinDataFormat, &mut payload).unwrap();
+                    write_reply(&mut rep_writer, id, &(first + second)).unwrap();
+                }
+            }
+            requester
+                .poll_replies(|id, reply| {
+                    replies.insert(id, read_u64_at(reply, 0).unwrap());
+                })
+                .unwrap();
+        }
+        assert_eq!(requester.pending(), 0);
+        assert_eq!(replies, expected);
+        //a duplicated reply is ignored
+        write_reply(&mut rep_writer, 7, &0u64).unwrap();
+        assert_eq!(requester.poll_replies(|_, _| panic!("Unexpected reply")).unwrap(), 0);
+    }
+
+    #[test]
+    fn expired_requests() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let req_metadata = Metadata::new(100, 1000, 100_000, 1000, FOREVER, Nanos);
+        let rep_metadata = Metadata::new(200, 2000, 100_000, 1000, FOREVER, Nanos);
+        let req_writer = shm_writer(test_tmp_dir.path(), &req_metadata, EncoderHandler::default()).unwrap();
+        let mut rep_writer = shm_writer(test_tmp_dir.path(), &rep_metadata, EncoderHandler::default()).unwrap();
+        let rep_reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        let mut requester = ReqRep::new(req_writer, rep_reader, Duration::from_millis(20));
+        let id = requester.send_request(&"late").unwrap();
+        assert!(requester.is_pending(id));
+        std::thread::sleep(Duration::from_millis(40));
+        write_reply(&mut rep_writer, id, &"too late").unwrap();
+        assert_eq!(requester.poll_replies(|_, _| panic!("Unexpected reply")).unwrap(), 0);
+        assert!(!requester.is_pending(id));
+        assert!(requester.take_expired().is_empty());
+        assert!(split_request(&[1, 2, 3]).is_none());
+    }
+}
@@ -0,0 +1,173 @@
+//! Provides a writer which spreads the records over a set of channels.
+use super::ShmWriter;
+use crate::api::{Encodable, Handler, WriteError, Writer};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Error;
+
+/// A [Writer](../api/trait.Writer.html) which spreads the records over a set of channels, called shards,
+/// so the load could be balanced between many consumers, each of them reading a single shard.
+/// Successive records written using [write](#method.write) go round-robin over the shards, while
+/// [write_sharded](#method.write_sharded) picks the shard by hashing the record key, so all the
+/// records with the same key land on the same shard, in the order they were written.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+///
+/// const FOREVER: u64 = 99_999_999_999;
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let writers = (0..4)
+///     .map(|id| {
+///         let metadata = Metadata::new(1850, 42 + id, 30_000, 100, FOREVER, Nanos);
+///         shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap()
+///     })
+///     .collect();
+/// let mut sharded_writer = ShardedWriter::new(writers);
+/// sharded_writer.write(&"Any shard would do").unwrap();
+/// sharded_writer.write_sharded(&"EURUSD", &"Always on the same shard").unwrap();
+/// ```
+pub struct ShardedWriter<H: Handler> {
+    writers: Vec<ShmWriter<H>>,
+    next: usize,
+}
+
+impl<H: Handler> ShardedWriter<H> {
+    /// Creates a sharded writer over the channels of the given writers. The position of a writer
+    /// in the list is the index of its shard.
+    ///
+    /// # Arguments
+    ///
+    /// * `writers` - The writers of the shards
+    ///
+    /// # Panics
+    ///
+    /// If no writer is given.
+    #[inline]
+    pub fn new(writers: Vec<ShmWriter<H>>) -> ShardedWriter<H> {
+        assert!(!writers.is_empty(), "A sharded writer requires at least one shard");
+        ShardedWriter { writers, next: 0 }
+    }
+
+    /// Writes a record into the shard picked by its key.
+    ///
+    /// Returns the total amount of bytes wrote into the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the record, e.g. an instrument or an user id
+    /// * `data` - The data to be written
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the writer of the picked shard.
+    #[inline]
+    pub fn write_sharded<K: Hash + ?Sized, E: Encodable>(&mut self, key: &K, data: &E) -> Result<u32, WriteError> {
+        let shard = self.shard_of(key);
+        self.writers[shard].write(data)
+    }
+
+    /// Returns the index of the shard which holds the records with the given key. The shard of a key
+    /// depends only on the key and the number of shards.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of a record
+    #[inline]
+    pub fn shard_of<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.writers.len() as u64) as usize
+    }
+
+    /// Returns the number of shards.
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.writers.len()
+    }
+
+    /// Returns a reference to the writer of the given shard, if such a shard exists.
+    #[inline]
+    pub fn writer(&self, shard: usize) -> Option<&ShmWriter<H>> {
+        self.writers.get(shard)
+    }
+
+    /// Consumes the sharded writer, returning the writers of the shards.
+    #[inline]
+    pub fn into_inner(self) -> Vec<ShmWriter<H>> {
+        self.writers
+    }
+}
+
+impl<H: Handler> Writer for ShardedWriter<H> {
+    /// Writes a record into the next shard, going round-robin over all the shards.
+    /// If the write fails the same shard will be tried by the next write.
+    ///
+    /// Returns the total amount of bytes wrote into the channel.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the writer of the shard.
+    #[inline]
+    fn write<E: Encodable>(&mut self, data: &E) -> Result<u32, WriteError> {
+        let written = self.writers[self.next].write(data)?;
+        self.next = (self.next + 1) % self.writers.len();
+        Ok(written)
+    }
+
+    /// Flushes all the shards.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writers.iter_mut().try_for_each(|writer| writer.flush())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Reader};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use std::collections::HashMap;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn spread_over_shards() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let writers = (0..3)
+            .map(|id| {
+                let metadata = Metadata::new(100, 1000 + id, 100_000, 1000, FOREVER, Nanos);
+                shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap()
+            })
+            .collect();
+        let mut sharded_writer = ShardedWriter::new(writers);
+        assert_eq!(sharded_writer.shards(), 3);
+        for i in 0..30u64 {
+            sharded_writer.write(&i).unwrap();
+        }
+        let keys = ["alpha", "beta", "gamma", "delta", "epsilon"];
+        for i in 0..50 {
+            let key = keys[i % keys.len()];
+            sharded_writer.write_sharded(key, &key).unwrap();
+        }
+        let mut key_shards = HashMap::new();
+        for shard in 0..3 {
+            let mut reader = shm_reader(test_tmp_dir.path(), 1000 + shard as u64).unwrap();
+            let mut round_robin = 0;
+            while let Some(record) = reader.try_read().unwrap() {
+                if record.len() == 8 {
+                    round_robin += 1;
+                } else {
+                    let key = std::str::from_utf8(record).unwrap().to_string();
+                    assert_eq!(sharded_writer.shard_of(key.as_str()), shard);
+                    assert_eq!(*key_shards.entry(key).or_insert(shard), shard);
+                }
+            }
+            assert_eq!(round_robin, 10);
+        }
+        assert_eq!(key_shards.len(), keys.len());
+    }
+}
@@ -0,0 +1,281 @@
+//! Provides adapters which read a channel as a byte stream, or write a byte stream into a channel.
+use crate::api::{ReadError, Reader, WriteError, Writer};
+use std::io::{BufRead, Error, ErrorKind, Read, Write};
+
+/// Adapts a channel [Reader](../api/trait.Reader.html) to the `std::io::Read` and `std::io::BufRead` traits, so a
+/// channel could feed any parser which expects a byte stream. The stream is the concatenation of the record bodies,
+/// optionally followed by a delimiter after every record. The current record is kept until it is fully consumed,
+/// then the next one is read from the channel.
+///
+/// Once the channel is closed the stream ends. If no record is available yet, but the channel is still written,
+/// a read fails with `WouldBlock`, and it could be retried later. Any other channel failure is reported as an
+/// I/O error of kind `Other`.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use std::io::Read;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// writer.write(&"Hello ").unwrap();
+/// writer.write(&"world").unwrap();
+/// drop(writer);
+/// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let mut text = String::new();
+/// ChannelReader::new(reader).read_to_string(&mut text).unwrap();
+/// assert_eq!(text, "Hello world");
+/// ```
+#[derive(Debug)]
+pub struct ChannelReader<R: Reader> {
+    inner: R,
+    record: Vec<u8>,
+    pos: usize,
+    delimiter: Option<u8>,
+}
+
+impl<R: Reader> ChannelReader<R> {
+    /// Creates a stream over the records read by the given reader.
+    #[inline]
+    pub fn new(inner: R) -> ChannelReader<R> {
+        ChannelReader {
+            inner,
+            record: Vec::new(),
+            pos: 0,
+            delimiter: None,
+        }
+    }
+
+    /// Sets a delimiter, which is inserted into the stream after every record, e.g. a new line
+    /// to read a channel of text records line by line.
+    ///
+    /// Returns the stream with the new delimiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `delimiter` - The byte which follows every record
+    #[inline]
+    pub fn with_delimiter(mut self, delimiter: u8) -> ChannelReader<R> {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Returns the underlying reader. The rest of the current record, if any, is lost.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    //Moves to the next record, if the current one was fully consumed. Returns false once the channel is closed.
+    fn next_record(&mut self) -> Result<bool, Error> {
+        if self.pos < self.record.len() {
+            return Ok(true);
+        }
+        match self.inner.try_read() {
+            Ok(Some(record)) => {
+                self.record.clear();
+                self.record.extend_from_slice(record);
+                self.record.extend(self.delimiter);
+                self.pos = 0;
+                Ok(true)
+            }
+            Ok(None) => Err(Error::new(ErrorKind::WouldBlock, "No record available yet")),
+            Err(ReadError::Closed) => Ok(false),
+            Err(err) => Err(Error::other(format!("Channel read failed: {:?}", err))),
+        }
+    }
+}
+
+impl<R: Reader> Read for ChannelReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Reader> BufRead for ChannelReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], Error> {
+        //records are never empty, so an empty buffer means the end of the stream
+        if self.next_record()? {
+            Ok(&self.record[self.pos..])
+        } else {
+            Ok(&[])
+        }
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.record.len());
+    }
+}
+
+/// Adapts a channel [Writer](../api/trait.Writer.html) to the `std::io::Write` trait, so any producer which
+/// writes into a byte stream, e.g. the `write!` macro or a serializer, could write into a channel.
+///
+/// The record boundaries are set by `flush`: the bytes written since the previous flush are buffered, and every
+/// flush writes them into the channel as a single record. This way a record could be assembled by several
+/// `write` calls, as most formatters do. A flush with nothing buffered writes no record, as empty records are
+/// reserved for heartbeats. When the adapter is dropped, the buffered bytes are flushed into a last record.
+///
+/// If the channel is busy, a flush fails with `WouldBlock` and the buffered bytes are kept, so the flush could be
+/// retried. On any other channel failure the flush fails with an I/O error of kind `Other`, and the buffered bytes
+/// are discarded, as they could never be written.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use std::io::Write;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let mut stream = ChannelWriter::new(writer);
+/// write!(stream, "{} + {} = {}", 1, 1, 2).unwrap();
+/// stream.flush().unwrap();
+/// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// assert_eq!(reader.try_read().unwrap().unwrap(), b"1 + 1 = 2");
+/// ```
+#[derive(Debug)]
+pub struct ChannelWriter<W: Writer> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Writer> ChannelWriter<W> {
+    /// Creates a stream which writes a record into the given writer on every flush.
+    #[inline]
+    pub fn new(inner: W) -> ChannelWriter<W> {
+        ChannelWriter {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the bytes buffered since the last flush, which will be written as the next record.
+    #[inline]
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    /// Returns the underlying writer. Writing directly into it puts the records before any buffered bytes.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    //Writes the buffered bytes as a record, if there are any.
+    fn write_pending(&mut self) -> Result<(), Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        match self.inner.write(&self.pending) {
+            Ok(_) => {
+                self.pending.clear();
+                Ok(())
+            }
+            Err(WriteError::Wait) => Err(Error::new(ErrorKind::WouldBlock, "Channel busy, record not written yet")),
+            Err(err) => {
+                self.pending.clear();
+                Err(Error::other(format!("Channel write failed: {:?}", err)))
+            }
+        }
+    }
+}
+
+impl<W: Writer> Write for ChannelWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    ///Writes the bytes buffered since the previous flush as a record, then flushes the underlying writer.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Writer> Drop for ChannelWriter<W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("Last record of the stream was not written: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata};
+    use std::io::BufReader;
+    use std::str::from_utf8;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn read_lines_from_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let txt = "There are 10 kinds of people: those who know binary and those who don't";
+        for word in txt.split_whitespace() {
+            writer.write(&word).unwrap();
+        }
+        drop(writer);
+        let stream = ChannelReader::new(shm_reader(test_tmp_dir.path(), 1000).unwrap()).with_delimiter(b'\n');
+        let lines: Vec<String> = BufReader::new(stream).lines().map(|line| line.unwrap()).collect();
+        assert_eq!(lines, txt.split_whitespace().collect::<Vec<&str>>());
+        //a channel which is still written waits for more records
+        let metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        writer.write(&"partial").unwrap();
+        let mut stream = ChannelReader::new(shm_reader(test_tmp_dir.path(), 2000).unwrap());
+        let mut first = [0u8; 4];
+        stream.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"part");
+        let mut rest = [0u8; 10];
+        assert_eq!(stream.read(&mut rest).unwrap(), 3);
+        assert_eq!(stream.read(&mut rest).unwrap_err().kind(), ErrorKind::WouldBlock);
+        drop(writer);
+        assert_eq!(stream.read(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn format_records_into_channel() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut stream = ChannelWriter::new(writer);
+        for i in 0..10 {
+            write!(stream, "Record {}", i).unwrap();
+            write!(stream, " of {}", 10).unwrap();
+            stream.flush().unwrap();
+        }
+        //a flush with nothing buffered writes no record
+        stream.flush().unwrap();
+        //the last record is written when the stream is dropped
+        write!(stream, "Last").unwrap();
+        assert_eq!(stream.pending(), b"Last");
+        drop(stream);
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        for i in 0..10 {
+            let record = reader.try_read().unwrap().unwrap();
+            assert_eq!(from_utf8(record).unwrap(), format!("Record {} of 10", i));
+        }
+        assert_eq!(reader.try_read().unwrap().unwrap(), b"Last");
+        assert_eq!(reader.try_read(), Err(ReadError::Closed));
+    }
+}
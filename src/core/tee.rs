@@ -0,0 +1,200 @@
+//! Provides a reader which mirrors the records it reads into another channel.
+use super::{CoalescedIter, ShmWriter, TryIter};
+use crate::api::{Handler, ReadError, Reader, WriteError};
+
+/// Defines how a [Tee](struct.Tee.html) reacts when a record cannot be mirrored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeeMode {
+    /// The reading goes on, but no more records will be mirrored, as the mirror would not be a faithful copy anymore.
+    Lenient,
+    /// The tee will be marked as exhausted, so no more records will be read.
+    Strict,
+}
+
+/// A [Reader](../api/trait.Reader.html) which decorates another reader and mirrors every record it reads
+/// into a second channel, returning the record to the caller unchanged. Records are mirrored
+/// [verbatim](struct.ShmWriter.html#method.write_raw), so the second channel will be a faithful copy of
+/// the stream, e.g. for live capture or auditing.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// # let copy_metadata = Metadata::new(1850, 43, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let copy_writer = shm_writer(&test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+/// let mut tee = Tee::new(reader, copy_writer, TeeMode::Lenient);
+/// for rec in tee.coalesced_iter() {
+///     println!("{:?}", rec);
+/// }
+/// ```
+pub struct Tee<R: Reader, H: Handler> {
+    inner: R,
+    writer: ShmWriter<H>,
+    mode: TeeMode,
+    write_failure: Option<WriteError>,
+    failure: Option<ReadError>,
+}
+
+impl<R: Reader, H: Handler> Tee<R, H> {
+    /// Creates a tee which mirrors the records read by the given reader using the given writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be decorated
+    /// * `writer` - The writer of the channel which will hold the copy of the records
+    /// * `mode` - How to react when a record cannot be mirrored
+    #[inline]
+    pub fn new(reader: R, writer: ShmWriter<H>, mode: TeeMode) -> Tee<R, H> {
+        Tee {
+            inner: reader,
+            writer,
+            mode,
+            write_failure: None,
+            failure: None,
+        }
+    }
+
+    /// Returns the error which stopped the mirroring of records, if any.
+    #[inline]
+    pub fn write_failure(&self) -> Option<&WriteError> {
+        self.write_failure.as_ref()
+    }
+
+    /// Returns a reference to the writer of the mirror channel.
+    #[inline]
+    pub fn writer(&self) -> &ShmWriter<H> {
+        &self.writer
+    }
+
+    /// Provides a *non-blocking* iterator over messages in the channel.
+    #[inline]
+    pub fn try_iter(&mut self) -> TryIter<'_, Self> {
+        TryIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Provides a *non-blocking* iterator over the messages currently available in the channel,
+    /// which ends after it yields a single `Nothing`.
+    #[inline]
+    pub fn coalesced_iter(&mut self) -> CoalescedIter<'_, Self> {
+        CoalescedIter {
+            inner: self,
+            done: false,
+        }
+    }
+
+    /// Consumes the tee, returning the decorated reader and the writer of the mirror channel.
+    #[inline]
+    pub fn into_inner(self) -> (R, ShmWriter<H>) {
+        (self.inner, self.writer)
+    }
+}
+
+impl<R: Reader, H: Handler> Reader for Tee<R, H> {
+    /// Reads a record using the decorated reader, than mirrors it.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the decorated reader. In `Strict` mode, if a record cannot be mirrored
+    /// the tee fails with `Failed`.
+    fn try_read<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        let read_res = self.inner.try_read()?;
+        if let Some(record) = read_res {
+            if self.write_failure.is_none() {
+                if let Err(write_err) = self.writer.write_raw(record) {
+                    match self.mode {
+                        TeeMode::Lenient => warn!("Tee could not mirror record {:?}. Mirroring stopped", write_err),
+                        TeeMode::Strict => {
+                            error!("Tee could not mirror record {:?}", write_err);
+                            self.failure = Some(ReadError::Failed);
+                        }
+                    }
+                    self.write_failure = Some(write_err);
+                    if let Some(failure) = self.failure {
+                        return Err(failure);
+                    }
+                }
+            }
+        }
+        Ok(read_res)
+    }
+
+    /// Checks if the decorated reader was exhausted, or the tee had failed.
+    #[inline]
+    fn exhausted(&self) -> Option<ReadError> {
+        self.inner.exhausted().or(self.failure)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{EncoderHandler, Writer};
+    use crate::core::TickUnit::Nanos;
+    use crate::core::{shm_reader, shm_writer, Metadata, ReadResult};
+    use assert_matches::assert_matches;
+    use tempdir::TempDir;
+    const FOREVER: u64 = 99_999_999_999;
+
+    #[test]
+    fn faithful_copy() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 10000, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        for i in 0..20u64 {
+            writer.write(&(i, "record")).unwrap();
+        }
+        let copy_metadata = Metadata::new(100, 2000, 10000, 1000, FOREVER, Nanos);
+        let copy_writer = shm_writer(test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+        let mut tee = Tee::new(shm_reader(test_tmp_dir.path(), 1000).unwrap(), copy_writer, TeeMode::Strict);
+        let mut records = Vec::new();
+        for res in tee.coalesced_iter() {
+            if let ReadResult::Record(rec) = res {
+                records.push(rec.to_vec());
+            }
+        }
+        assert_eq!(records.len(), 20);
+        assert_eq!(tee.writer().write_offset(), writer.write_offset());
+        let mut copy_reader = shm_reader(test_tmp_dir.path(), 2000).unwrap();
+        for rec in records {
+            assert_eq!(copy_reader.try_read().unwrap().unwrap(), &rec[..]);
+        }
+        assert_matches!(copy_reader.try_read(), Ok(None));
+    }
+
+    #[test]
+    fn mirror_failure() {
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let metadata = Metadata::new(100, 1000, 32 * 1024, 1000, FOREVER, Nanos);
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let msg = [1u8; 200];
+        for _ in 0..100 {
+            writer.write(&msg).unwrap();
+        }
+        let copy_metadata = Metadata::new(100, 2000, 16 * 1024, 1000, FOREVER, Nanos);
+        let copy_writer = shm_writer(test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+        let mut tee = Tee::new(shm_reader(test_tmp_dir.path(), 1000).unwrap(), copy_writer, TeeMode::Lenient);
+        let read = tee.try_iter().take_while(|res| matches!(res, ReadResult::Record(_))).count();
+        assert_eq!(read, 100);
+        assert_matches!(tee.write_failure(), Some(WriteError::NoSpaceForRecord));
+        assert!(tee.exhausted().is_none());
+        let copy_metadata = Metadata::new(100, 3000, 16 * 1024, 1000, FOREVER, Nanos);
+        let copy_writer = shm_writer(test_tmp_dir.path(), &copy_metadata, EncoderHandler::default()).unwrap();
+        let mut tee = Tee::new(shm_reader(test_tmp_dir.path(), 1000).unwrap(), copy_writer, TeeMode::Strict);
+        let read = tee.try_iter().take_while(|res| matches!(res, ReadResult::Record(_))).count();
+        assert!(read < 100);
+        assert_eq!(tee.exhausted(), Some(ReadError::Failed));
+    }
+}
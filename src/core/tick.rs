@@ -43,7 +43,8 @@ impl TickUnit {
     ///
     /// # Arguments
     ///
-    /// * `id` - The identifier of the tick unit as u8. In the curent kekbit version it must be **0, 3, 6 or 9**
+    /// * `id` - The identifier of the tick unit as u8. In the curent kekbit version it must be **0, 3, 6 or 9**.
+    ///   The legacy id **1**, written for `Secs` by older versions, is still accepted, so their channels remain readable.
     ///
     /// # Panics
     ///
@@ -56,6 +57,10 @@ impl TickUnit {
             6 => TickUnit::Micros,
             3 => TickUnit::Millis,
             0 => TickUnit::Secs,
+            1 => {
+                warn!("Legacy time unit id 1 found, it will be handled as seconds");
+                TickUnit::Secs
+            }
             _ => panic!("Unknown time unit id {}", id),
         }
     }
@@ -83,6 +88,27 @@ impl TickUnit {
             TickUnit::Secs => duration.as_secs(),
         }
     }
+    /// Returns the `Duration` of the given number of tick units. It is the inverse of [convert](#method.convert).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use kekbit::core::TickUnit::*;
+    ///
+    ///assert_eq!(Nanos.duration(1_500_000_000), Duration::new(1, 500_000_000));
+    ///assert_eq!(Millis.duration(1_500), Duration::new(1, 500_000_000));
+    ///assert_eq!(Secs.duration(1), Duration::from_secs(1));
+    /// ```
+    #[inline]
+    pub fn duration(self, ticks: u64) -> Duration {
+        match self {
+            TickUnit::Nanos => Duration::from_nanos(ticks),
+            TickUnit::Micros => Duration::from_micros(ticks),
+            TickUnit::Millis => Duration::from_millis(ticks),
+            TickUnit::Secs => Duration::from_secs(ticks),
+        }
+    }
     ///Returns the difference, measured in the current tick unit, between the current time and midnight, January 1, 1970 UTC.
     ///
     /// # Examples
@@ -110,6 +136,22 @@ mod tests {
         assert_eq!(Secs.id(), TickUnit::from_id(Secs.id()).id());
     }
 
+    #[test]
+    fn check_duration_symetry() {
+        let duration = Duration::new(1_234, 567_891_011);
+        for tick in [Nanos, Micros, Millis, Secs].iter() {
+            let ticks = tick.convert(duration);
+            assert_eq!(tick.convert(tick.duration(ticks)), ticks);
+            assert!(tick.duration(ticks) <= duration);
+        }
+    }
+
+    #[test]
+    fn check_legacy_secs_id() {
+        assert_eq!(TickUnit::from_id(1), Secs);
+        assert_eq!(TickUnit::from_id(1).id(), 0);
+    }
+
     #[test]
     #[should_panic]
     fn check_wrong_id() {
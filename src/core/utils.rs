@@ -1,10 +1,28 @@
+use std::fs::File;
+use std::io::Error;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 pub(crate) const WATERMARK: u64 = 0xFFFF_FFFF_1111_1111;
 pub(crate) const CLOSE: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+pub(crate) const HEARTBEAT: u64 = 0; //a record without payload, skipped by readers
 pub(crate) const U64_SIZE: usize = std::mem::size_of::<u64>(); //8 bytes, size of u64
 pub(crate) const REC_HEADER_LEN: u32 = 8; //8 bytes for len or message type
-pub(crate) const FOOTER_LEN: u32 = 32; //we need 8 bytes for WATERMARK|CLOSE_MARK the other are for future use
+pub(crate) const VERSION_OFFSET: usize = 8; //offset of the storage version in the channel metadata
+pub(crate) const READY: u64 = 1; //set by the writer in the ready flag when the channel is ready to be read
+pub(crate) const READY_OFFSET: usize = 64; //offset of the ready flag in the channel metadata
+pub(crate) const CONTROL_OFFSET: usize = 88; //offset of the control word in the channel metadata
+pub(crate) const CONTROL_GENERATION_OFFSET: usize = 96; //offset of the control word generation, odd while the word is updated
+pub(crate) const COMPRESSED: u64 = 1 << 32; //record flag, set in the high bits of the record length
+pub(crate) const CONTINUED: u64 = 1 << 33; //record flag, set for every chunk of a chunked record except the last one
+pub(crate) const BATCHED: u64 = 1 << 34; //record flag, set for every record of an atomic batch except the last one
+const REC_FLAGS: u64 = COMPRESSED | CONTINUED | BATCHED;
+pub(crate) const FOOTER_LEN: u32 = 64; //8 bytes for WATERMARK|CLOSE_MARK, followed by the footer fields
+pub(crate) const FOOTER_COMMITTED_OFFSET: usize = 8; //offset in the footer of the length of the channel space used by the writer
+pub(crate) const FOOTER_LAST_WRITE_OFFSET: usize = 16; //offset in the footer of the time of the last write
+pub(crate) const FOOTER_DIGEST_GENERATION_OFFSET: usize = 24; //offset in the footer of the digest generation, 0 if there is no digest
+pub(crate) const FOOTER_DIGEST_OFFSET: usize = 32; //offset in the footer of the running digest of the records
+pub(crate) const DIGEST_LEN: usize = 32; //length of the BLAKE3 digest of the records
 
 const REC_ALIGNMENT: u32 = U64_SIZE as u32; //8 bytes, size of u64
 
@@ -13,11 +31,28 @@ pub(crate) const fn align(value: u32) -> u32 {
     (value + (REC_ALIGNMENT - 1)) & !(REC_ALIGNMENT - 1)
 }
 
+//Strips the record flags from a record header, markers are left unchanged.
+#[inline]
+pub(crate) const fn strip_flags(header: u64) -> u64 {
+    let flags = header >> 32;
+    if flags != 0 && flags & !(REC_FLAGS >> 32) == 0 {
+        header & 0xFFFF_FFFF
+    } else {
+        header
+    }
+}
+
 #[inline]
 pub(crate) const fn is_aligned(val: u32) -> bool {
     val & (REC_ALIGNMENT - 1) == 0
 }
 
+//Checks that a memory address could be used for atomic u64 accesses
+#[inline]
+pub(crate) fn is_ptr_aligned(ptr: *const u8) -> bool {
+    (ptr as usize) & (U64_SIZE - 1) == 0
+}
+
 #[inline]
 pub(crate) fn store_atomic_u64(pos_ptr: *mut u64, value: u64, order: Ordering) {
     let store_pos = unsafe { &*(pos_ptr as *const AtomicU64) };
@@ -29,3 +64,225 @@ pub(crate) fn load_atomic_u64(pos_ptr: *mut u64, order: Ordering) -> u64 {
     let store_pos = unsafe { &*(pos_ptr as *const AtomicU64) };
     store_pos.load(order)
 }
+
+//Publishes a record whose body was already written. The marker which follows the record is stored before the
+//record header, so a reader which acquires the header also sees the record body and the marker which follows it.
+//The words are stored by the given function, so the writer and the loom model of the protocol share this logic.
+#[inline]
+pub(crate) fn publish_record<W: Copy>(store: impl Fn(W, u64, Ordering), rec_word: W, next_word: W, header: u64, marker: u64) {
+    store(next_word, marker, Ordering::Release);
+    store(rec_word, header, Ordering::Release);
+}
+
+//What a reader finds at a position of the channel data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Slot {
+    //a record, with the header holding its length and flags, and the position of the word which follows it
+    Record { header: u64, next: u32 },
+    //a record which is not followed by a marker or by another record, as its writer died while publishing it
+    Unpublished { header: u64, next: u32 },
+    Heartbeat,
+    Watermark,
+    Close,
+    //neither a record nor a known marker
+    Unknown(u64),
+}
+
+//Reads the slot which starts at the given position of the channel data. The words are loaded by the given function,
+//so the reader and the loom model of the protocol share this logic. The writer publishes the marker which follows
+//a record before the record itself, so a record is trusted only if a marker or another record follows it.
+#[inline]
+pub(crate) fn read_slot(
+    load: impl Fn(u32, Ordering) -> u64,
+    offset: u32,
+    rec_header_len: u32,
+    max_msg_len: u32,
+    capacity: u32,
+) -> Slot {
+    let header = load(offset, Ordering::Acquire);
+    if header == HEARTBEAT {
+        return Slot::Heartbeat;
+    }
+    match strip_flags(header) {
+        WATERMARK => Slot::Watermark,
+        CLOSE => Slot::Close,
+        len if len <= max_msg_len as u64 => {
+            let next = offset + align(rec_header_len + len as u32);
+            let published = next <= capacity
+                && match strip_flags(load(next, Ordering::Acquire)) {
+                    WATERMARK | CLOSE => true,
+                    follower => follower <= max_msg_len as u64 && next + align(rec_header_len + follower as u32) <= capacity,
+                };
+            if published {
+                Slot::Record { header, next }
+            } else {
+                Slot::Unpublished { header, next }
+            }
+        }
+        marker => Slot::Unknown(marker),
+    }
+}
+
+///Returns the amount of bytes available to an unprivileged user in the file system which holds the given path,
+///or None if the free space could not be determined.
+#[cfg(unix)]
+pub(crate) fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+///Returns the amount of bytes available to an unprivileged user in the file system which holds the given file,
+///or None if the free space could not be determined.
+#[cfg(unix)]
+pub(crate) fn file_free_space(file: &File) -> Option<u64> {
+    use std::os::unix::io::AsRawFd;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatvfs(file.as_raw_fd(), &mut stats) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+///Checks if an I/O error was caused by the lack of space in the file system
+#[inline]
+pub(crate) fn is_no_space(err: &Error) -> bool {
+    #[cfg(unix)]
+    {
+        err.raw_os_error() == Some(libc::ENOSPC)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+///Takes an exclusive advisory lock on the given file, which is held until the file is closed.
+///Fails with `WouldBlock` if the lock is already held through another file handle.
+#[cfg(unix)]
+pub(crate) fn lock_file(file: &File) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock_file(_file: &File) -> Result<(), Error> {
+    Ok(())
+}
+
+///Locks the given memory region in RAM, so it will never be swapped out.
+#[cfg(unix)]
+pub(crate) fn lock_memory(ptr: *const u8, len: usize) -> Result<(), Error> {
+    if unsafe { libc::mlock(ptr as *const libc::c_void, len) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock_memory(_ptr: *const u8, _len: usize) -> Result<(), Error> {
+    Err(Error::new(
+        std::io::ErrorKind::Other,
+        "Memory locking is not supported on this platform",
+    ))
+}
+
+//Run with: RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::*;
+    use loom::cell::UnsafeCell;
+    use loom::sync::atomic::AtomicU64;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    const REC_SIZE: u32 = 16; //an 8 bytes header followed by an 8 bytes body
+    const RECORDS: usize = 2;
+    const CAPACITY: u32 = REC_SIZE * RECORDS as u32;
+
+    //A channel with room for two records. The header words, the last one being the marker kept in the footer, are
+    //accessed atomically, while the record bodies are plain memory, as they are in a channel.
+    struct TinyChannel {
+        headers: [AtomicU64; RECORDS + 1],
+        bodies: [UnsafeCell<u64>; RECORDS],
+    }
+
+    unsafe impl Sync for TinyChannel {}
+
+    impl TinyChannel {
+        fn new() -> TinyChannel {
+            //a new channel starts with a watermark, followed by zeroed memory
+            TinyChannel {
+                headers: [AtomicU64::new(WATERMARK), AtomicU64::new(0), AtomicU64::new(0)],
+                bodies: [UnsafeCell::new(0), UnsafeCell::new(0)],
+            }
+        }
+
+        fn header(&self, offset: u32) -> &AtomicU64 {
+            assert_eq!(offset % REC_SIZE, 0);
+            &self.headers[(offset / REC_SIZE) as usize]
+        }
+
+        fn write(&self, idx: usize, body: u64) {
+            self.bodies[idx].with_mut(|ptr| unsafe { *ptr = body });
+            let offset = idx as u32 * REC_SIZE;
+            let store = |offset: u32, value: u64, order: Ordering| self.header(offset).store(value, order);
+            publish_record(store, offset, offset + REC_SIZE, U64_SIZE as u64, WATERMARK);
+        }
+
+        fn read_slot(&self, offset: u32) -> Slot {
+            let load = |offset: u32, order: Ordering| self.header(offset).load(order);
+            read_slot(load, offset, REC_HEADER_LEN, U64_SIZE as u32, CAPACITY)
+        }
+    }
+
+    fn body(idx: usize) -> u64 {
+        0x00B0_D1E5 + idx as u64
+    }
+
+    #[test]
+    fn loom_two_writes_single_read() {
+        loom::model(|| {
+            let channel = Arc::new(TinyChannel::new());
+            let writer = channel.clone();
+            let handle = thread::spawn(move || {
+                for idx in 0..RECORDS {
+                    writer.write(idx, body(idx));
+                }
+            });
+            let mut offset = 0;
+            for idx in 0..RECORDS {
+                match channel.read_slot(offset) {
+                    Slot::Record { header, next } => {
+                        assert_eq!(header, U64_SIZE as u64);
+                        //loom fails the model if the body could be read before it was written
+                        channel.bodies[idx].with(|ptr| assert_eq!(unsafe { *ptr }, body(idx)));
+                        offset = next;
+                    }
+                    //nothing more published yet, so the reader must not go past the watermark
+                    Slot::Watermark => break,
+                    slot => panic!("Record {} read as {:?}", idx, slot),
+                }
+            }
+            handle.join().unwrap();
+        });
+    }
+}
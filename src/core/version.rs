@@ -9,8 +9,7 @@ pub(crate) struct Version {
 
 impl Version {
     #[inline]
-    #[allow(dead_code)]
-    fn new(major: u16, minor: u16, patch: u32) -> Self {
+    pub fn new(major: u16, minor: u16, patch: u32) -> Self {
         let mut v_u64 = 0u64;
         v_u64 |= u64::from(major) << 48;
         v_u64 |= u64::from(minor) << 32;
@@ -33,14 +32,21 @@ impl Version {
         (self.version & 0x0000_0000_FFFF_FFFF) as u32
     }
 
+    //A storage could be read only by a kekbit release which knows about its layout, and the layout of a major
+    //version is never compatible with the one of another major version.
     #[inline]
     pub fn is_compatible(self, other: Version) -> bool {
-        self >= other
+        self.major() == other.major() && self >= other
     }
 
+    //Version 2 moved the data region from byte 1024 to the end of the metadata, and added to the metadata the
+    //record header length at 60, the ready flag at 64, the application id at 72, the metadata length at 80,
+    //the small channel flag at 84, the control word and its generation at 88 and 96, and the record layout at 104.
+    //It also added the channel footer, which follows the data region, and the COMPRESSED, CONTINUED and BATCHED
+    //record flags. Storages of version 1 are rejected.
     #[inline]
     pub fn latest() -> Version {
-        Version::new(1, 0, 0)
+        Version::new(2, 0, 0)
     }
 }
 
@@ -84,8 +90,8 @@ mod tests {
     fn check_version_creation() {
         for i in 1..1024 {
             let v = Version::new(i, i, i as u32);
-            assert!(v.major() == i as u16);
-            assert!(v.minor() == i as u16);
+            assert!(v.major() == i);
+            assert!(v.minor() == i);
             assert!(v.patch() == i as u32);
             let v_u64: u64 = v.into();
             assert!(v_u64 == *v);
@@ -93,6 +99,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_compatibility() {
+        let latest = Version::latest();
+        assert!(latest.is_compatible(latest));
+        assert!(latest.is_compatible(Version::new(latest.major(), 0, 0)));
+        assert!(!latest.is_compatible(Version::new(1, 0, 0)));
+        assert!(!latest.is_compatible(Version::new(latest.major(), latest.minor() + 1, 0)));
+        assert!(!latest.is_compatible(Version::new(latest.major() + 1, 0, 0)));
+    }
+
     #[test]
     fn check_conversion() {
         let v1 = Version::new(1, 2, 3);
@@ -1,21 +1,40 @@
-use super::utils::{align, store_atomic_u64, CLOSE, REC_HEADER_LEN, WATERMARK};
-use super::Metadata;
+#[cfg(feature = "digest")]
+use super::checksum::digest_record;
+use super::flusher::FlushInterval;
+use super::utils::{
+    align, is_ptr_aligned, load_atomic_u64, publish_record, store_atomic_u64, BATCHED, CLOSE, CONTINUED,
+    CONTROL_GENERATION_OFFSET, CONTROL_OFFSET, FOOTER_COMMITTED_OFFSET, FOOTER_LAST_WRITE_OFFSET, HEARTBEAT, READY, READY_OFFSET,
+    U64_SIZE, WATERMARK,
+};
+#[cfg(feature = "digest")]
+use super::utils::{strip_flags, FOOTER_DIGEST_GENERATION_OFFSET, FOOTER_DIGEST_OFFSET};
+use super::{BackgroundFlusher, Metadata};
 use crate::api::Handler;
 use crate::api::{ChannelError, Encodable, WriteError, Writer};
-use log::{debug, error, info};
 use memmap::MmapMut;
 use std::cmp::min;
+#[cfg(feature = "digest")]
+use std::convert::TryInto;
+use std::fs::{remove_file, File};
 use std::io::Error;
 use std::io::ErrorKind::WriteZero;
 use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::ptr;
 use std::ptr::copy_nonoverlapping;
 use std::result::Result;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Implementation of the [Writer](trait.Writer.html) which access a persistent channel through
 /// memory mapping,  A `ShmWriter` must be created using the [shm_writer](fn.shm_writer.html) function.
 /// Any `ShmWriter` exclusively holds the channel is bound to, and it is *not thread safe*.
 /// If multiple threads must write into a channel they should be externally synchronized.
+/// On unix, a writer holds an exclusive advisory lock on the channel storage for its whole lifetime,
+/// so no other writer could ever be created for the same channel while it is alive.
 ///
 /// # Examples
 ///
@@ -38,45 +57,248 @@ pub struct ShmWriter<H: Handler> {
     metadata: Metadata,
     data_ptr: *mut u8,
     write_offset: u32,
-    mmap: MmapMut,
+    mmap: Arc<MmapMut>,
+    frontier: Option<Arc<AtomicU32>>,
     write: KekWrite,
     rec_handler: H,
+    storage: Option<File>,
+    last_write: u64,
+    #[cfg(feature = "digest")]
+    digest: Option<Box<blake3::Hasher>>,
+    flush_interval: Option<FlushInterval>,
+    delete_on_close: Option<PathBuf>,
+    #[cfg(unix)]
+    unlink_on_close: Option<String>,
 }
 
 impl<H: Handler> ShmWriter<H> {
     #[allow(clippy::cast_ptr_alignment)]
     pub(super) fn new(mut mmap: MmapMut, rec_handler: H) -> Result<ShmWriter<H>, ChannelError> {
+        if !is_ptr_aligned(mmap.as_ptr()) {
+            return Err(ChannelError::MisalignedBuffer {
+                address: mmap.as_ptr() as usize,
+            });
+        }
         let buf = &mut mmap[..];
         let metadata = Metadata::read(buf)?;
-        let metadata_ptr = buf.as_ptr() as *mut u64;
-        let head_len = metadata.len();
-        let data_ptr = unsafe { metadata_ptr.add(head_len) } as *mut u8;
+        let data_ptr = unsafe { buf.as_mut_ptr().add(metadata.len()) };
         let write = KekWrite::new(data_ptr, metadata.max_msg_len() as usize);
+        let last_write = metadata.tick_unit().nix_time();
         let writer = ShmWriter {
             metadata,
             data_ptr,
             write_offset: 0,
-            mmap,
+            mmap: Arc::new(mmap),
+            frontier: None,
             write,
             rec_handler,
+            storage: None,
+            last_write,
+            #[cfg(feature = "digest")]
+            digest: None,
+            flush_interval: None,
+            delete_on_close: None,
+            #[cfg(unix)]
+            unlink_on_close: None,
         };
         info!(
-            "Kekbit channel writer created. Size is {}MB. Max msg size {}KB",
+            "[channel {} writer {}] Kekbit channel writer created. Size is {}MB. Max msg size {}KB",
+            writer.metadata.channel_id(),
+            writer.metadata.writer_id(),
             writer.metadata.capacity() / 1_000_000,
             writer.metadata.max_msg_len() / 1_000
         );
         //Set The WATERMARK
         store_atomic_u64(writer.data_ptr as *mut u64, WATERMARK, Ordering::Release);
+        writer.update_footer();
         Ok(writer)
     }
 
+    //Keeps the file which backs the memory map, so the advisory lock taken on it is held as long as this writer lives.
+    #[inline]
+    pub(super) fn with_storage(mut self, storage: File) -> ShmWriter<H> {
+        self.storage = Some(storage);
+        self
+    }
+
+    //Maintains the running digest of the records written from now on, which is published in the channel footer.
+    #[cfg(feature = "digest")]
+    #[inline]
+    pub(super) fn with_digest(mut self) -> ShmWriter<H> {
+        self.digest = Some(Box::default());
+        self.update_footer();
+        self
+    }
+
+    //Removes the file which backs the channel once the channel is closed.
+    #[inline]
+    pub(super) fn with_delete_on_close(mut self, kek_file_path: PathBuf) -> ShmWriter<H> {
+        self.delete_on_close = Some(kek_file_path);
+        self
+    }
+
+    //Unlinks the name of the shared memory object which backs the channel once the channel is closed.
+    #[cfg(unix)]
+    #[inline]
+    pub(super) fn with_unlink_on_close(mut self, name: String) -> ShmWriter<H> {
+        self.unlink_on_close = Some(name);
+        self
+    }
+
     #[inline]
     fn write_metadata(&mut self, write_ptr: *mut u64, len: u64, aligned_rec_len: u32) {
+        self.write_metadata_with_marker(write_ptr, len, aligned_rec_len, WATERMARK);
+    }
+
+    #[inline]
+    fn write_metadata_with_marker(&mut self, write_ptr: *mut u64, len: u64, aligned_rec_len: u32, marker: u64) {
+        //we should always have space for the 8 bytes required by WATERMARK as they are acounted in the Footer
+        let next_ptr = unsafe { write_ptr.add(aligned_rec_len as usize) };
+        publish_record(store_atomic_u64, write_ptr, next_ptr, len, marker);
+    }
+
+    //Writes a record followed by the given marker, which is published before the record itself.
+    #[allow(clippy::cast_ptr_alignment)]
+    fn write_with_marker<E: Encodable>(&mut self, data: &E, marker: u64) -> Result<u32, WriteError> {
+        let read_head_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) };
+        let rec_header_len = self.metadata.rec_header_len();
+        let write_ptr = unsafe { read_head_ptr.add(rec_header_len as usize) };
+        let available = self.available();
+        if available <= rec_header_len {
+            return Err(WriteError::ChannelFull);
+        }
+        let len = min(self.metadata.max_msg_len(), available - rec_header_len) as usize;
+        let write_res = self.rec_handler.handle(data, self.write.reset(write_ptr, len));
+        match write_res {
+            Ok(_) => {
+                if self.write.total == 0 {
+                    Err(WriteError::EmptyRecord)
+                } else if !self.write.failed {
+                    let aligned_rec_len = align(self.write.total as u32 + rec_header_len);
+                    self.check_bounds(aligned_rec_len)?;
+                    let header = self.write.total as u64 | self.rec_handler.rec_flags();
+                    self.write_metadata_with_marker(read_head_ptr as *mut u64, header, aligned_rec_len >> 3, marker);
+                    self.advance(aligned_rec_len);
+                    Ok(aligned_rec_len)
+                } else {
+                    Err(WriteError::NoSpaceForRecord)
+                }
+            }
+            //the handler may forward the error raised when the record did not fit
+            Err(_) if self.write.failed => Err(WriteError::NoSpaceForRecord),
+            Err(io_err) => Err(WriteError::EncodingError(io_err)),
+        }
+    }
+
+    //Checks that a record of the given length, written at the current offset, would not go beyond
+    //the channel capacity. It guards the mapped memory against any error in the record length computation.
+    #[inline]
+    fn check_bounds(&self, rec_len: u32) -> Result<(), WriteError> {
+        match self.write_offset.checked_add(rec_len) {
+            Some(end) if end <= self.metadata.capacity() => Ok(()),
+            _ => {
+                error!(
+                    "[channel {} writer {}] Record of {} bytes at offset {} would exceed the channel capacity {}",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    rec_len,
+                    self.write_offset,
+                    self.metadata.capacity()
+                );
+                Err(WriteError::ChannelFull)
+            }
+        }
+    }
+
+    #[inline]
+    fn advance(&mut self, rec_len: u32) {
+        self.last_write = self.metadata.tick_unit().nix_time();
+        #[cfg(feature = "digest")]
+        if let Some(mut hasher) = self.digest.take() {
+            self.digest_records(&mut hasher, rec_len);
+            self.digest = Some(hasher);
+        }
+        self.write_offset += rec_len;
+        debug_assert!(self.write_offset <= self.metadata.capacity());
+        if let Some(frontier) = &self.frontier {
+            frontier.store(self.write_offset, Ordering::Release);
+        }
+        self.update_footer();
+        let (now, write_offset) = (self.last_write, self.write_offset);
+        if let Some(range) = self.flush_interval.as_mut().and_then(|policy| policy.due(now, write_offset)) {
+            self.flush_written(range);
+        }
+    }
+
+    //Flushes the given range of the channel, together with the marker which follows it.
+    fn flush_written(&self, range: Range<u32>) {
+        let data_offset = self.data_ptr as usize - self.mmap.as_ptr() as usize;
+        let start = data_offset + range.start as usize;
+        let end = min(data_offset + range.end as usize + U64_SIZE, self.mmap.len());
+        if let Err(err) = self.mmap.flush_range(start, end - start) {
+            error!(
+                "[channel {} writer {}] Flush of the range {:?} failed: {}",
+                self.metadata.channel_id(),
+                self.metadata.writer_id(),
+                range,
+                err
+            );
+        }
+    }
+
+    //Adds to the digest the records written in the given space, which starts at the current write offset.
+    //Heartbeats are skipped, the walk stops at the marker which follows the last record.
+    #[cfg(feature = "digest")]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn digest_records(&self, hasher: &mut blake3::Hasher, len: u32) {
+        let rec_header_len = self.metadata.rec_header_len();
+        let end = self.write_offset + len;
+        let mut offset = self.write_offset;
+        while offset < end {
+            let rec_ptr = unsafe { self.data_ptr.add(offset as usize) };
+            match strip_flags(load_atomic_u64(rec_ptr as *mut u64, Ordering::Relaxed)) {
+                HEARTBEAT => offset += rec_header_len,
+                rec_len if rec_len <= self.metadata.max_msg_len() as u64 => {
+                    let record = unsafe { std::slice::from_raw_parts(rec_ptr.add(rec_header_len as usize), rec_len as usize) };
+                    digest_record(hasher, record);
+                    offset += align(rec_header_len + rec_len as u32);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    //Publishes the writer progress in the channel footer, which lies right after the channel capacity.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    fn update_footer(&self) {
+        let footer_ptr = unsafe { self.data_ptr.add(self.metadata.capacity() as usize) };
         unsafe {
-            //we should always have space for the 8 bytes required by WATERMARK as they are acounted in the Footer
-            store_atomic_u64(write_ptr.add(aligned_rec_len as usize), WATERMARK, Ordering::Release);
+            #[cfg(feature = "digest")]
+            if let Some(hasher) = &self.digest {
+                let digest = hasher.finalize();
+                let generation_ptr = footer_ptr.add(FOOTER_DIGEST_GENERATION_OFFSET) as *mut u64;
+                //as for the control word, an odd generation tells the readers an update is in progress
+                let generation = load_atomic_u64(generation_ptr, Ordering::Relaxed);
+                store_atomic_u64(generation_ptr, generation + 1, Ordering::Relaxed);
+                fence(Ordering::Release);
+                for (i, word) in digest.as_bytes().chunks_exact(U64_SIZE).enumerate() {
+                    let word_ptr = footer_ptr.add(FOOTER_DIGEST_OFFSET + i * U64_SIZE) as *mut u64;
+                    store_atomic_u64(word_ptr, u64::from_ne_bytes(word.try_into().unwrap()), Ordering::Relaxed);
+                }
+                store_atomic_u64(generation_ptr, generation + 2, Ordering::Release);
+            }
+            store_atomic_u64(
+                footer_ptr.add(FOOTER_LAST_WRITE_OFFSET) as *mut u64,
+                self.last_write,
+                Ordering::Relaxed,
+            );
+            store_atomic_u64(
+                footer_ptr.add(FOOTER_COMMITTED_OFFSET) as *mut u64,
+                self.write_offset as u64,
+                Ordering::Release,
+            );
         }
-        store_atomic_u64(write_ptr, len, Ordering::Release);
     }
 }
 
@@ -96,7 +318,7 @@ impl<H: Handler> Writer for ShmWriter<H> {
     /// # Errors
     ///
     /// Two kinds of [failures](enum.WriteError.html) may occur. One if the encoding operation failed, the other if the channel
-    /// rejected the message for reasons such data is too large or no space is available in the channel.
+    /// rejected the message for reasons such data is too large, is empty or no space is available in the channel.
     ///
     /// # Examples
     ///
@@ -118,29 +340,9 @@ impl<H: Handler> Writer for ShmWriter<H> {
     /// writer.write(&msg_data).unwrap();
     /// ```
     ///
-    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
     fn write<E: Encodable>(&mut self, data: &E) -> Result<u32, WriteError> {
-        let read_head_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) };
-        let write_ptr = unsafe { read_head_ptr.add(REC_HEADER_LEN as usize) };
-        let available = self.available();
-        if available <= REC_HEADER_LEN {
-            return Err(WriteError::ChannelFull);
-        }
-        let len = min(self.metadata.max_msg_len(), available - REC_HEADER_LEN) as usize;
-        let write_res = self.rec_handler.handle(data, self.write.reset(write_ptr, len));
-        match write_res {
-            Ok(_) => {
-                if !self.write.failed {
-                    let aligned_rec_len = align(self.write.total as u32 + REC_HEADER_LEN);
-                    self.write_metadata(read_head_ptr as *mut u64, self.write.total as u64, aligned_rec_len >> 3);
-                    self.write_offset += aligned_rec_len;
-                    Ok(aligned_rec_len)
-                } else {
-                    Err(WriteError::NoSpaceForRecord)
-                }
-            }
-            Err(io_err) => Err(WriteError::EncodingError(io_err)),
-        }
+        self.write_with_marker(data, WATERMARK)
     }
     /// Flushes the channel's outstanding memory map modifications to disk. Calling  this method explicitly
     /// it is not encouraged as flushing does occur automatically and comes with a performance penalty.
@@ -175,28 +377,86 @@ impl<H: Handler> Writer for ShmWriter<H> {
     /// ```
     #[inline]
     fn flush(&mut self) -> Result<(), std::io::Error> {
-        debug!("Flushing the channel");
+        trace!(
+            "[channel {} writer {}] Flushing the channel",
+            self.metadata.channel_id(),
+            self.metadata.writer_id()
+        );
         self.mmap.flush()
     }
 }
 
 impl<H: Handler> Drop for ShmWriter<H> {
     /// Marks this channel as `closed`, flushes the changes to the disk, and removes the memory mapping.
+    /// If the channel was created with [delete_on_close](struct.ChannelBuilder.html#method.delete_on_close)
+    /// the file which backs the channel is removed too, while a channel stored in a
+    /// [named shared memory object](struct.NamedShmStorage.html) has its name unlinked.
     fn drop(&mut self) {
         let write_index = self.write_offset;
-        info!("Closing message queue..");
+        trace!(
+            "[channel {} writer {}] Closing message queue..",
+            self.metadata.channel_id(),
+            self.metadata.writer_id()
+        );
         unsafe {
             #[allow(clippy::cast_ptr_alignment)]
             //we should always have the 8 bytes required by CLOSE as they are acounted in the Footer
             let write_ptr = self.data_ptr.offset(write_index as isize) as *mut u64;
             store_atomic_u64(write_ptr, CLOSE, Ordering::Release);
-            info!("Channel amrked as closed")
+            info!(
+                "[channel {} writer {}] Channel marked as closed",
+                self.metadata.channel_id(),
+                self.metadata.writer_id()
+            )
         }
         self.write_offset = self.mmap.len() as u32;
         if self.mmap.flush().is_ok() {
-            info!("All changes flushed");
+            trace!(
+                "[channel {} writer {}] All changes flushed",
+                self.metadata.channel_id(),
+                self.metadata.writer_id()
+            );
         } else {
-            error!("Flush Failed");
+            error!(
+                "[channel {} writer {}] Flush Failed",
+                self.metadata.channel_id(),
+                self.metadata.writer_id()
+            );
+        }
+        if let Some(kek_file_path) = self.delete_on_close.take() {
+            match remove_file(&kek_file_path) {
+                Ok(_) => info!(
+                    "[channel {} writer {}] Kekbit file {:?} removed",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    kek_file_path
+                ),
+                Err(err) => error!(
+                    "[channel {} writer {}] Could not remove kekbit file {:?}: {}",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    kek_file_path,
+                    err
+                ),
+            }
+        }
+        #[cfg(unix)]
+        if let Some(name) = self.unlink_on_close.take() {
+            match super::named::unlink_shm(&name) {
+                Ok(_) => info!(
+                    "[channel {} writer {}] Kekbit shared memory {} unlinked",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    name
+                ),
+                Err(err) => error!(
+                    "[channel {} writer {}] Could not unlink kekbit shared memory {}: {}",
+                    self.metadata.channel_id(),
+                    self.metadata.writer_id(),
+                    name,
+                    err
+                ),
+            }
         }
     }
 }
@@ -204,8 +464,411 @@ impl<H: Handler> ShmWriter<H> {
     ///Returns the amount of space in this channel still available for write.
     #[inline]
     pub fn available(&self) -> u32 {
-        (self.metadata.capacity() - self.write_offset) & 0xFFFF_FFF8 //rounded down to alignement
+        self.metadata.capacity().saturating_sub(self.write_offset) & 0xFFFF_FFF8
+        //rounded down to alignement
     }
+    /// Writes a heartbeat into the channel. A heartbeat is a record without payload,
+    /// which readers skip. Heartbeats are the only empty records allowed in a channel.
+    ///
+    /// Returns the total amount of bytes wrote into the channel.
+    ///
+    /// # Errors
+    ///
+    /// If there is no space left in the channel.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub fn heartbeat(&mut self) -> Result<u32, WriteError> {
+        let rec_header_len = self.metadata.rec_header_len();
+        if self.available() <= rec_header_len {
+            return Err(WriteError::ChannelFull);
+        }
+        self.check_bounds(rec_header_len)?;
+        let write_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) };
+        self.write_metadata(write_ptr as *mut u64, HEARTBEAT, rec_header_len >> 3);
+        self.advance(rec_header_len);
+        Ok(rec_header_len)
+    }
+
+    /// Writes the given bytes, verbatim, as a single record. The record handler is bypassed, so
+    /// this method is meant to forward records which are already fully formed, e.g. records read from
+    /// another channel, without decorating them again.
+    ///
+    /// Returns the total amount of bytes wrote into the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The record to be written
+    ///
+    /// # Errors
+    ///
+    /// If the record is empty, too large or there is no space left in the channel.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<u32, WriteError> {
+        if bytes.is_empty() {
+            return Err(WriteError::EmptyRecord);
+        }
+        let rec_header_len = self.metadata.rec_header_len();
+        let available = self.available();
+        if available <= rec_header_len {
+            return Err(WriteError::ChannelFull);
+        }
+        if bytes.len() > min(self.metadata.max_msg_len(), available - rec_header_len) as usize {
+            return Err(WriteError::NoSpaceForRecord);
+        }
+        let aligned_rec_len = align(bytes.len() as u32 + rec_header_len);
+        self.check_bounds(aligned_rec_len)?;
+        let read_head_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) };
+        unsafe {
+            copy_nonoverlapping(bytes.as_ptr(), read_head_ptr.add(rec_header_len as usize), bytes.len());
+        }
+        self.write_metadata(read_head_ptr as *mut u64, bytes.len() as u64, aligned_rec_len >> 3);
+        self.advance(aligned_rec_len);
+        Ok(aligned_rec_len)
+    }
+
+    /// Writes a last record, then closes the channel, e.g. to note where the stream continues when a channel
+    /// is rotated. The record is published together with the `CLOSE` marker which follows it, so a reader
+    /// never waits for more records after it. Once the final record is written the remaining space
+    /// of the channel is reserved, so any further write fails with `ChannelFull`.
+    ///
+    /// Returns the total amount of bytes wrote into the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the final record
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [write](#method.write). If the final record cannot be written the channel is not closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write_final(&"Continued in channel 43").unwrap();
+    /// assert_eq!(writer.available(), 0);
+    /// ```
+    pub fn write_final<E: Encodable>(&mut self, data: &E) -> Result<u32, WriteError> {
+        let written = self.write_with_marker(data, CLOSE)?;
+        info!(
+            "[channel {} writer {}] Final record written. Channel marked as closed",
+            self.metadata.channel_id(),
+            self.metadata.writer_id()
+        );
+        //the close marker is kept in place, as the rest of the channel is reserved
+        let remaining = self.metadata.capacity() - self.write_offset;
+        self.advance(remaining);
+        Ok(written)
+    }
+
+    /// Writes a payload which could be larger than the maximum message length of the channel. The payload is split
+    /// into chunks of at most `max_msg_len` bytes, each one written as a record. Every chunk, except the last one,
+    /// is flagged as continued, so [read_reassembled](struct.ShmReader.html#method.read_reassembled) could
+    /// put the payload back together. Like [write_raw](#method.write_raw) the record handler is bypassed.
+    ///
+    /// The write is atomic, either all the chunks are written or none of them. Readers will never see
+    /// only a part of the chunks, as the first chunk is published only after all the others were written.
+    ///
+    /// Returns the total amount of bytes wrote into the channel by all the chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The payload to be written
+    ///
+    /// # Errors
+    ///
+    /// If the payload is empty or there is no space left in the channel for all the chunks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// let metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// let blob = vec![42u8; 1000];
+    /// writer.write_chunked(&blob).unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(reader.read_reassembled().unwrap().unwrap(), &blob[..]);
+    /// ```
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn write_chunked(&mut self, data: &[u8]) -> Result<u32, WriteError> {
+        if data.is_empty() {
+            return Err(WriteError::EmptyRecord);
+        }
+        let rec_header_len = self.metadata.rec_header_len();
+        let available = self.available();
+        if available <= rec_header_len {
+            return Err(WriteError::ChannelFull);
+        }
+        let chunk_len = self.metadata.max_msg_len() as usize;
+        let total: u64 = data
+            .chunks(chunk_len)
+            .map(|chunk| align(chunk.len() as u32 + rec_header_len) as u64)
+            .sum();
+        if total > available as u64 {
+            return Err(WriteError::NoSpaceForRecord);
+        }
+        self.check_bounds(total as u32)?;
+        let last = (data.len() - 1) / chunk_len;
+        let mut offset = self.write_offset as usize;
+        let mut first_header = 0;
+        for (idx, chunk) in data.chunks(chunk_len).enumerate() {
+            let rec_ptr = unsafe { self.data_ptr.add(offset) };
+            unsafe {
+                copy_nonoverlapping(chunk.as_ptr(), rec_ptr.add(rec_header_len as usize), chunk.len());
+            }
+            let header = chunk.len() as u64 | if idx < last { CONTINUED } else { 0 };
+            if idx == 0 {
+                first_header = header;
+            } else {
+                store_atomic_u64(rec_ptr as *mut u64, header, Ordering::Release);
+            }
+            offset += align(chunk.len() as u32 + rec_header_len) as usize;
+        }
+        //we should always have space for the 8 bytes required by WATERMARK as they are acounted in the Footer
+        store_atomic_u64(unsafe { self.data_ptr.add(offset) } as *mut u64, WATERMARK, Ordering::Release);
+        let first_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) } as *mut u64;
+        store_atomic_u64(first_ptr, first_header, Ordering::Release);
+        self.advance(total as u32);
+        Ok(total as u32)
+    }
+
+    /// Writes a batch of records which readers see all at once, or not at all. Every record is encoded by the record
+    /// handler, as by [write](trait.Writer.html#tymethod.write). Every record, except the last one, is flagged as
+    /// part of a batch, so readers could tell where the batch ends using
+    /// [batch_continues](struct.ShmReader.html#method.batch_continues).
+    ///
+    /// The records are published in the following order: first the bodies of all the records, and the headers of all
+    /// the records but the first one, are written in place. Then the marker which follows the last record is stored.
+    /// The header of the first record is stored last, with release semantics. Until that store the reader waits at the
+    /// first record, as it finds there the marker left by the previous write, so it can never go past it into the batch.
+    /// Once a reader acquires the first header, all the other records of the batch are already visible to it.
+    ///
+    /// Returns the total amount of bytes wrote into the channel by all the records.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The records of the batch
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [write](trait.Writer.html#tymethod.write), for any of the records. If a record cannot be
+    /// written, none of the records of the batch are published.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// let metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write_batch(&["debit", "credit"]).unwrap();
+    /// let mut reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(reader.try_read().unwrap().unwrap(), b"debit");
+    /// assert!(reader.batch_continues());
+    /// assert_eq!(reader.try_read().unwrap().unwrap(), b"credit");
+    /// assert!(!reader.batch_continues());
+    /// ```
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn write_batch<E: Encodable>(&mut self, items: &[E]) -> Result<u32, WriteError> {
+        if items.is_empty() {
+            return Err(WriteError::EmptyRecord);
+        }
+        let rec_header_len = self.metadata.rec_header_len();
+        let last = items.len() - 1;
+        let mut offset = self.write_offset;
+        let mut first_header = 0;
+        for (idx, item) in items.iter().enumerate() {
+            let available = self.metadata.capacity().saturating_sub(offset) & 0xFFFF_FFF8;
+            if available <= rec_header_len {
+                return Err(if idx == 0 {
+                    WriteError::ChannelFull
+                } else {
+                    WriteError::NoSpaceForRecord
+                });
+            }
+            let rec_ptr = unsafe { self.data_ptr.add(offset as usize) };
+            let len = min(self.metadata.max_msg_len(), available - rec_header_len) as usize;
+            let write_ptr = unsafe { rec_ptr.add(rec_header_len as usize) };
+            match self.rec_handler.handle(item, self.write.reset(write_ptr, len)) {
+                Ok(_) if self.write.total == 0 => return Err(WriteError::EmptyRecord),
+                Ok(_) if !self.write.failed => (),
+                Ok(_) => return Err(WriteError::NoSpaceForRecord),
+                Err(_) if self.write.failed => return Err(WriteError::NoSpaceForRecord),
+                Err(io_err) => return Err(WriteError::EncodingError(io_err)),
+            }
+            let header = self.write.total as u64 | self.rec_handler.rec_flags() | if idx < last { BATCHED } else { 0 };
+            if idx == 0 {
+                first_header = header;
+            } else {
+                store_atomic_u64(rec_ptr as *mut u64, header, Ordering::Release);
+            }
+            offset += align(self.write.total as u32 + rec_header_len);
+        }
+        let total = offset - self.write_offset;
+        self.check_bounds(total)?;
+        //we should always have space for the 8 bytes required by WATERMARK as they are acounted in the Footer
+        store_atomic_u64(
+            unsafe { self.data_ptr.add(offset as usize) } as *mut u64,
+            WATERMARK,
+            Ordering::Release,
+        );
+        let first_ptr = unsafe { self.data_ptr.add(self.write_offset as usize) } as *mut u64;
+        store_atomic_u64(first_ptr, first_header, Ordering::Release);
+        self.advance(total);
+        Ok(total)
+    }
+
+    /// Declares the channel ready to be read. A writer should call this method after it has fully
+    /// initialized(e.g. set up its handlers or written a schema record), so readers created with
+    /// [try_shm_reader_ready](fn.try_shm_reader_ready.html) do not race ahead of it.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    pub fn set_ready(&mut self) {
+        let ready_ptr = unsafe { self.mmap.as_ptr().add(READY_OFFSET) } as *mut u64;
+        store_atomic_u64(ready_ptr, READY, Ordering::Release);
+        info!(
+            "[channel {} writer {}] Channel marked as ready",
+            self.metadata.channel_id(),
+            self.metadata.writer_id()
+        );
+    }
+
+    /// Publishes a control word, an out-of-band message for the channel readers(e.g. pause, resume or
+    /// schema changed). The control word is kept in the channel metadata, apart from the records, and
+    /// every update increments its generation, so readers polling it with
+    /// [control_word](struct.ShmReader.html#method.control_word) can tell a new message from a repeated one.
+    ///
+    /// Returns the generation of the published control word.
+    ///
+    /// # Arguments
+    ///
+    /// * `control` - The control word, its meaning is up to the application
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// const PAUSE: u64 = 1;
+    /// let metadata = Metadata::new(1850, 42, 3000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+    /// assert_eq!(writer.set_control_word(PAUSE), 1);
+    /// assert_eq!(reader.control_word(), (PAUSE, 1));
+    /// ```
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn set_control_word(&mut self, control: u64) -> u64 {
+        let generation_ptr = unsafe { self.mmap.as_ptr().add(CONTROL_GENERATION_OFFSET) } as *mut u64;
+        let control_ptr = unsafe { self.mmap.as_ptr().add(CONTROL_OFFSET) } as *mut u64;
+        //only the writer changes the generation, an odd one tells the readers an update is in progress
+        let generation = load_atomic_u64(generation_ptr, Ordering::Relaxed);
+        store_atomic_u64(generation_ptr, generation + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        store_atomic_u64(control_ptr, control, Ordering::Relaxed);
+        store_atomic_u64(generation_ptr, generation + 2, Ordering::Release);
+        trace!(
+            "[channel {} writer {}] Control word set to {:#x}",
+            self.metadata.channel_id(),
+            self.metadata.writer_id(),
+            control
+        );
+        (generation + 2) >> 1
+    }
+
+    /// Starts a [BackgroundFlusher](struct.BackgroundFlusher.html) which periodically flushes, from a dedicated thread,
+    /// the records written by this writer. This way the records are persisted without the writer ever
+    /// blocking on a flush operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The time interval between two flush operations
+    ///
+    /// # Errors
+    ///
+    /// If the flusher thread cannot be started.
+    pub fn background_flusher(&mut self, interval: Duration) -> Result<BackgroundFlusher, Error> {
+        let frontier = self.frontier.get_or_insert_with(|| Arc::new(AtomicU32::new(0))).clone();
+        frontier.store(self.write_offset, Ordering::Release);
+        let data_offset = self.data_ptr as usize - self.mmap.as_ptr() as usize;
+        BackgroundFlusher::start(self.mmap.clone(), data_offset, frontier, interval)
+    }
+
+    /// Flushes the records from within the writes, but at most once per time interval, whatever the number of records
+    /// written in between. Only the part of the channel written since the previous flush is persisted. The time is
+    /// the one already read by every write, so the policy adds no clock reads. It bounds the flush rate of
+    /// bursty producers without delegating the flushes to a [background flusher](#method.background_flusher).
+    /// A failed flush is only logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time interval between two flush operations
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    /// use std::time::Duration;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// let metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.flush_interval(Duration::from_millis(5));
+    /// writer.write(&"Flushed soon").unwrap();
+    /// ```
+    #[inline]
+    pub fn flush_interval(&mut self, interval: Duration) {
+        let interval = self.metadata.tick_unit().convert(interval);
+        self.flush_interval = Some(FlushInterval::new(interval, self.last_write, 0));
+    }
+
+    /// Returns how long, measured in the channel tick unit, until the readers could consider the channel
+    /// abandoned, if nothing else is written into it. A writer which has nothing to write should send a
+    /// [heartbeat](#method.heartbeat) before this interval runs out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Millis;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// let metadata = Metadata::new(1850, 42, 30_000, 100, 5_000, Millis);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// if writer.ticks_until_timeout() < 1_000 {
+    ///     writer.heartbeat().unwrap();
+    /// }
+    /// ```
+    #[inline]
+    pub fn ticks_until_timeout(&self) -> u64 {
+        let idle = self.metadata.tick_unit().nix_time().saturating_sub(self.last_write);
+        self.metadata.timeout().saturating_sub(idle)
+    }
+
     ///Returns the amount of data written into this channel.
     #[inline]
     pub fn write_offset(&self) -> u32 {
@@ -217,6 +880,71 @@ impl<H: Handler> ShmWriter<H> {
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
+
+    /// Replaces the handler used to push records into the channel. The records written from now on
+    /// will be decorated by the new handler, the ones already written are left unchanged.
+    ///
+    /// Returns the replaced handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `rec_handler` - The handler used for the next records
+    #[inline]
+    pub fn set_handler(&mut self, rec_handler: H) -> H {
+        std::mem::replace(&mut self.rec_handler, rec_handler)
+    }
+
+    /// Switches this writer to a handler of a different type, e.g. to start adding a sequence number
+    /// to every record after a configuration change. As the [Handler](../api/trait.Handler.html) methods are
+    /// generic, handlers cannot be boxed as trait objects, so the writer type changes together with its handler.
+    /// The channel stays open, and the records already written are left unchanged.
+    ///
+    /// Returns a writer which continues this channel using the new handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `rec_handler` - The handler used for the next records
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kekbit::core::TickUnit::Nanos;
+    /// use kekbit::core::*;
+    /// use kekbit::api::*;
+    ///
+    /// const FOREVER: u64 = 99_999_999_999;
+    /// let metadata = Metadata::new(1850, 42, 30_000, 100, FOREVER, Nanos);
+    /// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+    /// let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+    /// writer.write(&"Plain").unwrap();
+    /// let mut writer = writer.with_handler(ChainedHandler::link(EncoderHandler::default(), SequenceHandler::default()));
+    /// writer.write(&"Sequenced").unwrap();
+    /// ```
+    pub fn with_handler<N: Handler>(self, rec_handler: N) -> ShmWriter<N> {
+        //the channel must not be closed, so the fields are moved out without running the writer's drop
+        let mut this = ManuallyDrop::new(self);
+        unsafe {
+            let writer = ShmWriter {
+                metadata: ptr::read(&this.metadata),
+                data_ptr: this.data_ptr,
+                write_offset: this.write_offset,
+                mmap: ptr::read(&this.mmap),
+                frontier: ptr::read(&this.frontier),
+                write: ptr::read(&this.write),
+                rec_handler,
+                storage: ptr::read(&this.storage),
+                last_write: this.last_write,
+                #[cfg(feature = "digest")]
+                digest: ptr::read(&this.digest),
+                flush_interval: ptr::read(&this.flush_interval),
+                delete_on_close: ptr::read(&this.delete_on_close),
+                #[cfg(unix)]
+                unlink_on_close: ptr::read(&this.unlink_on_close),
+            };
+            ptr::drop_in_place(&mut this.rec_handler);
+            writer
+        }
+    }
 }
 
 struct KekWrite {
@@ -265,7 +993,7 @@ impl Write for KekWrite {
             ));
         }
         unsafe {
-            let crt_ptr = self.write_ptr.add(self.total as usize);
+            let crt_ptr = self.write_ptr.add(self.total);
             copy_nonoverlapping(data.as_ptr(), crt_ptr, data_len);
         }
         self.total += data_len;
@@ -1,3 +1,7 @@
+#[macro_use]
+mod logging;
 pub mod api;
 pub mod core;
 pub mod retry;
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,34 @@
+//! The log macros used by kekbit. They forward to the `log` crate, unless the `no-log` feature is enabled,
+//! which compiles out kekbit's own log statements, without changing the log level of the application.
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "no-log")) {
+            log::error!($($arg)+)
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "no-log")) {
+            log::warn!($($arg)+)
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "no-log")) {
+            log::info!($($arg)+)
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        if cfg!(not(feature = "no-log")) {
+            log::trace!($($arg)+)
+        }
+    };
+}
@@ -1,5 +1,6 @@
 //! Provides components which retry multiple times to read or write on the kebit store without blocking.s
 use crate::api::Encodable;
+use crate::api::ReadError;
 use crate::api::Reader;
 use crate::api::WriteError;
 use crate::api::Writer;
@@ -10,6 +11,10 @@ use parking_lot::Mutex;
 use std::iter::FusedIterator;
 use std::iter::Iterator;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SPIN_ROUNDS: u32 = 8;
+const YIELD_ROUNDS: u32 = 8;
 
 /// A nonblocking iterator over messages in the channel which tries multiple times to read
 /// a message from a channel.
@@ -59,6 +64,254 @@ impl<'a, R: Reader> Iterator for RetryIter<'a, R> {
 
 impl<'a, R: Reader> FusedIterator for RetryIter<'a, R> {}
 
+/// The way an [AdaptivePoller](struct.AdaptivePoller.html) waits for new records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollWait {
+    /// Busy spin
+    Spin,
+    /// Yield the current thread
+    Yield,
+    /// Put the current thread to sleep for the given interval
+    Sleep(Duration),
+}
+
+impl PollWait {
+    /// Waits once, in the way described by this value.
+    #[inline]
+    pub fn wait(&self) {
+        match self {
+            PollWait::Spin => std::hint::spin_loop(),
+            PollWait::Yield => std::thread::yield_now(),
+            PollWait::Sleep(interval) => std::thread::sleep(*interval),
+        }
+    }
+}
+
+/// Describes how many times, for how long, and how to wait between the attempts to perform an operation,
+/// such connecting to a channel using [try_shm_reader_with](../core/fn.try_shm_reader_with.html).
+/// An operation is attempted until it succeeds, the maximum number of attempts was reached, or the total
+/// duration elapsed. Low latency consumers may spin for a short time, while patient ones should sleep.
+///
+/// The default policy sleeps 100 milliseconds between attempts, for up to 11 attempts, the same as
+/// `try_shm_reader(root_path, channel_id, 1000, 10)`.
+///
+/// # Examples
+///
+/// ```
+/// use kekbit::retry::*;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::default()
+///     .duration(Duration::from_millis(5))
+///     .max_attempts(u64::MAX)
+///     .wait(PollWait::Spin);
+/// assert_eq!(policy.get_wait(), PollWait::Spin);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    duration: Duration,
+    max_attempts: u64,
+    wait: PollWait,
+}
+
+impl Default for RetryPolicy {
+    #[inline]
+    fn default() -> RetryPolicy {
+        RetryPolicy::sleeping(1000, 10)
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy which sleeps for about `duration_millis/tries` milliseconds between attempts, and which
+    /// gives up after `tries` failed retries. This is the policy used by [try_shm_reader](../core/fn.try_shm_reader.html).
+    ///
+    /// # Arguments
+    ///
+    /// * `duration_millis` - How long it should try in milliseconds
+    /// * `tries` - How many times it will retry during the given time duration
+    #[inline]
+    pub fn sleeping(duration_millis: u64, tries: u64) -> RetryPolicy {
+        assert!(tries > 0);
+        RetryPolicy {
+            duration: Duration::from_millis(duration_millis),
+            max_attempts: tries + 1,
+            wait: PollWait::Sleep(Duration::from_millis(duration_millis / tries)),
+        }
+    }
+
+    /// Sets for how long the operation will be retried. No more attempts are made once it elapsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The total duration of all the attempts
+    #[inline]
+    pub fn duration(mut self, duration: Duration) -> RetryPolicy {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The maximum number of attempts. Must be greater than zero.
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: u64) -> RetryPolicy {
+        assert!(max_attempts > 0);
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets how to wait between two attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - The way to wait between two attempts
+    #[inline]
+    pub fn wait(mut self, wait: PollWait) -> RetryPolicy {
+        self.wait = wait;
+        self
+    }
+
+    /// Returns for how long the operation will be retried.
+    #[inline]
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the maximum number of attempts, including the first one.
+    #[inline]
+    pub fn get_max_attempts(&self) -> u64 {
+        self.max_attempts
+    }
+
+    /// Returns how the policy waits between two attempts.
+    #[inline]
+    pub fn get_wait(&self) -> PollWait {
+        self.wait
+    }
+
+    /// Attempts the given operation until it succeeds or this policy gives up.
+    ///
+    /// Returns the result of the first successful attempt, or the result of the last attempt.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to be attempted
+    pub fn retry<T, E, F: FnMut() -> Result<T, E>>(&self, mut op: F) -> Result<T, E> {
+        let start = Instant::now();
+        let mut attempts = 1;
+        loop {
+            let res = op();
+            if res.is_ok() || attempts >= self.max_attempts || start.elapsed() >= self.duration {
+                return res;
+            }
+            self.wait.wait();
+            attempts += 1;
+        }
+    }
+}
+
+/// A poller which decorates a reader and adapts the way it waits for records to the channel activity.
+/// While the channel is idle, it first spins, than it yields, than it sleeps with exponentially increasing
+/// intervals, up to a maximum interval. As soon as a record is read, it goes back to spinning. This gives
+/// a low latency while records flow, and a low CPU usage while the channel is idle.
+///
+/// # Examples
+///
+/// ```
+/// # use kekbit::core::TickUnit::Nanos;
+/// use kekbit::core::*;
+/// use kekbit::api::*;
+/// use kekbit::retry::*;
+/// use std::time::Duration;
+/// # const FOREVER: u64 = 99_999_999_999;
+/// # let metadata = Metadata::new(1850, 42, 300_000, 1000, FOREVER, Nanos);
+/// let test_tmp_dir = tempdir::TempDir::new("kektest").unwrap();
+/// # let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+/// let reader = shm_reader(&test_tmp_dir.path(), 42).unwrap();
+/// let mut poller = AdaptivePoller::new(reader, Duration::from_micros(50), Duration::from_millis(200));
+/// match poller.next_or_wait() {
+///     Ok(Some(rec)) => println!("Got record {:?}", rec),
+///     Ok(None) => println!("Nothing yet"),
+///     Err(err) => println!("Channel exhausted {:?}", err),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AdaptivePoller<R: Reader> {
+    inner: R,
+    idle_rounds: u32,
+    min_sleep: Duration,
+    max_sleep: Duration,
+}
+
+impl<R: Reader> AdaptivePoller<R> {
+    /// Creates a poller which decorates the given reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader which will be decorated
+    /// * `min_sleep` - The first sleep interval, used after the poller has done spinning and yielding
+    /// * `max_sleep` - The maximum sleep interval
+    #[inline]
+    pub fn new(reader: R, min_sleep: Duration, max_sleep: Duration) -> AdaptivePoller<R> {
+        assert!(min_sleep <= max_sleep);
+        AdaptivePoller {
+            inner: reader,
+            idle_rounds: 0,
+            min_sleep,
+            max_sleep,
+        }
+    }
+
+    /// Returns the way the poller will wait, the next time it finds no record available.
+    #[inline]
+    pub fn current_wait(&self) -> PollWait {
+        if self.idle_rounds < SPIN_ROUNDS {
+            PollWait::Spin
+        } else if self.idle_rounds < SPIN_ROUNDS + YIELD_ROUNDS {
+            PollWait::Yield
+        } else {
+            let shift = (self.idle_rounds - SPIN_ROUNDS - YIELD_ROUNDS).min(31);
+            PollWait::Sleep(self.min_sleep.saturating_mul(1 << shift).min(self.max_sleep))
+        }
+    }
+
+    /// Reads the next record from the channel. If no record is available, it waits once, according
+    /// to the channel activity, than it tries again.
+    ///
+    /// Returns the next record, or None if still no record is available after the wait.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the decorated reader.
+    pub fn next_or_wait<'a>(&mut self) -> Result<Option<&'a [u8]>, ReadError> {
+        if let Some(record) = self.inner.try_read()? {
+            self.idle_rounds = 0;
+            return Ok(Some(record));
+        }
+        self.current_wait().wait();
+        self.idle_rounds = self.idle_rounds.saturating_add(1);
+        let read_res = self.inner.try_read()?;
+        if read_res.is_some() {
+            self.idle_rounds = 0;
+        }
+        Ok(read_res)
+    }
+
+    /// Returns a mutable reference to the decorated reader, e.g. to read without waiting.
+    #[inline]
+    pub fn reader_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes the poller, returning the decorated reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
 /// Writer which decorates another writer in order to make it available to multiple threads.
 /// This writer is non-blocking but will try multiple times before it give up.
 #[repr(transparent)]
@@ -113,8 +366,8 @@ mod test {
         let test_tmp_dir = TempDir::new("kektest").unwrap();
         let txt = "There are 10 kinds of people";
         let mut msgs = txt.split_whitespace();
-        let mut writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
-        let mut reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let mut reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
         let mut retry_iter: RetryIter<ShmReader> = reader.try_iter().into();
         match_assert!(retry_iter.size_hint(), (0, None));
         match_assert!(retry_iter.next(), Some(ReadResult::Nothing));
@@ -132,11 +385,43 @@ mod test {
         match_assert!(retry_iter.size_hint(), (0, Some(0)));
     }
 
+    #[test]
+    fn adaptive_poll() {
+        let metadata = Metadata::new(100, 1000, 10000, 1000, 1000, TickUnit::Millis);
+        let test_tmp_dir = TempDir::new("kektest").unwrap();
+        let mut writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
+        let min_sleep = Duration::from_micros(10);
+        let max_sleep = Duration::from_micros(100);
+        let mut poller = AdaptivePoller::new(reader, min_sleep, max_sleep);
+        let mut waits = Vec::new();
+        for _ in 0..SPIN_ROUNDS + YIELD_ROUNDS + 6 {
+            waits.push(poller.current_wait());
+            match_assert!(poller.next_or_wait(), Ok(None));
+        }
+        assert_eq!(waits[0], PollWait::Spin);
+        assert_eq!(waits[SPIN_ROUNDS as usize], PollWait::Yield);
+        let sleeps: Vec<Duration> = waits
+            .iter()
+            .filter_map(|w| match w {
+                PollWait::Sleep(interval) => Some(*interval),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sleeps,
+            vec![min_sleep, min_sleep * 2, min_sleep * 4, min_sleep * 8, max_sleep, max_sleep]
+        );
+        writer.write(&"Wake up").unwrap();
+        match_assert!(poller.next_or_wait(), Ok(Some(b"Wake up")));
+        assert_eq!(poller.current_wait(), PollWait::Spin);
+    }
+
     #[test]
     fn retry_write() {
         let metadata = Metadata::new(100, 1000, 10000, 1000, 1000, TickUnit::Millis);
         let test_tmp_dir = TempDir::new("kektest").unwrap();
-        let writer = shm_writer(&test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
+        let writer = shm_writer(test_tmp_dir.path(), &metadata, EncoderHandler::default()).unwrap();
         let arc_mx = Arc::new(Mutex::new(writer));
         let handles: Vec<std::thread::JoinHandle<()>> = (0..5)
             .map(|i| (i, arc_mx.clone()))
@@ -160,7 +445,7 @@ mod test {
             handle.join().unwrap();
         }
         let mut read_results = std::collections::HashMap::<&str, i32>::new();
-        let mut shm_reader = shm_reader(&test_tmp_dir.path(), 1000).unwrap();
+        let mut shm_reader = shm_reader(test_tmp_dir.path(), 1000).unwrap();
         let reader_iter = shm_reader.try_iter();
         for msg in reader_iter {
             match msg {
@@ -0,0 +1,112 @@
+//! Helpers which run writers and readers in other processes, for the tests of the features which depend
+//! on the interaction between processes. A helper runs the test binary again, restricted to the
+//! [spawned_role](fn.spawned_role.html) test, and the environment of the new process selects what it runs.
+use crate::api::{EncoderHandler, ReadError, Reader, Writer};
+use crate::core::TickUnit::Nanos;
+use crate::core::{read_u64_at, shm_writer, try_shm_reader, Metadata};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const FOREVER: u64 = 99_999_999_999;
+const ROLE_VAR: &str = "KEKBIT_TEST_ROLE";
+const ROOT_VAR: &str = "KEKBIT_TEST_ROOT";
+const CHANNEL_VAR: &str = "KEKBIT_TEST_CHANNEL";
+const RECORDS_VAR: &str = "KEKBIT_TEST_RECORDS";
+//how long a spawned writer or reader may run before it is considered stuck
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A writer or a reader running in another process.
+pub(crate) struct Spawned {
+    child: Child,
+}
+
+impl Spawned {
+    /// Waits for the spawned writer or reader to complete, and returns true if it succeeded.
+    /// A process which is still running after the timeout is killed, and it is reported as failed.
+    pub(crate) fn join(mut self) -> bool {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => return status.success(),
+                Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                _ => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+//Runs the test binary again, restricted to the test which plays the given role.
+fn spawn(role: &str, root_path: &Path, channel_id: u64, records: u64) -> Spawned {
+    let child = Command::new(env::current_exe().unwrap())
+        .args(["test_support::spawned_role", "--exact", "--test-threads=1"])
+        .env(ROLE_VAR, role)
+        .env(ROOT_VAR, root_path)
+        .env(CHANNEL_VAR, channel_id.to_string())
+        .env(RECORDS_VAR, records.to_string())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    Spawned { child }
+}
+
+/// Spawns a writer which creates a channel and writes into it the given number of records, every
+/// record holding its sequence number as an u64. The channel is closed once all the records are written.
+pub(crate) fn spawn_writer(root_path: &Path, channel_id: u64, records: u64) -> Spawned {
+    spawn("writer", root_path, channel_id, records)
+}
+
+/// Spawns a reader which waits for a channel written by [spawn_writer](fn.spawn_writer.html), and reads it until
+/// it is closed. The reader succeeds only if it gets the given number of records, in sequence, before the timeout.
+pub(crate) fn spawn_reader(root_path: &Path, channel_id: u64, records: u64) -> Spawned {
+    spawn("reader", root_path, channel_id, records)
+}
+
+fn write_records(root_path: &Path, channel_id: u64, records: u64) -> bool {
+    let metadata = Metadata::new(100, channel_id, (records as u32 + 1) * 16, 1000, FOREVER, Nanos);
+    let mut writer = shm_writer(root_path, &metadata, EncoderHandler::default()).unwrap();
+    (0..records).all(|i| writer.write(&i).is_ok())
+}
+
+fn read_records(root_path: &Path, channel_id: u64, records: u64) -> bool {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut reader = try_shm_reader(root_path, channel_id, 5000, 500).unwrap();
+    let mut expected = 0;
+    loop {
+        match reader.try_read() {
+            Ok(Some(record)) => {
+                if read_u64_at(record, 0) != Some(expected) {
+                    return false;
+                }
+                expected += 1;
+            }
+            Ok(None) if Instant::now() < deadline => std::thread::yield_now(),
+            Ok(None) => return false,
+            Err(ReadError::Closed) => return expected == records,
+            Err(_) => return false,
+        }
+    }
+}
+
+//Plays the role selected by the environment of a spawned process. In a regular test run there is no role to play.
+#[test]
+fn spawned_role() {
+    let role = match env::var(ROLE_VAR) {
+        Ok(role) => role,
+        Err(_) => return,
+    };
+    let root_path = PathBuf::from(env::var(ROOT_VAR).unwrap());
+    let channel_id = env::var(CHANNEL_VAR).unwrap().parse().unwrap();
+    let records = env::var(RECORDS_VAR).unwrap().parse().unwrap();
+    let succeeded = match role.as_str() {
+        "writer" => write_records(&root_path, channel_id, records),
+        "reader" => read_records(&root_path, channel_id, records),
+        _ => false,
+    };
+    assert!(succeeded, "Spawned {} failed", role);
+}